@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+
+use aws_sdk_sqs::types::MessageAttributeValue;
+use aws_smithy_types::Blob;
+use aws_smithy_types::error::operation::BuildError;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use serde_json::Result as SerdeJsonResult;
+
+use crate::{
+    CHECKSUM_ATTRIBUTE_NAME, CODEC_ATTRIBUTE_NAME, KeyContext, S3Pointer, SqsExtendedClient,
+    message_attribute_size,
+};
+
+pub const ATTRIBUTES_ATTRIBUTE_NAME: &str = "ExtendedPayloadAttributes";
+
+//-ATTRIBUTE ENVELOPE-----------------------------------------------------------
+
+// The S3 object of a message whose attributes were offloaded with its body:
+// `{"body":"..","messageAttributes":{"<name>":{"dataType":"..","stringValue":".."}}}`
+// with binary values base64 encoded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AttributeEnvelope {
+    body: String,
+    #[serde(rename = "messageAttributes")]
+    message_attributes: HashMap<String, EnvelopeAttribute>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct EnvelopeAttribute {
+    #[serde(rename = "dataType")]
+    data_type: String,
+    #[serde(
+        rename = "stringValue",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    string_value: Option<String>,
+    #[serde(
+        rename = "binaryValue",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    binary_value: Option<String>,
+}
+
+impl AttributeEnvelope {
+    pub(crate) fn new(
+        body: &str,
+        message_attributes: &HashMap<String, MessageAttributeValue>,
+    ) -> AttributeEnvelope {
+        AttributeEnvelope {
+            body: body.to_string(),
+            message_attributes: message_attributes
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.clone(),
+                        EnvelopeAttribute {
+                            data_type: value.data_type.clone(),
+                            string_value: value.string_value.clone(),
+                            binary_value: value
+                                .binary_value
+                                .as_ref()
+                                .map(|blob| BASE64.encode(blob.as_ref())),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn marshall_json(&self) -> String {
+        // only strings and maps of strings - serializing cannot fail
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub(crate) fn unmarshall_json(input: &str) -> SerdeJsonResult<AttributeEnvelope> {
+        serde_json::from_str(input)
+    }
+
+    // Attributes whose binary value is not valid base64 are dropped.
+    pub(crate) fn into_parts(self) -> (String, HashMap<String, MessageAttributeValue>) {
+        let message_attributes: HashMap<String, MessageAttributeValue> = self
+            .message_attributes
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value.into_message_attribute_value()?)))
+            .collect();
+
+        (self.body, message_attributes)
+    }
+}
+
+impl EnvelopeAttribute {
+    fn into_message_attribute_value(self) -> Option<MessageAttributeValue> {
+        let binary_value: Option<Blob> = match self.binary_value {
+            None => None,
+            Some(encoded) => Some(Blob::new(BASE64.decode(encoded).ok()?)),
+        };
+
+        MessageAttributeValue::builder()
+            .data_type(self.data_type)
+            .set_string_value(self.string_value)
+            .set_binary_value(binary_value)
+            .build()
+            .ok()
+    }
+}
+
+impl SqsExtendedClient {
+    // Takes the largest attributes off an offloaded message until the message
+    // with the S3 pointer as its body fits under the threshold. Attributes the
+    // client reads before downloading the payload always stay on the message.
+    pub(crate) fn take_offloaded_attributes(
+        &self,
+        attributes: &mut HashMap<String, MessageAttributeValue>,
+        threshold: usize,
+        bucket_name: &str,
+        body: &str,
+        key_context: &KeyContext<'_>,
+    ) -> HashMap<String, MessageAttributeValue> {
+        let mut taken: HashMap<String, MessageAttributeValue> = HashMap::new();
+
+        if !self.offload_attributes {
+            return taken;
+        }
+
+        // the pointer is only measured - its key and checksum have the length
+        // of the ones the payload is uploaded with
+        let s3_pointer: S3Pointer = self.new_s3_pointer(bucket_name.to_string(), body, key_context);
+
+        while self.pointer_message_size(&s3_pointer, body.len(), attributes, taken.len())
+            > threshold
+        {
+            let largest: Option<String> = attributes
+                .iter()
+                .filter(|(name, _)| !self.is_client_attribute(name))
+                .max_by_key(|(name, value)| message_attribute_size(name, value))
+                .map(|(name, _)| name.clone());
+
+            let Some(name) = largest else {
+                break;
+            };
+
+            if let Some(value) = attributes.remove(&name) {
+                taken.insert(name, value);
+            }
+        }
+
+        taken
+    }
+
+    fn is_client_attribute(&self, name: &str) -> bool {
+        self.reserved_attributes
+            .iter()
+            .any(|reserved| reserved == name)
+            || name == CHECKSUM_ATTRIBUTE_NAME
+            || name == CODEC_ATTRIBUTE_NAME
+            || name == ATTRIBUTES_ATTRIBUTE_NAME
+    }
+
+    pub(crate) fn attributes_attribute_for(
+        attribute_count: usize,
+    ) -> Result<MessageAttributeValue, BuildError> {
+        MessageAttributeValue::builder()
+            .data_type("Number")
+            .string_value(attribute_count.to_string())
+            .build()
+    }
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use aws_sdk_sqs::operation::send_message_batch::SendMessageBatchOutput;
+    use aws_sdk_sqs::types::{Message, SendMessageBatchRequestEntry};
+    use aws_smithy_http_client::test_util::StaticReplayClient;
+    use aws_smithy_runtime_api::client::orchestrator::HttpRequest;
+
+    use super::*;
+    use crate::tests::{
+        make_replay_event, make_replay_s3_client, make_replay_sqs_client, make_test_s3_client,
+    };
+    use crate::{ExtendedMessage, SqsExtendedClientBuilder};
+
+    fn make_string_attribute(value: &str) -> MessageAttributeValue {
+        MessageAttributeValue::builder()
+            .data_type("String")
+            .string_value(value)
+            .build()
+            .expect("invalid attribute")
+    }
+
+    fn make_test_key_context() -> KeyContext<'static> {
+        KeyContext::new(None, None, None, SystemTime::UNIX_EPOCH)
+    }
+
+    #[test]
+    fn test_attribute_envelope_round_trip() {
+        let attributes: HashMap<String, MessageAttributeValue> = HashMap::from([
+            ("Trace".to_string(), make_string_attribute("abc")),
+            (
+                "Thumbnail".to_string(),
+                MessageAttributeValue::builder()
+                    .data_type("Binary")
+                    .binary_value(Blob::new(b"hello world".to_vec()))
+                    .build()
+                    .expect("invalid attribute"),
+            ),
+        ]);
+
+        let envelope: AttributeEnvelope = AttributeEnvelope::new("BODY", &attributes);
+        let json: String = envelope.marshall_json();
+
+        assert!(json.contains(r#""binaryValue":"aGVsbG8gd29ybGQ=""#));
+        assert!(json.contains(r#""Trace":{"dataType":"String","stringValue":"abc"}"#));
+
+        let (body, restored) = AttributeEnvelope::unmarshall_json(&json)
+            .expect("unmarshall failed")
+            .into_parts();
+
+        assert_eq!("BODY", body);
+        assert_eq!(attributes, restored);
+    }
+
+    #[test]
+    fn test_take_offloaded_attributes() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_attribute_offloading(true)
+                .build();
+
+        let mut attributes: HashMap<String, MessageAttributeValue> = HashMap::from([
+            ("Large".to_string(), make_string_attribute(&"L".repeat(900))),
+            (
+                "Larger".to_string(),
+                make_string_attribute(&"L".repeat(1000)),
+            ),
+            ("Small".to_string(), make_string_attribute("S")),
+            (
+                CHECKSUM_ATTRIBUTE_NAME.to_string(),
+                make_string_attribute(&"C".repeat(800)),
+            ),
+        ]);
+
+        let taken: HashMap<String, MessageAttributeValue> = sqs_extended_client
+            .take_offloaded_attributes(
+                &mut attributes,
+                1200,
+                "bucket-name",
+                "BODY",
+                &make_test_key_context(),
+            );
+
+        let mut taken_names: Vec<&String> = taken.keys().collect();
+        taken_names.sort();
+
+        assert_eq!(vec!["Large", "Larger"], taken_names);
+        assert!(attributes.contains_key("Small"));
+        assert!(attributes.contains_key(CHECKSUM_ATTRIBUTE_NAME));
+    }
+
+    #[test]
+    fn test_take_offloaded_attributes_disabled() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client()).build();
+
+        let mut attributes: HashMap<String, MessageAttributeValue> =
+            HashMap::from([("Large".to_string(), make_string_attribute(&"L".repeat(900)))]);
+
+        assert!(
+            sqs_extended_client
+                .take_offloaded_attributes(
+                    &mut attributes,
+                    1024,
+                    "bucket-name",
+                    "BODY",
+                    &make_test_key_context()
+                )
+                .is_empty()
+        );
+        assert_eq!(1, attributes.len());
+    }
+
+    #[tokio::test]
+    async fn test_send_message_batch_offloads_attributes() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event("")]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![make_replay_event(
+            r#"{"Successful":[{"Id":"1","MessageId":"ID","MD5OfMessageBody":"MD5"}],"Failed":[]}"#,
+        )]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("bucket-name".to_string())
+                .with_message_size_threshold(2048)
+                .with_attribute_offloading(true)
+                .build();
+
+        let entry: SendMessageBatchRequestEntry = SendMessageBatchRequestEntry::builder()
+            .id("1")
+            .message_body("BODY")
+            .message_attributes("Large", make_string_attribute(&"L".repeat(3000)))
+            .message_attributes("Small", make_string_attribute("S"))
+            .build()
+            .expect("invalid entry");
+
+        let _output: SendMessageBatchOutput = sqs_extended_client
+            .send_message_batch(
+                make_replay_sqs_client(&sqs_http_client)
+                    .send_message_batch()
+                    .queue_url("https://sqs.us-west-2.amazonaws.com/123456789012/queue")
+                    .entries(entry),
+            )
+            .await
+            .expect("send_message_batch failed");
+
+        let s3_requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+        let envelope: &str = std::str::from_utf8(s3_requests[0].body().bytes().expect("no body"))
+            .expect("invalid utf-8");
+        assert!(envelope.contains(r#""Large":{"dataType":"String""#));
+
+        let sqs_requests: Vec<&HttpRequest> = sqs_http_client.actual_requests().collect();
+        let request: serde_json::Value =
+            serde_json::from_slice(sqs_requests[0].body().bytes().expect("no body"))
+                .expect("invalid request");
+        let sent_attributes: &serde_json::Value = &request["Entries"][0]["MessageAttributes"];
+
+        assert_eq!("4", sent_attributes["ExtendedPayloadSize"]["StringValue"]);
+        assert_eq!(
+            "1",
+            sent_attributes[ATTRIBUTES_ATTRIBUTE_NAME]["StringValue"]
+        );
+        assert_eq!("S", sent_attributes["Small"]["StringValue"]);
+        assert!(sent_attributes.get("Large").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_receive_strips_attributes_marker() {
+        let attributes: HashMap<String, MessageAttributeValue> =
+            HashMap::from([("Large".to_string(), make_string_attribute("LLL"))]);
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(vec![make_replay_event(
+            &AttributeEnvelope::new("BODY", &attributes).marshall_json(),
+        )]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("bucket-name".to_string())
+                .build();
+
+        let msg: Message = Message::builder()
+            .receipt_handle("HANDLE")
+            .body(r#"["software.amazon.payloadoffloading.PayloadS3Pointer",{"s3BucketName":"bucket-name","s3Key":"KEY"}]"#)
+            .message_attributes(
+                "ExtendedPayloadSize",
+                MessageAttributeValue::builder()
+                    .data_type("Number")
+                    .string_value("4")
+                    .build()
+                    .expect("invalid attribute"),
+            )
+            .message_attributes(
+                ATTRIBUTES_ATTRIBUTE_NAME,
+                SqsExtendedClient::attributes_attribute_for(1).expect("invalid attribute"),
+            )
+            .build();
+
+        let extended_message: ExtendedMessage = sqs_extended_client
+            .resolve_message(msg)
+            .await
+            .expect("resolve failed");
+
+        assert_eq!("BODY", extended_message.payload());
+        assert_eq!(
+            Some("LLL".to_string()),
+            extended_message.message_attributes()["Large"]
+                .string_value
+                .clone()
+        );
+        assert!(
+            !extended_message
+                .message_attributes()
+                .contains_key(ATTRIBUTES_ATTRIBUTE_NAME)
+        );
+    }
+}
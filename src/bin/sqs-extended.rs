@@ -0,0 +1,281 @@
+use std::error::Error;
+use std::io::Read;
+use std::path::PathBuf;
+
+use aws_config::BehaviorVersion;
+use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_sqs::operation::receive_message::builders::ReceiveMessageFluentBuilder;
+use aws_sdk_sqs::operation::send_message::SendMessageOutput;
+use aws_sdk_sqs::types::MessageAttributeValue;
+use clap::{Parser, Subcommand};
+use sqs_extended_client::{
+    ExtendedMessage, JavaPointerCodec, JsonObjectPointerCodec, PointerCodec, S3Pointer,
+    SqsExtendedClient, SqsExtendedClientBuilder,
+};
+
+//-ARGUMENTS--------------------------------------------------------------------
+
+#[derive(Parser)]
+#[command(
+    name = "sqs-extended",
+    about = "Send, receive and inspect messages of the SQS extended client"
+)]
+struct Cli {
+    #[arg(
+        long,
+        global = true,
+        env = "SQS_EXTENDED_BUCKET",
+        help = "Bucket that payloads are offloaded to"
+    )]
+    bucket: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        default_value = "",
+        help = "Prefix of offloaded payload keys"
+    )]
+    object_prefix: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Send a message body read from a file or stdin")]
+    Send {
+        #[arg(long)]
+        queue_url: String,
+        #[arg(long, help = "Read the body from this file instead of stdin")]
+        file: Option<PathBuf>,
+        #[arg(long, help = "Offload the body even when it is under the threshold")]
+        always_s3: bool,
+    },
+    #[command(
+        about = "Receive messages, print their payloads and delete them with their S3 payloads"
+    )]
+    Receive {
+        #[arg(long)]
+        queue_url: String,
+        #[arg(long, default_value_t = 1)]
+        max_messages: i32,
+        #[arg(
+            long,
+            help = "Leave the messages on the queue and their payloads in S3"
+        )]
+        keep: bool,
+    },
+    #[command(about = "Print messages without deleting or hiding them")]
+    Peek {
+        #[arg(long)]
+        queue_url: String,
+        #[arg(long, default_value_t = 1)]
+        max_messages: i32,
+    },
+    #[command(about = "Delete a message, and its payload for extended receipt handles")]
+    Delete {
+        #[arg(long)]
+        queue_url: String,
+        #[arg(long)]
+        receipt_handle: String,
+    },
+    #[command(
+        about = "Decode an S3 pointer JSON or an extended receipt handle",
+        long_about = "Decode an S3 pointer JSON or an extended receipt handle. Only the built-in \
+                      Java and JSON object pointer formats are decoded - pointers written by a \
+                      custom PointerCodec are not understood. --fetch downloads from the bucket \
+                      named in the pointer with the default S3 client, ignoring any custom \
+                      BucketRouter."
+    )]
+    InspectPointer {
+        input: String,
+        #[arg(long, help = "Download and print the payload too")]
+        fetch: bool,
+    },
+}
+
+//-MAIN-------------------------------------------------------------------------
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli: Cli = Cli::parse();
+
+    let config: aws_config::SdkConfig = aws_config::load_defaults(BehaviorVersion::latest()).await;
+    let s3_client: aws_sdk_s3::Client = aws_sdk_s3::Client::new(&config);
+    let sqs_client: aws_sdk_sqs::Client = aws_sdk_sqs::Client::new(&config);
+
+    let mut builder: SqsExtendedClientBuilder =
+        SqsExtendedClientBuilder::new(s3_client.clone()).with_object_prefix(cli.object_prefix);
+
+    if let Some(bucket) = cli.bucket {
+        builder = builder.with_s3_bucket_name(bucket);
+    }
+
+    match cli.command {
+        Command::Send {
+            queue_url,
+            file,
+            always_s3,
+        } => {
+            let body: String = match file {
+                Some(path) => std::fs::read_to_string(path)?,
+                None => {
+                    let mut body: String = String::new();
+                    std::io::stdin().read_to_string(&mut body)?;
+                    body
+                }
+            };
+
+            let output: SendMessageOutput = builder
+                .with_always_through_s3(always_s3)
+                .build()
+                .send_message(
+                    sqs_client
+                        .send_message()
+                        .queue_url(queue_url)
+                        .message_body(body),
+                )
+                .await?;
+
+            println!("{}", output.message_id().unwrap_or_default());
+        }
+        Command::Receive {
+            queue_url,
+            max_messages,
+            keep,
+        } => {
+            let sqs_extended_client: SqsExtendedClient = builder.build();
+
+            let receive_msg: ReceiveMessageFluentBuilder = sqs_client
+                .receive_message()
+                .queue_url(&queue_url)
+                .max_number_of_messages(max_messages);
+
+            for message in sqs_extended_client.receive_message(receive_msg).await? {
+                print_message(&message);
+
+                if !keep {
+                    sqs_extended_client
+                        .delete_extended_message(
+                            sqs_client.delete_message().queue_url(&queue_url),
+                            &message,
+                        )
+                        .await?;
+                }
+            }
+        }
+        Command::Peek {
+            queue_url,
+            max_messages,
+        } => {
+            // a zero visibility timeout leaves the messages visible to consumers
+            let receive_msg: ReceiveMessageFluentBuilder = sqs_client
+                .receive_message()
+                .queue_url(&queue_url)
+                .max_number_of_messages(max_messages)
+                .visibility_timeout(0);
+
+            for message in builder.build().receive_message(receive_msg).await? {
+                print_message(&message);
+            }
+        }
+        Command::Delete {
+            queue_url,
+            receipt_handle,
+        } => {
+            builder
+                .build()
+                .delete_message(
+                    sqs_client
+                        .delete_message()
+                        .queue_url(queue_url)
+                        .receipt_handle(receipt_handle),
+                )
+                .await?;
+        }
+        Command::InspectPointer { input, fetch } => {
+            let s3_pointer: S3Pointer = decode_pointer(&builder.build(), &input)?;
+
+            println!("bucket: {}", s3_pointer.bucket_name());
+            println!("key:    {}", s3_pointer.key());
+
+            if !s3_pointer.class().is_empty() {
+                println!("class:  {}", s3_pointer.class());
+            }
+
+            if fetch {
+                let object: GetObjectOutput = s3_client
+                    .get_object()
+                    .bucket(s3_pointer.bucket_name())
+                    .key(s3_pointer.key())
+                    .send()
+                    .await?;
+
+                let bytes = object.body.collect().await?.into_bytes();
+
+                println!();
+                println!("{}", String::from_utf8_lossy(&bytes));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//-OUTPUT-----------------------------------------------------------------------
+
+// Tries the built-in pointer formats only - the CLI cannot load a custom
+// PointerCodec.
+fn decode_pointer(
+    sqs_extended_client: &SqsExtendedClient,
+    input: &str,
+) -> Result<S3Pointer, Box<dyn Error>> {
+    let input: &str = input.trim();
+
+    let (bucket, key, receipt_handle) =
+        sqs_extended_client.parse_extended_receipt_handle(input.to_string());
+
+    if !bucket.is_empty() && !key.is_empty() {
+        println!("receipt handle: {}", receipt_handle);
+        return Ok(S3Pointer::new(bucket, key, "".to_string()));
+    }
+
+    if JavaPointerCodec.detect(input) {
+        return Ok(JavaPointerCodec.decode(input)?);
+    }
+
+    Ok(JsonObjectPointerCodec.decode(input)?)
+}
+
+fn print_message(message: &ExtendedMessage) {
+    println!(
+        "message id:     {}",
+        message.message_id().unwrap_or_default()
+    );
+    println!("receipt handle: {}", message.extended_receipt_handle());
+
+    if let Some(s3_pointer) = message.s3_pointer() {
+        println!(
+            "payload:        s3://{}/{}",
+            s3_pointer.bucket_name(),
+            s3_pointer.key()
+        );
+    }
+
+    let mut attributes: Vec<(&String, &MessageAttributeValue)> =
+        message.message_attributes().iter().collect();
+    attributes.sort_by_key(|(name, _)| *name);
+
+    for (name, value) in attributes {
+        match (value.string_value(), value.binary_value()) {
+            (Some(string), _) => println!("attribute:      {} = {}", name, string),
+            (None, Some(blob)) => {
+                println!("attribute:      {} = <{} bytes>", name, blob.as_ref().len())
+            }
+            (None, None) => println!("attribute:      {}", name),
+        }
+    }
+
+    println!();
+    println!("{}", message.payload());
+    println!();
+}
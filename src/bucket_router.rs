@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use crate::KeyContext;
+
+//-BUCKET ROUTER----------------------------------------------------------------
+
+// Places payloads in buckets per message, and tells the client which region to
+// reach a pointer's bucket in. Returning None falls back to the builder's bucket
+// and S3 client respectively.
+pub trait BucketRouter: Send + Sync {
+    fn bucket_name(&self, context: &KeyContext) -> Option<String>;
+    fn region(&self, bucket_name: &str) -> Option<String>;
+}
+
+// One bucket per region - payloads go to the bucket in their queue's region.
+#[derive(Debug, Clone, Default)]
+pub struct RegionalBucketRouter {
+    buckets: HashMap<String, String>,
+}
+
+impl RegionalBucketRouter {
+    pub fn new() -> RegionalBucketRouter {
+        RegionalBucketRouter {
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn with_bucket(mut self, region: String, bucket_name: String) -> RegionalBucketRouter {
+        self.buckets.insert(region, bucket_name);
+        self
+    }
+}
+
+impl BucketRouter for RegionalBucketRouter {
+    fn bucket_name(&self, context: &KeyContext) -> Option<String> {
+        self.buckets.get(context.queue_region()?).cloned()
+    }
+
+    fn region(&self, bucket_name: &str) -> Option<String> {
+        self.buckets
+            .iter()
+            .find(|(_, bucket)| bucket.as_str() == bucket_name)
+            .map(|(region, _)| region.clone())
+    }
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    fn make_test_router() -> RegionalBucketRouter {
+        RegionalBucketRouter::new()
+            .with_bucket("eu-west-2".to_string(), "payloads-london".to_string())
+            .with_bucket("us-east-1".to_string(), "payloads-virginia".to_string())
+    }
+
+    #[test]
+    fn test_regional_bucket_router_bucket_name() {
+        let router: RegionalBucketRouter = make_test_router();
+
+        let london: KeyContext = KeyContext::new(
+            Some("https://sqs.eu-west-2.amazonaws.com/123456789012/orders"),
+            None,
+            None,
+            SystemTime::now(),
+        );
+
+        assert_eq!(
+            Some("payloads-london".to_string()),
+            router.bucket_name(&london)
+        );
+
+        let tokyo: KeyContext = KeyContext::new(
+            Some("https://sqs.ap-northeast-1.amazonaws.com/123456789012/orders"),
+            None,
+            None,
+            SystemTime::now(),
+        );
+
+        assert_eq!(None, router.bucket_name(&tokyo));
+
+        let no_queue: KeyContext = KeyContext::new(None, None, None, SystemTime::now());
+
+        assert_eq!(None, router.bucket_name(&no_queue));
+    }
+
+    #[test]
+    fn test_regional_bucket_router_region() {
+        let router: RegionalBucketRouter = make_test_router();
+
+        assert_eq!(
+            Some("us-east-1".to_string()),
+            router.region("payloads-virginia")
+        );
+        assert_eq!(None, router.region("someone-elses-bucket"));
+    }
+}
@@ -0,0 +1,122 @@
+use aws_sdk_s3::operation::put_object::builders::PutObjectFluentBuilder;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use sha2::{Digest, Sha256};
+
+use crate::SqsExtendedClientError;
+
+pub const CHECKSUM_ATTRIBUTE_NAME: &str = "ExtendedPayloadChecksum";
+
+//-PAYLOAD CHECKSUM-------------------------------------------------------------
+
+// Checksums are recorded as `<algorithm>:<base64 digest>`, the digest encoded
+// the same way as S3's checksum headers so S3 verifies the upload too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadChecksum {
+    Crc32c,
+    Sha256,
+}
+
+impl PayloadChecksum {
+    pub fn name(self) -> &'static str {
+        match self {
+            PayloadChecksum::Crc32c => "crc32c",
+            PayloadChecksum::Sha256 => "sha256",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<PayloadChecksum> {
+        match name {
+            "crc32c" => Some(PayloadChecksum::Crc32c),
+            "sha256" => Some(PayloadChecksum::Sha256),
+            _ => None,
+        }
+    }
+
+    fn digest(self, payload: &[u8]) -> String {
+        match self {
+            PayloadChecksum::Crc32c => BASE64.encode(crc32c::crc32c(payload).to_be_bytes()),
+            PayloadChecksum::Sha256 => BASE64.encode(Sha256::digest(payload)),
+        }
+    }
+
+    pub fn compute(self, payload: &[u8]) -> String {
+        format!("{}:{}", self.name(), self.digest(payload))
+    }
+
+    pub(crate) fn apply_to(
+        self,
+        put_object: PutObjectFluentBuilder,
+        checksum: &str,
+    ) -> PutObjectFluentBuilder {
+        let digest: &str = checksum
+            .split_once(':')
+            .map(|(_, digest)| digest)
+            .unwrap_or_default();
+
+        match self {
+            PayloadChecksum::Crc32c => put_object.checksum_crc32_c(digest),
+            PayloadChecksum::Sha256 => put_object.checksum_sha256(digest),
+        }
+    }
+}
+
+// A checksum with an unknown algorithm cannot be verified, so it is reported as
+// a mismatch with an empty `found`.
+pub(crate) fn checksum_mismatch(expected: &str, payload: &[u8]) -> Option<SqsExtendedClientError> {
+    let found: String = expected
+        .split_once(':')
+        .and_then(|(name, _)| PayloadChecksum::from_name(name))
+        .map(|algorithm| algorithm.compute(payload))
+        .unwrap_or_default();
+
+    if found == expected {
+        return None;
+    }
+
+    Some(SqsExtendedClientError::ChecksumMismatch {
+        expected: expected.to_string(),
+        found,
+        context: None,
+    })
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute() {
+        assert_eq!(
+            "crc32c:yZRlqg==",
+            PayloadChecksum::Crc32c.compute(b"hello world")
+        );
+        assert_eq!(
+            "sha256:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=",
+            PayloadChecksum::Sha256.compute(b"hello world")
+        );
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let expected: String = PayloadChecksum::Sha256.compute(b"hello world");
+
+        assert!(checksum_mismatch(&expected, b"hello world").is_none());
+
+        let Some(SqsExtendedClientError::ChecksumMismatch { found, .. }) =
+            checksum_mismatch(&expected, b"hello w0rld")
+        else {
+            panic!("expected a checksum mismatch");
+        };
+        assert_eq!(PayloadChecksum::Sha256.compute(b"hello w0rld"), found);
+
+        let Some(SqsExtendedClientError::ChecksumMismatch { found, .. }) =
+            checksum_mismatch("md5:XrY7u+Ae7tCTyyK7j1rNww==", b"hello world")
+        else {
+            panic!("expected a checksum mismatch");
+        };
+        assert_eq!("", found);
+    }
+}
@@ -0,0 +1,328 @@
+use std::fmt;
+
+#[cfg(any(feature = "msgpack", feature = "cbor", feature = "protobuf"))]
+use base64::Engine;
+#[cfg(any(feature = "msgpack", feature = "cbor", feature = "protobuf"))]
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::ExtendedMessage;
+
+pub const CODEC_ATTRIBUTE_NAME: &str = "ExtendedPayloadCodec";
+
+//-CODEC------------------------------------------------------------------------
+
+// SQS message bodies must be text, so binary codecs base64 encode their output.
+// The encoded body is what gets measured against the message size threshold.
+pub trait Codec<T> {
+    fn name(&self) -> &str;
+    fn encode(&self, value: &T) -> Result<String, CodecError>;
+    fn decode(&self, body: &str) -> Result<T, CodecError>;
+}
+
+pub struct JsonCodec;
+
+impl JsonCodec {
+    pub const NAME: &'static str = "json";
+
+    // `send_json` and `receive_json` only need one direction, so their types
+    // need not implement both Serialize and Deserialize.
+    pub(crate) fn to_body<T: Serialize>(value: &T) -> Result<String, CodecError> {
+        serde_json::to_string(value).map_err(CodecError::Json)
+    }
+
+    pub(crate) fn from_body<T: DeserializeOwned>(body: &str) -> Result<T, CodecError> {
+        serde_json::from_str(body).map_err(CodecError::Json)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for JsonCodec {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn encode(&self, value: &T) -> Result<String, CodecError> {
+        Self::to_body(value)
+    }
+
+    fn decode(&self, body: &str) -> Result<T, CodecError> {
+        Self::from_body(body)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+pub struct MessagePackCodec;
+
+#[cfg(feature = "msgpack")]
+impl MessagePackCodec {
+    pub const NAME: &'static str = "msgpack";
+}
+
+#[cfg(feature = "msgpack")]
+impl<T: Serialize + DeserializeOwned> Codec<T> for MessagePackCodec {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn encode(&self, value: &T) -> Result<String, CodecError> {
+        let bytes: Vec<u8> =
+            rmp_serde::to_vec_named(value).map_err(CodecError::MessagePackEncode)?;
+        Ok(BASE64.encode(bytes))
+    }
+
+    fn decode(&self, body: &str) -> Result<T, CodecError> {
+        let bytes: Vec<u8> = BASE64.decode(body)?;
+        rmp_serde::from_slice(&bytes).map_err(CodecError::MessagePackDecode)
+    }
+}
+
+#[cfg(feature = "cbor")]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl CborCodec {
+    pub const NAME: &'static str = "cbor";
+}
+
+#[cfg(feature = "cbor")]
+impl<T: Serialize + DeserializeOwned> Codec<T> for CborCodec {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn encode(&self, value: &T) -> Result<String, CodecError> {
+        let mut bytes: Vec<u8> = Vec::new();
+        ciborium::into_writer(value, &mut bytes).map_err(CodecError::CborEncode)?;
+        Ok(BASE64.encode(bytes))
+    }
+
+    fn decode(&self, body: &str) -> Result<T, CodecError> {
+        let bytes: Vec<u8> = BASE64.decode(body)?;
+        ciborium::from_reader(bytes.as_slice()).map_err(CodecError::CborDecode)
+    }
+}
+
+#[cfg(feature = "protobuf")]
+pub struct ProtobufCodec;
+
+#[cfg(feature = "protobuf")]
+impl ProtobufCodec {
+    pub const NAME: &'static str = "protobuf";
+}
+
+#[cfg(feature = "protobuf")]
+impl<T: prost::Message + Default> Codec<T> for ProtobufCodec {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn encode(&self, value: &T) -> Result<String, CodecError> {
+        Ok(BASE64.encode(value.encode_to_vec()))
+    }
+
+    fn decode(&self, body: &str) -> Result<T, CodecError> {
+        let bytes: Vec<u8> = BASE64.decode(body)?;
+        T::decode(bytes.as_slice()).map_err(CodecError::ProtobufDecode)
+    }
+}
+
+//-TYPED MESSAGE----------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct TypedMessage<T> {
+    message: ExtendedMessage,
+    value: T,
+}
+
+impl<T> TypedMessage<T> {
+    pub(crate) fn new(message: ExtendedMessage, value: T) -> TypedMessage<T> {
+        TypedMessage { message, value }
+    }
+
+    pub fn message(&self) -> &ExtendedMessage {
+        &self.message
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_value(self) -> T {
+        self.value
+    }
+}
+
+//-ERRORS-----------------------------------------------------------------------
+
+#[derive(Debug)]
+pub enum CodecError {
+    Json(serde_json::Error),
+    #[cfg(any(feature = "msgpack", feature = "cbor", feature = "protobuf"))]
+    Base64(base64::DecodeError),
+    #[cfg(feature = "msgpack")]
+    MessagePackEncode(rmp_serde::encode::Error),
+    #[cfg(feature = "msgpack")]
+    MessagePackDecode(rmp_serde::decode::Error),
+    #[cfg(feature = "cbor")]
+    CborEncode(ciborium::ser::Error<std::io::Error>),
+    #[cfg(feature = "cbor")]
+    CborDecode(ciborium::de::Error<std::io::Error>),
+    #[cfg(feature = "protobuf")]
+    ProtobufDecode(prost::DecodeError),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "JSON codec failed: {}", err),
+            #[cfg(any(feature = "msgpack", feature = "cbor", feature = "protobuf"))]
+            Self::Base64(err) => write!(f, "Base64 decode failed: {}", err),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePackEncode(err) => write!(f, "MessagePack encode failed: {}", err),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePackDecode(err) => write!(f, "MessagePack decode failed: {}", err),
+            #[cfg(feature = "cbor")]
+            Self::CborEncode(err) => write!(f, "CBOR encode failed: {}", err),
+            #[cfg(feature = "cbor")]
+            Self::CborDecode(err) => write!(f, "CBOR decode failed: {}", err),
+            #[cfg(feature = "protobuf")]
+            Self::ProtobufDecode(err) => write!(f, "Protobuf decode failed: {}", err),
+        }
+    }
+}
+
+#[cfg(any(feature = "msgpack", feature = "cbor", feature = "protobuf"))]
+impl From<base64::DecodeError> for CodecError {
+    fn from(err: base64::DecodeError) -> Self {
+        Self::Base64(err)
+    }
+}
+
+impl std::error::Error for CodecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(err) => Some(err),
+            #[cfg(any(feature = "msgpack", feature = "cbor", feature = "protobuf"))]
+            Self::Base64(err) => Some(err),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePackEncode(err) => Some(err),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePackDecode(err) => Some(err),
+            #[cfg(feature = "cbor")]
+            Self::CborEncode(err) => Some(err),
+            #[cfg(feature = "cbor")]
+            Self::CborDecode(err) => Some(err),
+            #[cfg(feature = "protobuf")]
+            Self::ProtobufDecode(err) => Some(err),
+        }
+    }
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::SqsExtendedClientError;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Order {
+        id: u32,
+        item: String,
+    }
+
+    fn make_test_order() -> Order {
+        Order {
+            id: 7,
+            item: "rusty crab 🦀".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_json_codec() {
+        let body: String = JsonCodec.encode(&make_test_order()).expect("encode failed");
+
+        assert_eq!(r#"{"id":7,"item":"rusty crab 🦀"}"#, body);
+
+        let order: Order = JsonCodec.decode(&body).expect("decode failed");
+
+        assert_eq!(make_test_order(), order);
+        assert_eq!("json", Codec::<Order>::name(&JsonCodec));
+    }
+
+    #[test]
+    fn test_json_codec_decode_error() {
+        let result: Result<Order, CodecError> = JsonCodec.decode("not json");
+
+        assert!(matches!(result, Err(CodecError::Json(_))));
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack_codec() {
+        let body: String = MessagePackCodec
+            .encode(&make_test_order())
+            .expect("encode failed");
+        let order: Order = MessagePackCodec.decode(&body).expect("decode failed");
+
+        assert_eq!(make_test_order(), order);
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_codec() {
+        let body: String = CborCodec.encode(&make_test_order()).expect("encode failed");
+        let order: Order = CborCodec.decode(&body).expect("decode failed");
+
+        assert_eq!(make_test_order(), order);
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn test_protobuf_codec() {
+        #[derive(Clone, PartialEq, prost::Message)]
+        struct ProtoOrder {
+            #[prost(uint32, tag = "1")]
+            id: u32,
+            #[prost(string, tag = "2")]
+            item: String,
+        }
+
+        let proto_order: ProtoOrder = ProtoOrder {
+            id: 7,
+            item: "rusty crab 🦀".to_string(),
+        };
+
+        let body: String = ProtobufCodec.encode(&proto_order).expect("encode failed");
+        let decoded: ProtoOrder = ProtobufCodec.decode(&body).expect("decode failed");
+
+        assert_eq!(proto_order, decoded);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_binary_codec_rejects_invalid_base64() {
+        let result: Result<Order, CodecError> = MessagePackCodec.decode("!!not base64!!");
+
+        assert!(matches!(result, Err(CodecError::Base64(_))));
+    }
+
+    #[test]
+    fn test_codec_error_source() {
+        let decode_error: CodecError =
+            <JsonCodec as Codec<Order>>::decode(&JsonCodec, "{").expect_err("decode should fail");
+        let err: SqsExtendedClientError = decode_error.into();
+
+        let codec_error: &dyn Error = err.source().expect("no codec error");
+        assert!(codec_error.is::<CodecError>());
+
+        let serde_error: &dyn Error = codec_error.source().expect("no serde error");
+        assert!(serde_error.is::<serde_json::Error>());
+        assert!(serde_error.source().is_none());
+    }
+}
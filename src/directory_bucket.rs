@@ -0,0 +1,167 @@
+//-DIRECTORY BUCKETS------------------------------------------------------------
+
+// S3 Express One Zone directory buckets are named `<base name>--<zone id>--x-s3`.
+// The S3 client creates and refreshes their sessions itself, but they have no
+// object tags or storage classes, so the client skips those for them.
+pub(crate) fn is_directory_bucket(bucket_name: &str) -> bool {
+    bucket_name
+        .strip_suffix("--x-s3")
+        .and_then(|rest| rest.rsplit_once("--"))
+        .is_some_and(|(base_name, zone_id)| !base_name.is_empty() && !zone_id.is_empty())
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use aws_sdk_s3::types::StorageClass;
+    use aws_smithy_http_client::test_util::StaticReplayClient;
+    use aws_smithy_runtime_api::client::orchestrator::HttpRequest;
+
+    use super::*;
+    use crate::tests::{make_replay_event, make_replay_s3_client};
+    use crate::{
+        KeyContext, PayloadChecksum, PayloadCleanup, S3Pointer, SqsExtendedClient,
+        SqsExtendedClientBuilder, SqsExtendedClientError,
+    };
+
+    static DIRECTORY_BUCKET: &str = "payloads--usw2-az1--x-s3";
+
+    static CREATE_SESSION_RESPONSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<CreateSessionResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <Credentials>
+        <SessionToken>TEST_SESSION_TOKEN</SessionToken>
+        <SecretAccessKey>TEST_SESSION_SECRET_ACCESS_KEY</SecretAccessKey>
+        <AccessKeyId>TEST_SESSION_ACCESS_KEY_ID</AccessKeyId>
+        <Expiration>2100-01-01T00:00:00Z</Expiration>
+    </Credentials>
+</CreateSessionResult>"#;
+
+    async fn offload(
+        sqs_extended_client: &SqsExtendedClient,
+        bucket_name: &str,
+    ) -> Result<S3Pointer, SqsExtendedClientError> {
+        sqs_extended_client
+            .offload_payload(
+                bucket_name.to_string(),
+                "hello world",
+                &KeyContext::new(None, None, None, SystemTime::now()),
+            )
+            .await
+    }
+
+    #[test]
+    fn test_is_directory_bucket() {
+        assert!(is_directory_bucket(DIRECTORY_BUCKET));
+        assert!(!is_directory_bucket("payloads"));
+        assert!(!is_directory_bucket("--x-s3"));
+        assert!(!is_directory_bucket("payloads--x-s3"));
+    }
+
+    #[test]
+    fn test_directory_bucket_default_payload_cleanup() {
+        let http_client: StaticReplayClient = StaticReplayClient::new(Vec::new());
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&http_client))
+                .with_s3_bucket_name(DIRECTORY_BUCKET.to_string())
+                .with_content_addressed_keys(true)
+                .build();
+
+        assert_eq!(
+            PayloadCleanup::RetentionOnly,
+            sqs_extended_client.payload_cleanup
+        );
+    }
+
+    #[tokio::test]
+    async fn test_offload_to_directory_bucket() {
+        let http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(CREATE_SESSION_RESPONSE),
+            make_replay_event(""),
+        ]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&http_client))
+                .with_s3_bucket_name(DIRECTORY_BUCKET.to_string())
+                .with_storage_class(StorageClass::StandardIa)
+                .with_payload_checksum(PayloadChecksum::Crc32c)
+                .build();
+
+        offload(&sqs_extended_client, DIRECTORY_BUCKET)
+            .await
+            .expect("offload failed");
+
+        let requests: Vec<&HttpRequest> = http_client.actual_requests().collect();
+
+        assert_eq!(2, requests.len());
+
+        assert!(requests[0].uri().starts_with(
+            "https://payloads--usw2-az1--x-s3.s3express-usw2-az1.us-west-2.amazonaws.com/?session"
+        ));
+
+        let put_object: &HttpRequest = requests[1];
+
+        assert_eq!("PUT", put_object.method());
+        assert_eq!(
+            Some("TEST_SESSION_TOKEN"),
+            put_object.headers().get("x-amz-s3session-token")
+        );
+        assert_eq!(
+            Some("yZRlqg=="),
+            put_object.headers().get("x-amz-checksum-crc32c")
+        );
+        assert_eq!(None, put_object.headers().get("x-amz-storage-class"));
+        assert_eq!(None, put_object.headers().get("x-amz-tagging"));
+    }
+
+    #[tokio::test]
+    async fn test_offload_to_general_purpose_bucket() {
+        let http_client: StaticReplayClient = StaticReplayClient::new(vec![make_replay_event("")]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&http_client))
+                .with_s3_bucket_name("payloads".to_string())
+                .with_storage_class(StorageClass::StandardIa)
+                .with_payload_cleanup(PayloadCleanup::ReferenceCounted)
+                .build();
+
+        offload(&sqs_extended_client, "payloads")
+            .await
+            .expect("offload failed");
+
+        let requests: Vec<&HttpRequest> = http_client.actual_requests().collect();
+
+        assert_eq!(1, requests.len());
+        assert_eq!(
+            Some("STANDARD_IA"),
+            requests[0].headers().get("x-amz-storage-class")
+        );
+        assert_eq!(
+            Some("ExtendedPayloadReferences=1"),
+            requests[0].headers().get("x-amz-tagging")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_directory_bucket_rejects_reference_counting() {
+        let http_client: StaticReplayClient = StaticReplayClient::new(Vec::new());
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&http_client))
+                .with_s3_bucket_name(DIRECTORY_BUCKET.to_string())
+                .with_payload_cleanup(PayloadCleanup::ReferenceCounted)
+                .build();
+
+        let result: Result<S3Pointer, SqsExtendedClientError> =
+            offload(&sqs_extended_client, DIRECTORY_BUCKET).await;
+
+        assert!(matches!(
+            result,
+            Err(SqsExtendedClientError::DirectoryBucketUnsupported { .. })
+        ));
+        assert_eq!(0, http_client.actual_requests().count());
+    }
+}
@@ -0,0 +1,462 @@
+use std::fmt;
+use std::ops::Deref;
+
+use aws_sdk_sqs::types::Message;
+
+use crate::{ExtendedMessage, S3Pointer, SqsExtendedClientError};
+
+//-ERROR CONTEXT----------------------------------------------------------------
+
+// What a failed call was working on. Each layer fills in what it knows, so a
+// payload download that fails inside `receive_message` names the operation and
+// queue as well as the message, bucket and key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    operation: Option<String>,
+    queue_url: Option<String>,
+    topic_arn: Option<String>,
+    message_id: Option<String>,
+    bucket: Option<String>,
+    key: Option<String>,
+    payload_size: Option<usize>,
+    leaked_payloads: Vec<S3Pointer>,
+    messages_moved: Option<usize>,
+}
+
+impl ErrorContext {
+    pub(crate) fn new(operation: &str) -> ErrorContext {
+        ErrorContext {
+            operation: Some(operation.to_string()),
+            ..ErrorContext::default()
+        }
+    }
+
+    pub(crate) fn for_message(message: &Message) -> ErrorContext {
+        ErrorContext {
+            message_id: message.message_id.clone(),
+            ..ErrorContext::default()
+        }
+    }
+
+    pub(crate) fn for_extended_message(operation: &str, message: &ExtendedMessage) -> ErrorContext {
+        let context: ErrorContext = ErrorContext::new(operation)
+            .with_message_id(message.message_id())
+            .with_payload_size(message.payload_size());
+
+        match message.s3_pointer() {
+            Some(s3_pointer) => context.with_s3_pointer(s3_pointer),
+            None => context,
+        }
+    }
+
+    pub(crate) fn for_s3_pointer(s3_pointer: &S3Pointer) -> ErrorContext {
+        ErrorContext::default().with_s3_pointer(s3_pointer)
+    }
+
+    // The payloads of unsent messages that could not be rolled back.
+    pub(crate) fn for_leaked_payloads(leaked_payloads: Vec<S3Pointer>) -> ErrorContext {
+        ErrorContext {
+            leaked_payloads,
+            ..ErrorContext::default()
+        }
+    }
+
+    // How many messages a redrive moved before it stopped.
+    pub(crate) fn for_messages_moved(messages_moved: usize) -> ErrorContext {
+        ErrorContext {
+            messages_moved: Some(messages_moved),
+            ..ErrorContext::default()
+        }
+    }
+
+    pub(crate) fn with_queue_url(mut self, queue_url: Option<&str>) -> ErrorContext {
+        self.queue_url = queue_url.map(str::to_string);
+        self
+    }
+
+    #[cfg(feature = "sns")]
+    pub(crate) fn with_topic_arn(mut self, topic_arn: Option<&str>) -> ErrorContext {
+        self.topic_arn = topic_arn.map(str::to_string);
+        self
+    }
+
+    pub(crate) fn with_message_id(mut self, message_id: Option<&str>) -> ErrorContext {
+        self.message_id = message_id.map(str::to_string);
+        self
+    }
+
+    pub(crate) fn with_s3_pointer(mut self, s3_pointer: &S3Pointer) -> ErrorContext {
+        self.bucket = Some(s3_pointer.s3_bucket_name.clone());
+        self.key = Some(s3_pointer.s3_key.clone());
+        self
+    }
+
+    pub(crate) fn with_payload_size(mut self, payload_size: Option<usize>) -> ErrorContext {
+        self.payload_size = payload_size;
+        self
+    }
+
+    pub fn operation(&self) -> Option<&str> {
+        self.operation.as_deref()
+    }
+
+    pub fn queue_url(&self) -> Option<&str> {
+        self.queue_url.as_deref()
+    }
+
+    pub fn topic_arn(&self) -> Option<&str> {
+        self.topic_arn.as_deref()
+    }
+
+    pub fn message_id(&self) -> Option<&str> {
+        self.message_id.as_deref()
+    }
+
+    pub fn bucket(&self) -> Option<&str> {
+        self.bucket.as_deref()
+    }
+
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    pub fn payload_size(&self) -> Option<usize> {
+        self.payload_size
+    }
+
+    // Objects uploaded for messages that were never sent, which the client
+    // could not delete again - `reconcile_payloads` removes them later.
+    pub fn leaked_payloads(&self) -> &[S3Pointer] {
+        &self.leaked_payloads
+    }
+
+    // The messages `redrive_messages` moved before the failure - they are on
+    // the target queue and gone from the source queue.
+    pub fn messages_moved(&self) -> Option<usize> {
+        self.messages_moved
+    }
+
+    // The fields set here win - they were added closer to the failure.
+    fn or(self, outer: ErrorContext) -> ErrorContext {
+        ErrorContext {
+            operation: self.operation.or(outer.operation),
+            queue_url: self.queue_url.or(outer.queue_url),
+            topic_arn: self.topic_arn.or(outer.topic_arn),
+            message_id: self.message_id.or(outer.message_id),
+            bucket: self.bucket.or(outer.bucket),
+            key: self.key.or(outer.key),
+            payload_size: self.payload_size.or(outer.payload_size),
+            leaked_payloads: if self.leaked_payloads.is_empty() {
+                outer.leaked_payloads
+            } else {
+                self.leaked_payloads
+            },
+            messages_moved: self.messages_moved.or(outer.messages_moved),
+        }
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let payload_size: Option<String> = self.payload_size.map(|size| size.to_string());
+        let messages_moved: Option<String> = self.messages_moved.map(|moved| moved.to_string());
+        let leaked_payloads: Option<String> = (!self.leaked_payloads.is_empty()).then(|| {
+            self.leaked_payloads
+                .iter()
+                .map(|s3_pointer| format!("{}/{}", s3_pointer.s3_bucket_name, s3_pointer.s3_key))
+                .collect::<Vec<String>>()
+                .join(" ")
+        });
+
+        let fields: Vec<String> = [
+            ("operation", self.operation.as_deref()),
+            ("queue", self.queue_url.as_deref()),
+            ("topic", self.topic_arn.as_deref()),
+            ("message id", self.message_id.as_deref()),
+            ("bucket", self.bucket.as_deref()),
+            ("key", self.key.as_deref()),
+            ("payload size", payload_size.as_deref()),
+            ("leaked payloads", leaked_payloads.as_deref()),
+            ("messages moved", messages_moved.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| format!("{}: {}", name, value)))
+        .collect();
+
+        write!(f, "{}", fields.join(", "))
+    }
+}
+
+//-CONTEXTUAL-------------------------------------------------------------------
+
+// The error a variant of SqsExtendedClientError wraps, with the context it was
+// raised in. It derefs to the wrapped error, so matching on the variant and
+// inspecting the SDK error work as before.
+#[derive(Debug)]
+pub struct Contextual<E> {
+    error: E,
+    context: Option<Box<ErrorContext>>,
+}
+
+impl<E> Contextual<E> {
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    pub fn into_error(self) -> E {
+        self.error
+    }
+
+    pub fn context(&self) -> Option<&ErrorContext> {
+        self.context.as_deref()
+    }
+}
+
+impl<E> From<E> for Contextual<E> {
+    fn from(error: E) -> Self {
+        Contextual {
+            error,
+            context: None,
+        }
+    }
+}
+
+impl<E> Deref for Contextual<E> {
+    type Target = E;
+
+    fn deref(&self) -> &E {
+        &self.error
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for Contextual<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl SqsExtendedClientError {
+    pub(crate) fn with_context(mut self, context: ErrorContext) -> SqsExtendedClientError {
+        let slot: &mut Option<Box<ErrorContext>> = self.context_slot();
+        let inner: ErrorContext = slot.take().map_or_else(ErrorContext::default, |c| *c);
+        *slot = Some(Box::new(inner.or(context)));
+        self
+    }
+
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Self::S3Upload(err) => err.context(),
+            Self::S3Download(err) => err.context(),
+            Self::S3DeleteObject(err) => err.context(),
+            Self::S3DeleteObjects(err) => err.context(),
+            Self::S3ListObjects(err) => err.context(),
+            Self::S3HeadObject(err) => err.context(),
+            Self::S3GetObjectTagging(err) => err.context(),
+            Self::S3PutObjectTagging(err) => err.context(),
+            Self::S3CopyObject(err) => err.context(),
+            Self::S3DownloadToBytes(err) => err.context(),
+            Self::S3DownloadToUtf8(err) => err.context(),
+            Self::S3RetriesExhausted { context, .. } => context.as_deref(),
+            Self::SqsSendMessage(err) => err.context(),
+            Self::SqsSendMessageBatch(err) => err.context(),
+            Self::SqsGetQueueAttributes(err) => err.context(),
+            Self::SqsReceiveMessage(err) => err.context(),
+            Self::SqsDeleteMessage(err) => err.context(),
+            Self::SqsDeleteMessageBatch(err) => err.context(),
+            Self::SqsPurgeQueue(err) => err.context(),
+            Self::SqsChangeMessageVisibility(err) => err.context(),
+            Self::SqsChangeMessageVisibilityBatch(err) => err.context(),
+            Self::SqsBuildMessageAttribute(err) => err.context(),
+            Self::SqsReceiveMessageUnMarshallMessageBody(err) => err.context(),
+            Self::PointerCodec(err) => err.context(),
+            #[cfg(feature = "sns")]
+            Self::SnsPublish(err) => err.context(),
+            #[cfg(feature = "sns")]
+            Self::SnsPublishBatch(err) => err.context(),
+            Self::PayloadCodec(err) => err.context(),
+            #[cfg(feature = "sns")]
+            Self::SnsJsonMessageStructure { context } => context.as_deref(),
+            Self::PayloadCodecMismatch { context, .. } => context.as_deref(),
+            Self::ChecksumMismatch { context, .. } => context.as_deref(),
+            Self::DirectoryBucketUnsupported { context, .. } => context.as_deref(),
+            Self::ReconcileContentAddressedKeys { context } => context.as_deref(),
+            Self::ReconcileWithoutObjectPrefix { context } => context.as_deref(),
+            Self::NoBucketName { context } => context.as_deref(),
+            Self::NoMessageBody { context } => context.as_deref(),
+            Self::NoReceiptHandle { context } => context.as_deref(),
+        }
+    }
+
+    fn context_slot(&mut self) -> &mut Option<Box<ErrorContext>> {
+        match self {
+            Self::S3Upload(err) => &mut err.context,
+            Self::S3Download(err) => &mut err.context,
+            Self::S3DeleteObject(err) => &mut err.context,
+            Self::S3DeleteObjects(err) => &mut err.context,
+            Self::S3ListObjects(err) => &mut err.context,
+            Self::S3HeadObject(err) => &mut err.context,
+            Self::S3GetObjectTagging(err) => &mut err.context,
+            Self::S3PutObjectTagging(err) => &mut err.context,
+            Self::S3CopyObject(err) => &mut err.context,
+            Self::S3DownloadToBytes(err) => &mut err.context,
+            Self::S3DownloadToUtf8(err) => &mut err.context,
+            Self::S3RetriesExhausted { context, .. } => context,
+            Self::SqsSendMessage(err) => &mut err.context,
+            Self::SqsSendMessageBatch(err) => &mut err.context,
+            Self::SqsGetQueueAttributes(err) => &mut err.context,
+            Self::SqsReceiveMessage(err) => &mut err.context,
+            Self::SqsDeleteMessage(err) => &mut err.context,
+            Self::SqsDeleteMessageBatch(err) => &mut err.context,
+            Self::SqsPurgeQueue(err) => &mut err.context,
+            Self::SqsChangeMessageVisibility(err) => &mut err.context,
+            Self::SqsChangeMessageVisibilityBatch(err) => &mut err.context,
+            Self::SqsBuildMessageAttribute(err) => &mut err.context,
+            Self::SqsReceiveMessageUnMarshallMessageBody(err) => &mut err.context,
+            Self::PointerCodec(err) => &mut err.context,
+            #[cfg(feature = "sns")]
+            Self::SnsPublish(err) => &mut err.context,
+            #[cfg(feature = "sns")]
+            Self::SnsPublishBatch(err) => &mut err.context,
+            Self::PayloadCodec(err) => &mut err.context,
+            #[cfg(feature = "sns")]
+            Self::SnsJsonMessageStructure { context } => context,
+            Self::PayloadCodecMismatch { context, .. } => context,
+            Self::ChecksumMismatch { context, .. } => context,
+            Self::DirectoryBucketUnsupported { context, .. } => context,
+            Self::ReconcileContentAddressedKeys { context } => context,
+            Self::ReconcileWithoutObjectPrefix { context } => context,
+            Self::NoBucketName { context } => context,
+            Self::NoMessageBody { context } => context,
+            Self::NoReceiptHandle { context } => context,
+        }
+    }
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_smithy_http_client::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::body::SdkBody;
+
+    use super::*;
+    use crate::tests::{make_replay_event, make_replay_s3_client, make_replay_sqs_client};
+    use crate::{SqsExtendedClient, SqsExtendedClientBuilder};
+
+    static QUEUE_URL: &str = "https://sqs.us-west-2.amazonaws.com/123456789012/orders";
+
+    fn make_test_error() -> SqsExtendedClientError {
+        let invalid_utf8: Vec<u8> = vec![0xff];
+
+        std::str::from_utf8(&invalid_utf8)
+            .map(|_| ())
+            .expect_err("expected invalid UTF-8")
+            .into()
+    }
+
+    #[test]
+    fn test_with_context() {
+        let err: SqsExtendedClientError = make_test_error()
+            .with_context(ErrorContext::default().with_message_id(Some("MESSAGE_ID")))
+            .with_context(
+                ErrorContext::new("receive_message")
+                    .with_queue_url(Some("QUEUE_URL"))
+                    .with_message_id(Some("OTHER_MESSAGE_ID")),
+            );
+
+        assert!(matches!(err, SqsExtendedClientError::S3DownloadToUtf8(_)));
+
+        let context: &ErrorContext = err.context().expect("no context");
+
+        assert_eq!(Some("receive_message"), context.operation());
+        assert_eq!(Some("QUEUE_URL"), context.queue_url());
+        assert_eq!(Some("MESSAGE_ID"), context.message_id());
+        assert_eq!(None, context.bucket());
+    }
+
+    #[test]
+    fn test_with_context_request_error() {
+        let err: SqsExtendedClientError = SqsExtendedClientError::NoReceiptHandle { context: None }
+            .with_context(ErrorContext::new("delete_message").with_queue_url(Some("QUEUE_URL")));
+
+        assert!(matches!(
+            err,
+            SqsExtendedClientError::NoReceiptHandle { .. }
+        ));
+
+        let context: &ErrorContext = err.context().expect("no context");
+
+        assert_eq!(Some("delete_message"), context.operation());
+        assert_eq!(Some("QUEUE_URL"), context.queue_url());
+        assert_eq!(
+            "No receipt handle (operation: delete_message, queue: QUEUE_URL)",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let err: SqsExtendedClientError = make_test_error().with_context(
+            ErrorContext::new("receive_message")
+                .with_s3_pointer(&S3Pointer::new(
+                    "payloads".to_string(),
+                    "key".to_string(),
+                    "".to_string(),
+                ))
+                .with_payload_size(Some(300000)),
+        );
+
+        assert_eq!(
+            "S3 Byte Stream Error: invalid utf-8 sequence of 1 bytes from index 0 (operation: receive_message, bucket: payloads, key: key, payload size: 300000)",
+            err.to_string()
+        );
+        assert_eq!(
+            "S3 Byte Stream Error: invalid utf-8 sequence of 1 bytes from index 0",
+            make_test_error().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_receive_message_download_context() {
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![make_replay_event(
+            r#"{"Messages":[{
+                    "MessageId":"MESSAGE_ID",
+                    "ReceiptHandle":"RECEIPT_HANDLE",
+                    "Body":"[\"software.amazon.payloadoffloading.PayloadS3Pointer\",{\"s3BucketName\":\"payloads\",\"s3Key\":\"KEY\"}]",
+                    "MessageAttributes":{"ExtendedPayloadSize":{"DataType":"Number","StringValue":"300000"}}
+                }]}"#,
+        )]);
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(vec![ReplayEvent::new(
+            HttpRequest::new(SdkBody::empty()),
+            HttpResponse::new(
+                StatusCode::try_from(404).expect("invalid status code"),
+                SdkBody::from("<Error><Code>NoSuchKey</Code><Message>no key</Message></Error>"),
+            ),
+        )]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client)).build();
+
+        let err: SqsExtendedClientError = sqs_extended_client
+            .receive_message(
+                make_replay_sqs_client(&sqs_http_client)
+                    .receive_message()
+                    .queue_url(QUEUE_URL),
+            )
+            .await
+            .expect_err("receive should fail");
+
+        assert!(matches!(err, SqsExtendedClientError::S3Download(_)));
+
+        let context: &ErrorContext = err.context().expect("no context");
+
+        assert_eq!(Some("receive_message"), context.operation());
+        assert_eq!(Some(QUEUE_URL), context.queue_url());
+        assert_eq!(Some("MESSAGE_ID"), context.message_id());
+        assert_eq!(Some("payloads"), context.bucket());
+        assert_eq!(Some("KEY"), context.key());
+        assert_eq!(Some(300000), context.payload_size());
+    }
+}
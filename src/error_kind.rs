@@ -0,0 +1,276 @@
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::result::SdkError;
+
+use crate::SqsExtendedClientError;
+
+// Error codes S3, SQS and SNS use when a caller is sending too fast.
+const THROTTLING_ERROR_CODES: [&str; 7] = [
+    "SlowDown",
+    "Throttling",
+    "ThrottlingException",
+    "ThrottledException",
+    "RequestThrottled",
+    "RequestLimitExceeded",
+    "TooManyRequestsException",
+];
+const NOT_FOUND_ERROR_CODES: [&str; 6] = [
+    "NoSuchKey",
+    "NoSuchBucket",
+    "NotFound",
+    "QueueDoesNotExist",
+    "AWS.SimpleQueueService.NonExistentQueue",
+    "NotFoundException",
+];
+const ACCESS_DENIED_ERROR_CODES: [&str; 6] = [
+    "AccessDenied",
+    "AccessDeniedException",
+    "AuthorizationError",
+    "InvalidAccessKeyId",
+    "SignatureDoesNotMatch",
+    "ExpiredToken",
+];
+const TRANSIENT_ERROR_CODES: [&str; 4] = [
+    "InternalError",
+    "ServiceUnavailable",
+    "RequestTimeout",
+    "KMS.ThrottlingException",
+];
+
+//-ERROR KIND-------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    // The service asked the caller to slow down - retry with backoff.
+    Throttling,
+    // Timeouts, dropped connections, truncated downloads and 5xx responses -
+    // the same request may succeed when retried.
+    Transient,
+    // A queue, bucket or payload object does not exist, e.g. a payload deleted
+    // before its message was received.
+    NotFound,
+    AccessDenied,
+    // The request was rejected or could not be built - retrying will not help.
+    InvalidRequest,
+    // A payload, pointer or message body that cannot be read - a candidate for
+    // the dead-letter queue.
+    InvalidPayload,
+    // The client is configured for something it cannot do.
+    Configuration,
+    Other,
+}
+
+impl SqsExtendedClientError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::S3Upload(err) => sdk_error_kind(err),
+            Self::S3Download(err) => sdk_error_kind(err),
+            Self::S3DeleteObject(err) => sdk_error_kind(err),
+            Self::S3DeleteObjects(err) => sdk_error_kind(err),
+            Self::S3ListObjects(err) => sdk_error_kind(err),
+            Self::S3HeadObject(err) => sdk_error_kind(err),
+            Self::S3GetObjectTagging(err) => sdk_error_kind(err),
+            Self::S3PutObjectTagging(err) => sdk_error_kind(err),
+            Self::S3CopyObject(err) => sdk_error_kind(err),
+            Self::S3DownloadToBytes(_) => ErrorKind::Transient,
+            Self::S3DownloadToUtf8(_) => ErrorKind::InvalidPayload,
+            Self::S3RetriesExhausted { source, .. } => source.kind(),
+            Self::SqsSendMessage(err) => sdk_error_kind(err),
+            Self::SqsSendMessageBatch(err) => sdk_error_kind(err),
+            Self::SqsGetQueueAttributes(err) => sdk_error_kind(err),
+            Self::SqsReceiveMessage(err) => sdk_error_kind(err),
+            Self::SqsDeleteMessage(err) => sdk_error_kind(err),
+            Self::SqsDeleteMessageBatch(err) => sdk_error_kind(err),
+            Self::SqsPurgeQueue(err) => sdk_error_kind(err),
+            Self::SqsChangeMessageVisibility(err) => sdk_error_kind(err),
+            Self::SqsChangeMessageVisibilityBatch(err) => sdk_error_kind(err),
+            Self::SqsBuildMessageAttribute(_) => ErrorKind::InvalidRequest,
+            Self::SqsReceiveMessageUnMarshallMessageBody(_) => ErrorKind::InvalidPayload,
+            Self::PointerCodec(_) => ErrorKind::InvalidPayload,
+            #[cfg(feature = "sns")]
+            Self::SnsPublish(err) => sdk_error_kind(err),
+            #[cfg(feature = "sns")]
+            Self::SnsPublishBatch(err) => sdk_error_kind(err),
+            #[cfg(feature = "sns")]
+            Self::SnsJsonMessageStructure { .. } => ErrorKind::Configuration,
+            Self::PayloadCodec(_) => ErrorKind::InvalidPayload,
+            Self::PayloadCodecMismatch { .. } => ErrorKind::InvalidPayload,
+            Self::ChecksumMismatch { .. } => ErrorKind::InvalidPayload,
+            Self::DirectoryBucketUnsupported { .. } => ErrorKind::Configuration,
+            Self::ReconcileContentAddressedKeys { .. } => ErrorKind::Configuration,
+            Self::ReconcileWithoutObjectPrefix { .. } => ErrorKind::Configuration,
+            Self::NoBucketName { .. } => ErrorKind::Configuration,
+            Self::NoMessageBody { .. } => ErrorKind::InvalidRequest,
+            Self::NoReceiptHandle { .. } => ErrorKind::InvalidRequest,
+        }
+    }
+
+    // Whether the failed call may succeed if made again - throttling and
+    // transient errors.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.kind(), ErrorKind::Throttling | ErrorKind::Transient)
+    }
+
+    pub fn is_throttling(&self) -> bool {
+        self.kind() == ErrorKind::Throttling
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.kind() == ErrorKind::NotFound
+    }
+}
+
+fn sdk_error_kind<E: ProvideErrorMetadata>(err: &SdkError<E, HttpResponse>) -> ErrorKind {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            return ErrorKind::Transient;
+        }
+        SdkError::ConstructionFailure(_) => return ErrorKind::InvalidRequest,
+        _ => {}
+    }
+
+    if let Some(code) = err.code() {
+        if THROTTLING_ERROR_CODES.contains(&code) {
+            return ErrorKind::Throttling;
+        }
+        if NOT_FOUND_ERROR_CODES.contains(&code) {
+            return ErrorKind::NotFound;
+        }
+        if ACCESS_DENIED_ERROR_CODES.contains(&code) {
+            return ErrorKind::AccessDenied;
+        }
+        if TRANSIENT_ERROR_CODES.contains(&code) {
+            return ErrorKind::Transient;
+        }
+    }
+
+    // responses without an error code, e.g. to HEAD requests
+    match err
+        .raw_response()
+        .map(|response| response.status().as_u16())
+    {
+        Some(429) => ErrorKind::Throttling,
+        Some(404) => ErrorKind::NotFound,
+        Some(403) => ErrorKind::AccessDenied,
+        Some(500..=599) => ErrorKind::Transient,
+        Some(400..=499) => ErrorKind::InvalidRequest,
+        _ => ErrorKind::Other,
+    }
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use aws_smithy_http_client::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_runtime_api::client::orchestrator::HttpRequest;
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::body::SdkBody;
+
+    use super::*;
+    use crate::tests::{make_replay_s3_client, make_replay_sqs_client};
+
+    fn make_error_event(status: u16, body: &str) -> StaticReplayClient {
+        StaticReplayClient::new(vec![ReplayEvent::new(
+            HttpRequest::new(SdkBody::empty()),
+            HttpResponse::new(
+                StatusCode::try_from(status).expect("invalid status code"),
+                SdkBody::from(body),
+            ),
+        )])
+    }
+
+    async fn get_object_error(status: u16, body: &str) -> SqsExtendedClientError {
+        make_replay_s3_client(&make_error_event(status, body))
+            .get_object()
+            .bucket("payloads")
+            .key("key")
+            .send()
+            .await
+            .map(|_| ())
+            .expect_err("expected an error")
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_kind_not_found() {
+        let err: SqsExtendedClientError = get_object_error(
+            404,
+            "<Error><Code>NoSuchKey</Code><Message>no key</Message></Error>",
+        )
+        .await;
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.is_not_found());
+        assert!(!err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_kind_access_denied() {
+        let err: SqsExtendedClientError = get_object_error(
+            403,
+            "<Error><Code>AccessDenied</Code><Message>denied</Message></Error>",
+        )
+        .await;
+
+        assert_eq!(ErrorKind::AccessDenied, err.kind());
+    }
+
+    #[tokio::test]
+    async fn test_kind_sqs_invalid_request() {
+        let err: SqsExtendedClientError = make_replay_sqs_client(&make_error_event(
+            400,
+            r#"{"__type":"com.amazonaws.sqs#InvalidMessageContents","message":"bad"}"#,
+        ))
+        .delete_message()
+        .queue_url("https://sqs.us-west-2.amazonaws.com/123456789012/orders")
+        .receipt_handle("handle")
+        .send()
+        .await
+        .map(|_| ())
+        .expect_err("expected an error")
+        .into();
+
+        assert_eq!(ErrorKind::InvalidRequest, err.kind());
+    }
+
+    #[test]
+    fn test_kind_client_errors() {
+        assert_eq!(
+            ErrorKind::Configuration,
+            SqsExtendedClientError::NoBucketName { context: None }.kind()
+        );
+
+        let err: SqsExtendedClientError = SqsExtendedClientError::S3RetriesExhausted {
+            operation: "GetObject".to_string(),
+            attempts: 3,
+            source: Box::new(SqsExtendedClientError::ChecksumMismatch {
+                expected: "a".to_string(),
+                found: "b".to_string(),
+                context: None,
+            }),
+            context: None,
+        };
+
+        assert_eq!(ErrorKind::InvalidPayload, err.kind());
+        assert!(err.source().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_source() {
+        let err: SqsExtendedClientError = get_object_error(
+            404,
+            "<Error><Code>NoSuchKey</Code><Message>no key</Message></Error>",
+        )
+        .await;
+
+        assert!(err.source().is_some());
+        assert!(
+            SqsExtendedClientError::NoBucketName { context: None }
+                .source()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use aws_sdk_sqs::types::MessageAttributeValue;
+use aws_smithy_types::DateTime;
+use aws_smithy_types::date_time::Format;
+use uuid::Uuid;
+
+//-KEY CONTEXT------------------------------------------------------------------
+
+pub struct KeyContext<'a> {
+    queue_url: Option<&'a str>,
+    message_group_id: Option<&'a str>,
+    message_attributes: Option<&'a HashMap<String, MessageAttributeValue>>,
+    now: SystemTime,
+}
+
+impl<'a> KeyContext<'a> {
+    pub fn new(
+        queue_url: Option<&'a str>,
+        message_group_id: Option<&'a str>,
+        message_attributes: Option<&'a HashMap<String, MessageAttributeValue>>,
+        now: SystemTime,
+    ) -> KeyContext<'a> {
+        KeyContext {
+            queue_url,
+            message_group_id,
+            message_attributes,
+            now,
+        }
+    }
+
+    pub fn queue_url(&self) -> Option<&str> {
+        self.queue_url
+    }
+
+    // The last path segment of the queue URL.
+    pub fn queue_name(&self) -> Option<&str> {
+        self.queue_url?
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+    }
+
+    // The region in the queue URL's host - `sqs.<region>.amazonaws.com` or the
+    // legacy `<region>.queue.amazonaws.com`.
+    pub fn queue_region(&self) -> Option<&str> {
+        let host: &str = self.queue_url?.split("://").nth(1)?.split('/').next()?;
+        let mut labels = host.split('.');
+
+        match (labels.next()?, labels.next()?) {
+            ("sqs", region) => Some(region),
+            (region, "queue") => Some(region),
+            _ => None,
+        }
+    }
+
+    pub fn message_group_id(&self) -> Option<&str> {
+        self.message_group_id
+    }
+
+    pub fn message_attributes(&self) -> Option<&HashMap<String, MessageAttributeValue>> {
+        self.message_attributes
+    }
+
+    pub fn message_attribute(&self, name: &str) -> Option<&str> {
+        self.message_attributes?.get(name)?.string_value.as_deref()
+    }
+
+    pub fn now(&self) -> SystemTime {
+        self.now
+    }
+}
+
+//-KEY STRATEGIES---------------------------------------------------------------
+
+// Names the S3 object for an offloaded payload. The client still places the
+// returned name under `object_prefix`.
+pub trait KeyStrategy: Send + Sync {
+    fn object_name(&self, context: &KeyContext) -> String;
+}
+
+// `uuid`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomKeyStrategy;
+
+impl KeyStrategy for RandomKeyStrategy {
+    fn object_name(&self, _context: &KeyContext) -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+// `yyyy/mm/dd/<inner>` in UTC
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DatePartitionedKeyStrategy<S: KeyStrategy = RandomKeyStrategy> {
+    inner: S,
+}
+
+impl DatePartitionedKeyStrategy {
+    pub fn new() -> DatePartitionedKeyStrategy {
+        DatePartitionedKeyStrategy {
+            inner: RandomKeyStrategy,
+        }
+    }
+}
+
+impl<S: KeyStrategy> DatePartitionedKeyStrategy<S> {
+    pub fn wrapping(inner: S) -> DatePartitionedKeyStrategy<S> {
+        DatePartitionedKeyStrategy { inner }
+    }
+}
+
+impl<S: KeyStrategy> KeyStrategy for DatePartitionedKeyStrategy<S> {
+    fn object_name(&self, context: &KeyContext) -> String {
+        format!(
+            "{}/{}",
+            date_partition(context.now()),
+            self.inner.object_name(context)
+        )
+    }
+}
+
+// `<queue name>/<inner>`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueuePrefixKeyStrategy<S: KeyStrategy = RandomKeyStrategy> {
+    inner: S,
+}
+
+impl QueuePrefixKeyStrategy {
+    pub fn new() -> QueuePrefixKeyStrategy {
+        QueuePrefixKeyStrategy {
+            inner: RandomKeyStrategy,
+        }
+    }
+}
+
+impl<S: KeyStrategy> QueuePrefixKeyStrategy<S> {
+    pub fn wrapping(inner: S) -> QueuePrefixKeyStrategy<S> {
+        QueuePrefixKeyStrategy { inner }
+    }
+}
+
+impl<S: KeyStrategy> KeyStrategy for QueuePrefixKeyStrategy<S> {
+    fn object_name(&self, context: &KeyContext) -> String {
+        let queue_name: &str = context.queue_name().unwrap_or("unknown-queue");
+
+        format!(
+            "{}/{}",
+            sanitize_segment(queue_name),
+            self.inner.object_name(context)
+        )
+    }
+}
+
+// `<value of a message attribute>/<inner>` - e.g. a tenant ID
+#[derive(Debug, Clone)]
+pub struct AttributePrefixKeyStrategy<S: KeyStrategy = RandomKeyStrategy> {
+    attribute_name: String,
+    fallback: String,
+    inner: S,
+}
+
+impl AttributePrefixKeyStrategy {
+    pub fn new(attribute_name: String, fallback: String) -> AttributePrefixKeyStrategy {
+        AttributePrefixKeyStrategy {
+            attribute_name,
+            fallback,
+            inner: RandomKeyStrategy,
+        }
+    }
+}
+
+impl<S: KeyStrategy> AttributePrefixKeyStrategy<S> {
+    pub fn wrapping(
+        attribute_name: String,
+        fallback: String,
+        inner: S,
+    ) -> AttributePrefixKeyStrategy<S> {
+        AttributePrefixKeyStrategy {
+            attribute_name,
+            fallback,
+            inner,
+        }
+    }
+}
+
+impl<S: KeyStrategy> KeyStrategy for AttributePrefixKeyStrategy<S> {
+    fn object_name(&self, context: &KeyContext) -> String {
+        let segment: &str = context
+            .message_attribute(&self.attribute_name)
+            .filter(|value| !value.is_empty())
+            .unwrap_or(&self.fallback);
+
+        format!(
+            "{}/{}",
+            sanitize_segment(segment),
+            self.inner.object_name(context)
+        )
+    }
+}
+
+fn date_partition(now: SystemTime) -> String {
+    // "2025-09-17T10:11:12Z" -> "2025/09/17"
+    let date_time: String = DateTime::from(now)
+        .fmt(Format::DateTime)
+        .unwrap_or_default();
+
+    date_time
+        .get(..10)
+        .unwrap_or("1970-01-01")
+        .replace('-', "/")
+}
+
+// Attribute values and queue names end up in the key - keep them to a single
+// path segment.
+fn sanitize_segment(segment: &str) -> String {
+    segment.replace(['/', '\\'], "_")
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn make_test_time() -> SystemTime {
+        // 2025-09-17T10:11:12Z
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_758_103_872)
+    }
+
+    struct FixedKeyStrategy;
+
+    impl KeyStrategy for FixedKeyStrategy {
+        fn object_name(&self, _context: &KeyContext) -> String {
+            "00000000-0000-0000-0000-000000000000".to_string()
+        }
+    }
+
+    #[test]
+    fn test_queue_name() {
+        let context: KeyContext = KeyContext::new(
+            Some("https://sqs.eu-west-2.amazonaws.com/123456789012/orders.fifo"),
+            None,
+            None,
+            make_test_time(),
+        );
+
+        assert_eq!(Some("orders.fifo"), context.queue_name());
+
+        let no_queue: KeyContext = KeyContext::new(None, None, None, make_test_time());
+
+        assert_eq!(None, no_queue.queue_name());
+    }
+
+    #[test]
+    fn test_queue_region() {
+        let context: KeyContext = KeyContext::new(
+            Some("https://sqs.eu-west-2.amazonaws.com/123456789012/orders.fifo"),
+            None,
+            None,
+            make_test_time(),
+        );
+
+        assert_eq!(Some("eu-west-2"), context.queue_region());
+
+        let legacy: KeyContext = KeyContext::new(
+            Some("https://us-east-1.queue.amazonaws.com/123456789012/orders"),
+            None,
+            None,
+            make_test_time(),
+        );
+
+        assert_eq!(Some("us-east-1"), legacy.queue_region());
+
+        let localstack: KeyContext = KeyContext::new(
+            Some("http://localhost:4566/000000000000/orders"),
+            None,
+            None,
+            make_test_time(),
+        );
+
+        assert_eq!(None, localstack.queue_region());
+    }
+
+    #[test]
+    fn test_random_key_strategy() {
+        let context: KeyContext = KeyContext::new(None, None, None, make_test_time());
+
+        let name: String = RandomKeyStrategy.object_name(&context);
+
+        assert!(Uuid::parse_str(&name).is_ok());
+    }
+
+    #[test]
+    fn test_date_partitioned_key_strategy() {
+        let context: KeyContext = KeyContext::new(None, None, None, make_test_time());
+
+        assert_eq!(
+            "2025/09/17/00000000-0000-0000-0000-000000000000",
+            DatePartitionedKeyStrategy::wrapping(FixedKeyStrategy).object_name(&context)
+        );
+        assert!(
+            DatePartitionedKeyStrategy::new()
+                .object_name(&context)
+                .starts_with("2025/09/17/")
+        );
+    }
+
+    #[test]
+    fn test_queue_prefix_key_strategy() {
+        let context: KeyContext = KeyContext::new(
+            Some("https://sqs.eu-west-2.amazonaws.com/123456789012/orders"),
+            None,
+            None,
+            make_test_time(),
+        );
+
+        assert_eq!(
+            "orders/00000000-0000-0000-0000-000000000000",
+            QueuePrefixKeyStrategy::wrapping(FixedKeyStrategy).object_name(&context)
+        );
+
+        let no_queue: KeyContext = KeyContext::new(None, None, None, make_test_time());
+
+        assert_eq!(
+            "unknown-queue/00000000-0000-0000-0000-000000000000",
+            QueuePrefixKeyStrategy::wrapping(FixedKeyStrategy).object_name(&no_queue)
+        );
+    }
+
+    #[test]
+    fn test_attribute_prefix_key_strategy() {
+        let tenant: MessageAttributeValue = MessageAttributeValue::builder()
+            .data_type("String")
+            .string_value("acme/corp")
+            .build()
+            .expect("Failed to build MessageAttributeValue");
+
+        let attributes: HashMap<String, MessageAttributeValue> =
+            HashMap::from([("TenantId".to_string(), tenant)]);
+
+        let context: KeyContext = KeyContext::new(None, None, Some(&attributes), make_test_time());
+
+        let strategy = AttributePrefixKeyStrategy::wrapping(
+            "TenantId".to_string(),
+            "no-tenant".to_string(),
+            DatePartitionedKeyStrategy::wrapping(FixedKeyStrategy),
+        );
+
+        assert_eq!(
+            "acme_corp/2025/09/17/00000000-0000-0000-0000-000000000000",
+            strategy.object_name(&context)
+        );
+
+        let no_attributes: KeyContext = KeyContext::new(None, None, None, make_test_time());
+
+        assert_eq!(
+            "no-tenant/2025/09/17/00000000-0000-0000-0000-000000000000",
+            strategy.object_name(&no_attributes)
+        );
+    }
+}
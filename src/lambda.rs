@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use aws_lambda_events::sqs::{SqsBatchResponse, SqsEvent, SqsMessage, SqsMessageAttribute};
+use aws_sdk_sqs::types::{Message, MessageAttributeValue, MessageSystemAttributeName};
+use aws_smithy_types::Blob;
+use futures::future::join_all;
+
+use crate::telemetry;
+use crate::{ExtendedMessage, S3Pointer, SqsExtendedClient, SqsExtendedClientError};
+
+//-LAMBDA RECORD----------------------------------------------------------------
+
+// One record of an SQS event with its payload resolved - or the error that
+// stopped it from being resolved.
+#[derive(Debug)]
+pub struct LambdaRecord {
+    message_id: String,
+    result: Result<ExtendedMessage, SqsExtendedClientError>,
+}
+
+impl LambdaRecord {
+    // The ID reported back to Lambda in `batchItemFailures`.
+    pub fn message_id(&self) -> &str {
+        &self.message_id
+    }
+
+    pub fn result(&self) -> &Result<ExtendedMessage, SqsExtendedClientError> {
+        &self.result
+    }
+
+    pub fn into_parts(self) -> (String, Result<ExtendedMessage, SqsExtendedClientError>) {
+        (self.message_id, self.result)
+    }
+}
+
+//-LAMBDA-----------------------------------------------------------------------
+
+impl SqsExtendedClient {
+    // Resolves every record concurrently, keeping the order of the event. The
+    // messages' `extended_receipt_handle` can be passed to `delete_message`.
+    pub async fn resolve_lambda_records(&self, records: Vec<SqsMessage>) -> Vec<LambdaRecord> {
+        join_all(records.into_iter().map(|record| async move {
+            let message_id: String = record.message_id.clone().unwrap_or_default();
+            let result: Result<ExtendedMessage, SqsExtendedClientError> =
+                self.resolve_message(to_sqs_message(record)).await;
+
+            LambdaRecord { message_id, result }
+        }))
+        .await
+    }
+
+    // Resolves the event and hands each message to `handler` in order. Records
+    // that fail to resolve or to be handled are reported as batch item failures
+    // - for FIFO queues every record after the first failure is reported too, so
+    // message group ordering survives the retry. Needs `ReportBatchItemFailures`
+    // on the event source mapping.
+    //
+    // Once every handler has returned, the payloads of the records reported as
+    // successful are released according to the `PayloadCleanup`, as
+    // `delete_message` does - Lambda deletes those messages when the function
+    // returns. Failed records keep their payloads for the redelivery. A payload
+    // that cannot be released is logged and left to `reconcile_payloads`.
+    pub async fn process_sqs_event<F, Fut, E>(
+        &self,
+        event: SqsEvent,
+        handler: F,
+    ) -> SqsBatchResponse
+    where
+        F: Fn(ExtendedMessage) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+    {
+        let fifo: bool = event.records.iter().any(is_fifo_record);
+
+        let mut failures: Vec<String> = Vec::new();
+        let mut handled: Vec<S3Pointer> = Vec::new();
+
+        for record in self.resolve_lambda_records(event.records).await {
+            if fifo && !failures.is_empty() {
+                failures.push(record.message_id);
+                continue;
+            }
+
+            let Ok(msg) = record.result else {
+                failures.push(record.message_id);
+                continue;
+            };
+
+            let s3_pointer: Option<S3Pointer> = msg.s3_pointer().cloned();
+
+            match handler(msg).await {
+                Ok(()) => handled.extend(s3_pointer),
+                Err(_) => failures.push(record.message_id),
+            }
+        }
+
+        for s3_pointer in &handled {
+            if let Err(err) = self.release_payload(s3_pointer).await {
+                telemetry::warn_payload_release_failed(s3_pointer, &err);
+            }
+        }
+
+        let mut response: SqsBatchResponse = SqsBatchResponse::default();
+        response.set_failures(failures);
+        response
+    }
+}
+
+fn is_fifo_record(record: &SqsMessage) -> bool {
+    record
+        .event_source_arn
+        .as_deref()
+        .is_some_and(|arn| arn.ends_with(".fifo"))
+}
+
+// Event records carry the same fields as a ReceiveMessage response, so the
+// receive path can resolve them as is.
+fn to_sqs_message(record: SqsMessage) -> Message {
+    let attributes: HashMap<MessageSystemAttributeName, String> = record
+        .attributes
+        .into_iter()
+        .map(|(name, value)| (MessageSystemAttributeName::from(name.as_str()), value))
+        .collect();
+
+    let message_attributes: HashMap<String, MessageAttributeValue> = record
+        .message_attributes
+        .into_iter()
+        .filter_map(|(name, value)| {
+            to_message_attribute_value(value).map(|sqs_value| (name, sqs_value))
+        })
+        .collect();
+
+    Message::builder()
+        .set_message_id(record.message_id)
+        .set_receipt_handle(record.receipt_handle)
+        .set_body(record.body)
+        .set_md5_of_body(record.md5_of_body)
+        .set_md5_of_message_attributes(record.md5_of_message_attributes)
+        .set_attributes(Some(attributes))
+        .set_message_attributes(Some(message_attributes))
+        .build()
+}
+
+fn to_message_attribute_value(attribute: SqsMessageAttribute) -> Option<MessageAttributeValue> {
+    MessageAttributeValue::builder()
+        .set_data_type(attribute.data_type)
+        .set_string_value(attribute.string_value)
+        .set_binary_value(attribute.binary_value.map(|data| Blob::new(data.0)))
+        .set_string_list_values(Some(attribute.string_list_values))
+        .set_binary_list_values(Some(
+            attribute
+                .binary_list_values
+                .into_iter()
+                .map(|data| Blob::new(data.0))
+                .collect(),
+        ))
+        .build()
+        .ok()
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_smithy_http_client::test_util::StaticReplayClient;
+    use aws_smithy_runtime_api::client::orchestrator::HttpRequest;
+
+    use crate::SqsExtendedClientBuilder;
+    use crate::tests::{make_replay_event, make_replay_s3_client, make_test_s3_client};
+
+    use super::*;
+
+    fn make_test_event(event_source_arn: &str) -> SqsEvent {
+        let records: Vec<String> = ["1", "2", "3"]
+            .iter()
+            .map(|id| {
+                format!(
+                    r#"{{
+                        "messageId": "{id}",
+                        "receiptHandle": "handle-{id}",
+                        "body": "message {id}",
+                        "attributes": {{"ApproximateReceiveCount": "1"}},
+                        "messageAttributes": {{
+                            "TenantId": {{"stringValue": "acme", "dataType": "String"}},
+                            "Raw": {{"binaryValue": "SVQnUyBCSU5BUlkgSE9ORVNU", "dataType": "Binary"}}
+                        }},
+                        "eventSourceARN": "{event_source_arn}",
+                        "eventSource": "aws:sqs",
+                        "awsRegion": "eu-west-2"
+                    }}"#
+                )
+            })
+            .collect();
+
+        serde_json::from_str(&format!(r#"{{"Records": [{}]}}"#, records.join(",")))
+            .expect("Failed to deserialize SqsEvent")
+    }
+
+    #[test]
+    fn test_to_sqs_message() {
+        let event: SqsEvent = make_test_event("arn:aws:sqs:eu-west-2:123456789012:queue");
+
+        let msg: Message = to_sqs_message(event.records[0].clone());
+
+        assert_eq!(Some("1".to_string()), msg.message_id);
+        assert_eq!(Some("handle-1".to_string()), msg.receipt_handle);
+        assert_eq!(Some("message 1".to_string()), msg.body);
+        assert_eq!(
+            Some(&"1".to_string()),
+            msg.attributes
+                .as_ref()
+                .and_then(|attrs| attrs.get(&MessageSystemAttributeName::ApproximateReceiveCount))
+        );
+
+        let msg_attrs: HashMap<String, MessageAttributeValue> =
+            msg.message_attributes.expect("no message attributes");
+
+        assert_eq!(Some("acme".to_string()), msg_attrs["TenantId"].string_value);
+        assert_eq!(
+            Some(Blob::new("IT'S BINARY HONEST")),
+            msg_attrs["Raw"].binary_value
+        );
+    }
+
+    #[test]
+    fn test_is_fifo_record() {
+        let event: SqsEvent = make_test_event("arn:aws:sqs:eu-west-2:123456789012:queue.fifo");
+
+        assert!(is_fifo_record(&event.records[0]));
+        assert!(!is_fifo_record(&SqsMessage::default()));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_lambda_records() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client()).build();
+
+        let mut event: SqsEvent = make_test_event("arn:aws:sqs:eu-west-2:123456789012:queue");
+        event.records[1].receipt_handle = None;
+
+        let records: Vec<LambdaRecord> = sqs_extended_client
+            .resolve_lambda_records(event.records)
+            .await;
+
+        assert_eq!(3, records.len());
+        assert_eq!("1", records[0].message_id());
+        assert_eq!(
+            "message 1",
+            records[0]
+                .result()
+                .as_ref()
+                .expect("not resolved")
+                .payload()
+        );
+        assert!(matches!(
+            records[1].result(),
+            Err(SqsExtendedClientError::NoReceiptHandle { .. })
+        ));
+        assert_eq!(
+            "handle-3",
+            records[2]
+                .result()
+                .as_ref()
+                .expect("not resolved")
+                .extended_receipt_handle()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_sqs_event() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client()).build();
+
+        let handler = |msg: ExtendedMessage| async move {
+            match msg.payload() {
+                "message 2" => Err("failed"),
+                _ => Ok(()),
+            }
+        };
+
+        let response: SqsBatchResponse = sqs_extended_client
+            .process_sqs_event(
+                make_test_event("arn:aws:sqs:eu-west-2:123456789012:queue"),
+                handler,
+            )
+            .await;
+
+        let failures: Vec<&str> = response
+            .batch_item_failures
+            .iter()
+            .map(|failure| failure.item_identifier.as_str())
+            .collect();
+
+        assert_eq!(vec!["2"], failures);
+
+        let fifo_response: SqsBatchResponse = sqs_extended_client
+            .process_sqs_event(
+                make_test_event("arn:aws:sqs:eu-west-2:123456789012:queue.fifo"),
+                handler,
+            )
+            .await;
+
+        let fifo_failures: Vec<&str> = fifo_response
+            .batch_item_failures
+            .iter()
+            .map(|failure| failure.item_identifier.as_str())
+            .collect();
+
+        assert_eq!(vec!["2", "3"], fifo_failures);
+    }
+
+    #[tokio::test]
+    async fn test_process_sqs_event_releases_handled_payloads() {
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event("offloaded message"),
+            make_replay_event("offloaded message"),
+            make_replay_event(""),
+        ]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("bucket-name".to_string())
+                .build();
+
+        let mut event: SqsEvent = make_test_event("arn:aws:sqs:eu-west-2:123456789012:queue");
+        event.records.truncate(2);
+        for record in &mut event.records {
+            record.body = Some(format!(
+                r#"["software.amazon.payloadoffloading.PayloadS3Pointer",{{"s3BucketName":"bucket-name","s3Key":"KEY-{}"}}]"#,
+                record.message_id.as_deref().unwrap_or_default()
+            ));
+            record.message_attributes.insert(
+                "ExtendedPayloadSize".to_string(),
+                serde_json::from_str(r#"{"stringValue": "17", "dataType": "Number"}"#)
+                    .expect("Failed to deserialize SqsMessageAttribute"),
+            );
+        }
+
+        let handler = |msg: ExtendedMessage| async move {
+            match msg.message_id() {
+                Some("1") => Err("failed"),
+                _ => Ok(()),
+            }
+        };
+
+        let response: SqsBatchResponse =
+            sqs_extended_client.process_sqs_event(event, handler).await;
+
+        assert_eq!(1, response.batch_item_failures.len());
+
+        // only the handled message's payload goes - the failed one is
+        // redelivered and must still find its payload
+        let requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+
+        assert_eq!(3, requests.len());
+        assert!(
+            requests[..2]
+                .iter()
+                .all(|request| request.method() == "GET")
+        );
+        assert_eq!("DELETE", requests[2].method());
+        assert!(requests[2].uri().contains("KEY-2"));
+    }
+}
@@ -1,48 +1,141 @@
 use std::collections::HashMap;
 use std::fmt;
 use std::str::Utf8Error;
+use std::sync::Mutex;
+use std::time::SystemTime;
 
+use aws_sdk_s3::config::Region;
+use aws_sdk_s3::operation::copy_object::CopyObjectError;
 use aws_sdk_s3::operation::delete_object::DeleteObjectError;
+use aws_sdk_s3::operation::delete_objects::DeleteObjectsError;
 use aws_sdk_s3::operation::get_object::{GetObjectError, GetObjectOutput};
+use aws_sdk_s3::operation::get_object_tagging::{GetObjectTaggingError, GetObjectTaggingOutput};
+use aws_sdk_s3::operation::head_object::{HeadObjectError, HeadObjectOutput};
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Error;
+use aws_sdk_s3::operation::put_object::builders::PutObjectFluentBuilder;
 use aws_sdk_s3::operation::put_object::{PutObjectError, PutObjectOutput};
+use aws_sdk_s3::operation::put_object_tagging::PutObjectTaggingError;
 use aws_sdk_s3::primitives::ByteStreamError;
+use aws_sdk_s3::types::{StorageClass, Tag, Tagging};
 use aws_sdk_sqs::operation::change_message_visibility::builders::ChangeMessageVisibilityFluentBuilder;
 use aws_sdk_sqs::operation::change_message_visibility::{
     ChangeMessageVisibilityError, ChangeMessageVisibilityOutput,
 };
+use aws_sdk_sqs::operation::change_message_visibility_batch::ChangeMessageVisibilityBatchError;
 use aws_sdk_sqs::operation::delete_message::builders::DeleteMessageFluentBuilder;
 use aws_sdk_sqs::operation::delete_message::{DeleteMessageError, DeleteMessageOutput};
+use aws_sdk_sqs::operation::delete_message_batch::DeleteMessageBatchError;
+use aws_sdk_sqs::operation::get_queue_attributes::GetQueueAttributesError;
+use aws_sdk_sqs::operation::purge_queue::PurgeQueueError;
 use aws_sdk_sqs::operation::receive_message::builders::ReceiveMessageFluentBuilder;
 use aws_sdk_sqs::operation::receive_message::{ReceiveMessageError, ReceiveMessageOutput};
 use aws_sdk_sqs::operation::send_message::builders::SendMessageFluentBuilder;
 use aws_sdk_sqs::operation::send_message::{SendMessageError, SendMessageOutput};
+use aws_sdk_sqs::operation::send_message_batch::builders::SendMessageBatchFluentBuilder;
+use aws_sdk_sqs::operation::send_message_batch::{SendMessageBatchError, SendMessageBatchOutput};
 use aws_sdk_sqs::types::Message;
 use aws_sdk_sqs::types::MessageAttributeValue;
+use aws_sdk_sqs::types::SendMessageBatchRequestEntry;
 use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
 use aws_smithy_runtime_api::client::result::SdkError;
 use aws_smithy_runtime_api::http::Response;
+use aws_smithy_types::Blob;
 use aws_smithy_types::byte_stream::ByteStream;
 use aws_smithy_types::error::operation::BuildError;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use regex::Regex;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Result as SerdeJsonResult;
-use uuid::Uuid;
-
+use sha2::{Digest, Sha256};
+
+use attribute_envelope::AttributeEnvelope;
+use checksum::checksum_mismatch;
+use directory_bucket::is_directory_bucket;
+use telemetry::{Instrument, Span, operation_span, s3_transfer_span};
+
+mod attribute_envelope;
+mod bucket_router;
+mod checksum;
+mod codec;
+mod directory_bucket;
+mod error_context;
+mod error_kind;
+mod key_strategy;
+#[cfg(feature = "lambda")]
+mod lambda;
+mod message_size;
+mod pointer_codec;
+mod purge;
+mod reconcile;
+mod redrive;
+mod retry;
+#[cfg(feature = "sns")]
+mod sns;
+mod telemetry;
+
+pub use attribute_envelope::ATTRIBUTES_ATTRIBUTE_NAME;
+pub use bucket_router::{BucketRouter, RegionalBucketRouter};
+pub use checksum::{CHECKSUM_ATTRIBUTE_NAME, PayloadChecksum};
+#[cfg(feature = "cbor")]
+pub use codec::CborCodec;
+#[cfg(feature = "msgpack")]
+pub use codec::MessagePackCodec;
+#[cfg(feature = "protobuf")]
+pub use codec::ProtobufCodec;
+pub use codec::{CODEC_ATTRIBUTE_NAME, Codec, CodecError, JsonCodec, TypedMessage};
+pub use error_context::{Contextual, ErrorContext};
+pub use error_kind::ErrorKind;
+pub use key_strategy::{
+    AttributePrefixKeyStrategy, DatePartitionedKeyStrategy, KeyContext, KeyStrategy,
+    QueuePrefixKeyStrategy, RandomKeyStrategy,
+};
+#[cfg(feature = "lambda")]
+pub use lambda::LambdaRecord;
+pub use message_size::{message_attribute_size, message_attributes_size, message_size};
+pub use pointer_codec::{
+    JavaPointerCodec, JsonObjectPointerCodec, PointerCodec, PointerCodecError, PointerFormat,
+};
+pub use purge::{PurgeMode, PurgeReport};
+pub use reconcile::ReconcileReport;
+pub use redrive::{RedriveMode, RedriveOptions};
+pub use retry::RetryPolicy;
+#[cfg(feature = "sns")]
+pub use sns::SnsExtendedClient;
+
+// Queues created before SQS raised its limit to 1 MiB keep 256 KiB, so that is
+// the threshold unless the queue's own limit is looked up.
 const MAX_MESSAGE_SIZE_IN_BYTES: usize = 262144;
+const SQS_MAX_MESSAGE_SIZE_IN_BYTES: usize = 1048576;
 static DEFAULT_POINTER_CLASS: &str = "software.amazon.payloadoffloading.PayloadS3Pointer";
 static LEGACY_RESERVED_ATTRIBUTE_NAME: &str = "SQSLargePayloadSize";
+static REFERENCE_COUNT_TAG: &str = "ExtendedPayloadReferences";
 
 //-SQS EXTENDED CLIENT BUILDER--------------------------------------------------
 
 pub struct SqsExtendedClientBuilder {
     s3_client: aws_sdk_s3::Client,
     bucket_name: Option<String>,
-    message_size_threshold: usize,
+    bucket_router: Option<Box<dyn BucketRouter>>,
+    sqs_client: Option<aws_sdk_sqs::Client>,
+    message_size_threshold: Option<usize>,
     batch_message_size_threshold: usize,
     always_s3: bool,
     pointer_class: String,
+    pointer_codec: Box<dyn PointerCodec>,
     reserved_attributes: Vec<String>,
     object_prefix: String,
+    bucket_wide_reconcile: bool,
+    content_addressed_keys: bool,
+    payload_deduplication_ids: bool,
+    key_strategy: Box<dyn KeyStrategy>,
+    payload_cleanup: Option<PayloadCleanup>,
+    payload_checksum: Option<PayloadChecksum>,
+    storage_class: Option<StorageClass>,
+    unwrap_sns_envelopes: bool,
+    offload_attributes: bool,
+    retry_policy: Option<RetryPolicy>,
 }
 
 impl SqsExtendedClientBuilder {
@@ -50,15 +143,28 @@ impl SqsExtendedClientBuilder {
         SqsExtendedClientBuilder {
             s3_client,
             bucket_name: None,
-            message_size_threshold: MAX_MESSAGE_SIZE_IN_BYTES,
+            bucket_router: None,
+            sqs_client: None,
+            message_size_threshold: None,
             batch_message_size_threshold: MAX_MESSAGE_SIZE_IN_BYTES,
             always_s3: false,
             pointer_class: DEFAULT_POINTER_CLASS.to_string(),
+            pointer_codec: Box::new(JavaPointerCodec),
             reserved_attributes: vec![
                 "ExtendedPayloadSize".to_string(),
                 LEGACY_RESERVED_ATTRIBUTE_NAME.to_string(),
             ],
             object_prefix: "".to_string(),
+            bucket_wide_reconcile: false,
+            content_addressed_keys: false,
+            payload_deduplication_ids: false,
+            key_strategy: Box::new(RandomKeyStrategy),
+            payload_cleanup: None,
+            payload_checksum: None,
+            storage_class: None,
+            unwrap_sns_envelopes: false,
+            offload_attributes: false,
+            retry_policy: None,
         }
     }
 
@@ -67,8 +173,29 @@ impl SqsExtendedClientBuilder {
         self
     }
 
+    // The bucket set by `with_s3_bucket_name` is still required - it takes the
+    // payloads the router has no bucket for.
+    pub fn with_bucket_router(
+        mut self,
+        bucket_router: impl BucketRouter + 'static,
+    ) -> SqsExtendedClientBuilder {
+        self.bucket_router = Some(Box::new(bucket_router));
+        self
+    }
+
+    // Overrides the queue limits looked up by `with_queue_size_lookup`.
     pub fn with_message_size_threshold(mut self, msg_size: usize) -> SqsExtendedClientBuilder {
-        self.message_size_threshold = msg_size;
+        self.message_size_threshold = Some(msg_size);
+        self
+    }
+
+    // Looks up each queue's `MaximumMessageSize` on its first send and uses it
+    // as that queue's threshold.
+    pub fn with_queue_size_lookup(
+        mut self,
+        sqs_client: aws_sdk_sqs::Client,
+    ) -> SqsExtendedClientBuilder {
+        self.sqs_client = Some(sqs_client);
         self
     }
 
@@ -98,37 +225,203 @@ impl SqsExtendedClientBuilder {
         self
     }
 
+    // The format `send_message` writes - receiving accepts every PointerFormat.
+    pub fn with_pointer_format(
+        mut self,
+        pointer_format: PointerFormat,
+    ) -> SqsExtendedClientBuilder {
+        self.pointer_codec = pointer_format.codec();
+        self
+    }
+
+    pub fn with_pointer_codec(
+        mut self,
+        pointer_codec: impl PointerCodec + 'static,
+    ) -> SqsExtendedClientBuilder {
+        self.pointer_codec = Box::new(pointer_codec);
+        self
+    }
+
     pub fn with_object_prefix(mut self, prefix: String) -> SqsExtendedClientBuilder {
         self.object_prefix = prefix;
         self
     }
 
+    // Lets `reconcile_payloads` scan a bucket without an object prefix. Every
+    // object in it older than the retention period is deleted, so only use it
+    // for a bucket that holds nothing but this client's payloads.
+    pub fn with_bucket_wide_reconcile(
+        mut self,
+        bucket_wide_reconcile: bool,
+    ) -> SqsExtendedClientBuilder {
+        self.bucket_wide_reconcile = bucket_wide_reconcile;
+        self
+    }
+
+    // Gives offloaded messages sent to a FIFO queue without a deduplication ID
+    // the SHA-256 of their body as one. Only for queues with content-based
+    // deduplication enabled - elsewhere SQS rejects a message without an ID.
+    pub fn with_payload_deduplication_ids(
+        mut self,
+        payload_deduplication_ids: bool,
+    ) -> SqsExtendedClientBuilder {
+        self.payload_deduplication_ids = payload_deduplication_ids;
+        self
+    }
+
+    pub fn with_content_addressed_keys(
+        mut self,
+        content_addressed_keys: bool,
+    ) -> SqsExtendedClientBuilder {
+        self.content_addressed_keys = content_addressed_keys;
+        self
+    }
+
+    // Ignored for content addressed keys, which are always named by the
+    // payload's SHA-256.
+    pub fn with_key_strategy(
+        mut self,
+        key_strategy: impl KeyStrategy + 'static,
+    ) -> SqsExtendedClientBuilder {
+        self.key_strategy = Box::new(key_strategy);
+        self
+    }
+
+    pub fn with_payload_cleanup(
+        mut self,
+        payload_cleanup: PayloadCleanup,
+    ) -> SqsExtendedClientBuilder {
+        self.payload_cleanup = Some(payload_cleanup);
+        self
+    }
+
+    pub fn with_payload_checksum(
+        mut self,
+        payload_checksum: PayloadChecksum,
+    ) -> SqsExtendedClientBuilder {
+        self.payload_checksum = Some(payload_checksum);
+        self
+    }
+
+    // Not applied to S3 Express One Zone directory buckets, which only have
+    // their own storage class.
+    pub fn with_storage_class(mut self, storage_class: StorageClass) -> SqsExtendedClientBuilder {
+        self.storage_class = Some(storage_class);
+        self
+    }
+
+    // For SNS subscriptions without raw message delivery - the SQS body is then
+    // an SNS notification holding the published message and attributes.
+    pub fn with_sns_envelope_unwrapping(
+        mut self,
+        unwrap_sns_envelopes: bool,
+    ) -> SqsExtendedClientBuilder {
+        self.unwrap_sns_envelopes = unwrap_sns_envelopes;
+        self
+    }
+
+    // Moves the largest attributes of an offloaded message into its S3 object
+    // when the attributes alone would still be over the threshold.
+    pub fn with_attribute_offloading(
+        mut self,
+        offload_attributes: bool,
+    ) -> SqsExtendedClientBuilder {
+        self.offload_attributes = offload_attributes;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> SqsExtendedClientBuilder {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    #[cfg(feature = "sns")]
+    pub fn build_sns(self) -> SnsExtendedClient {
+        SnsExtendedClient::new(self.build())
+    }
+
     pub fn build(self) -> SqsExtendedClient {
         let receipt_handler_regex: Regex = Regex::new(r"^-\.\.s3BucketName\.\.-(.*)-\.\.s3BucketName\.\.--\.\.s3Key\.\.-(.*)-\.\.s3Key\.\.-(.*)").unwrap();
 
+        let directory_bucket: bool = self.bucket_name.as_deref().is_some_and(is_directory_bucket);
+
         SqsExtendedClient {
             s3_client: self.s3_client,
+            regional_s3_clients: Mutex::new(HashMap::new()),
             bucket_name: self.bucket_name,
+            bucket_router: self.bucket_router,
+            sqs_client: self.sqs_client,
+            queue_size_limits: Mutex::new(HashMap::new()),
             message_size_threshold: self.message_size_threshold,
+            batch_message_size_threshold: self.batch_message_size_threshold,
             always_through_s3: self.always_s3,
             pointer_class: self.pointer_class,
+            pointer_codec: self.pointer_codec,
             reserved_attributes: self.reserved_attributes,
             object_prefix: self.object_prefix,
+            bucket_wide_reconcile: self.bucket_wide_reconcile,
+            content_addressed_keys: self.content_addressed_keys,
+            payload_deduplication_ids: self.payload_deduplication_ids,
+            key_strategy: self.key_strategy,
+            // a content addressed object can be shared by many messages, so it
+            // must not be deleted when the first of them is acknowledged - and
+            // directory buckets have no object tags to count references in
+            payload_cleanup: self.payload_cleanup.unwrap_or(
+                match (self.content_addressed_keys, directory_bucket) {
+                    (false, _) => PayloadCleanup::DeleteOnAck,
+                    (true, false) => PayloadCleanup::ReferenceCounted,
+                    (true, true) => PayloadCleanup::RetentionOnly,
+                },
+            ),
+            payload_checksum: self.payload_checksum,
+            storage_class: self.storage_class,
+            unwrap_sns_envelopes: self.unwrap_sns_envelopes,
+            offload_attributes: self.offload_attributes,
+            retry_policy: self.retry_policy,
             extended_receipt_handler_regex: receipt_handler_regex,
         }
     }
 }
 
+//-PAYLOAD CLEANUP--------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadCleanup {
+    // Delete the S3 object when its message is deleted.
+    DeleteOnAck,
+    // Count the messages pointing at an object in an object tag and delete the
+    // object when the last of them is deleted.
+    ReferenceCounted,
+    // Never delete S3 objects - leave them to bucket lifecycle rules.
+    RetentionOnly,
+}
+
 //-SQS EXTENDED CLIENT----------------------------------------------------------
 
 pub struct SqsExtendedClient {
     s3_client: aws_sdk_s3::Client,
+    regional_s3_clients: Mutex<HashMap<String, aws_sdk_s3::Client>>,
     bucket_name: Option<String>,
-    message_size_threshold: usize,
+    bucket_router: Option<Box<dyn BucketRouter>>,
+    sqs_client: Option<aws_sdk_sqs::Client>,
+    queue_size_limits: Mutex<HashMap<String, usize>>,
+    message_size_threshold: Option<usize>,
+    batch_message_size_threshold: usize,
     always_through_s3: bool,
     pointer_class: String,
+    pointer_codec: Box<dyn PointerCodec>,
     reserved_attributes: Vec<String>,
     object_prefix: String,
+    bucket_wide_reconcile: bool,
+    content_addressed_keys: bool,
+    payload_deduplication_ids: bool,
+    key_strategy: Box<dyn KeyStrategy>,
+    payload_cleanup: PayloadCleanup,
+    payload_checksum: Option<PayloadChecksum>,
+    storage_class: Option<StorageClass>,
+    unwrap_sns_envelopes: bool,
+    offload_attributes: bool,
+    retry_policy: Option<RetryPolicy>,
     extended_receipt_handler_regex: Regex,
 }
 
@@ -136,166 +429,396 @@ impl SqsExtendedClient {
     pub async fn send_message(
         &self,
         msg_input: SendMessageFluentBuilder,
+    ) -> Result<SendMessageOutput, SqsExtendedClientError> {
+        let context: ErrorContext =
+            ErrorContext::new("send_message").with_queue_url(msg_input.get_queue_url().as_deref());
+        let span: Span = operation_span!("send_message", msg_input.get_queue_url().as_deref());
+
+        let result: Result<SendMessageOutput, SqsExtendedClientError> = self
+            .offload_and_send_message(msg_input)
+            .instrument(span.clone())
+            .await;
+
+        if let Ok(output) = &result {
+            span.record("message_id", output.message_id().unwrap_or_default());
+        }
+
+        result.map_err(|err| err.with_context(context))
+    }
+
+    pub async fn send_message_batch(
+        &self,
+        batch_input: SendMessageBatchFluentBuilder,
+    ) -> Result<SendMessageBatchOutput, SqsExtendedClientError> {
+        let context: ErrorContext = ErrorContext::new("send_message_batch")
+            .with_queue_url(batch_input.get_queue_url().as_deref());
+        let span: Span =
+            operation_span!("send_message_batch", batch_input.get_queue_url().as_deref());
+        span.record(
+            "message_count",
+            batch_input.get_entries().as_ref().map_or(0, Vec::len) as u64,
+        );
+
+        self.offload_and_send_message_batch(batch_input)
+            .instrument(span)
+            .await
+            .map_err(|err| err.with_context(context))
+    }
+
+    async fn offload_and_send_message(
+        &self,
+        msg_input: SendMessageFluentBuilder,
     ) -> Result<SendMessageOutput, SqsExtendedClientError> {
         let Some(bn) = &self.bucket_name else {
-            return Err(SqsExtendedClientError::NoBucketName);
+            return Err(SqsExtendedClientError::NoBucketName { context: None });
         };
         let bucket_name: String = bn.to_string();
 
         let Some(msg_bdy) = msg_input.get_message_body() else {
-            return Err(SqsExtendedClientError::NoMessageBody);
+            return Err(SqsExtendedClientError::NoMessageBody { context: None });
         };
         let message_body: &str = msg_bdy;
+        let queue_url: Option<String> = msg_input.get_queue_url().clone();
+
+        let threshold: usize = self.message_size_threshold_for(queue_url.as_deref()).await;
+
+        let offloaded: bool = self.always_through_s3
+            || self.message_exceeds_threshold(
+                message_body,
+                msg_input.get_message_attributes().as_ref(),
+                threshold,
+            );
+
+        Span::current()
+            .record("offloaded", offloaded)
+            .record("payload_bytes", message_body.len() as u64);
+
+        let result: Result<SendMessageOutput, SdkError<SendMessageError, Response>> = if offloaded {
+            let key_context: KeyContext = KeyContext::new(
+                msg_input.get_queue_url().as_deref(),
+                msg_input.get_message_group_id().as_deref(),
+                msg_input.get_message_attributes().as_ref(),
+                SystemTime::now(),
+            );
+
+            let mut message_attributes: HashMap<String, MessageAttributeValue> = msg_input
+                .get_message_attributes()
+                .clone()
+                .unwrap_or_default();
+            let offloaded_attributes: HashMap<String, MessageAttributeValue> = self
+                .take_offloaded_attributes(
+                    &mut message_attributes,
+                    threshold,
+                    &bucket_name,
+                    message_body,
+                    &key_context,
+                );
+
+            let envelope: Option<String> = (!offloaded_attributes.is_empty()).then(|| {
+                AttributeEnvelope::new(message_body, &offloaded_attributes).marshall_json()
+            });
+
+            let new_msg: S3Pointer = self
+                .offload_payload(
+                    bucket_name,
+                    envelope.as_deref().unwrap_or(message_body),
+                    &key_context,
+                )
+                .await?;
 
-        let result: Result<SendMessageOutput, SdkError<SendMessageError, Response>> = if self
-            .always_through_s3
-            || self.message_exceeds_threshold(message_body, msg_input.get_message_attributes())
-        {
-            let s3_key: String = self.s3_key(Uuid::new_v4().to_string());
-
-            let s3_result: Result<PutObjectOutput, SdkError<PutObjectError, HttpResponse>> = self
-                .s3_client
-                .put_object()
-                .bucket(&bucket_name)
-                .key(&s3_key)
-                .body(ByteStream::from(message_body.as_bytes().to_vec()))
-                .send()
-                .await;
-
-            if let Err(s3_error) = s3_result {
-                return Err(SqsExtendedClientError::S3Upload(s3_error));
-            }
+            let reserved_attribute: MessageAttributeValue =
+                Self::reserved_attribute_for(message_body.len())?;
 
-            let new_msg: S3Pointer = S3Pointer {
-                s3_bucket_name: bucket_name,
-                s3_key,
-                class: self.pointer_class.clone(),
+            let msg_input: SendMessageFluentBuilder = match self.fifo_deduplication_id(
+                msg_input.get_queue_url(),
+                msg_input.get_message_deduplication_id(),
+                message_body,
+            ) {
+                None => msg_input,
+                Some(deduplication_id) => msg_input.message_deduplication_id(deduplication_id),
             };
 
-            let message_body_size: usize = message_body.len();
+            let msg_input: SendMessageFluentBuilder = if offloaded_attributes.is_empty() {
+                msg_input
+            } else {
+                msg_input
+                    .set_message_attributes(Some(message_attributes))
+                    .message_attributes(
+                        ATTRIBUTES_ATTRIBUTE_NAME,
+                        Self::attributes_attribute_for(offloaded_attributes.len())?,
+                    )
+            };
 
-            let reserved_attribute: MessageAttributeValue = MessageAttributeValue::builder()
-                .data_type("Number")
-                .string_value(message_body_size.to_string())
-                .build()?;
+            let msg_input: SendMessageFluentBuilder = match &new_msg.checksum {
+                None => msg_input,
+                Some(checksum) => msg_input.message_attributes(
+                    CHECKSUM_ATTRIBUTE_NAME,
+                    Self::checksum_attribute_for(checksum)?,
+                ),
+            };
 
-            msg_input
-                .message_body(new_msg.marshall_json())
+            let result: Result<SendMessageOutput, SdkError<SendMessageError, Response>> = msg_input
+                .message_body(self.pointer_codec.encode(&new_msg))
                 .message_attributes(self.reserved_attributes[0].clone(), reserved_attribute)
                 .send()
-                .await
+                .await;
+
+            match result {
+                Ok(output) => {
+                    telemetry::record_message_sent(
+                        queue_url.as_deref(),
+                        Some(&new_msg.s3_bucket_name),
+                    );
+                    Ok(output)
+                }
+                Err(err) => {
+                    let leaked: Vec<S3Pointer> = self.roll_back_payloads([&new_msg]).await;
+                    return Err(SqsExtendedClientError::SqsSendMessage(err.into())
+                        .with_context(ErrorContext::for_leaked_payloads(leaked)));
+                }
+            }
         } else {
-            msg_input.send().await
+            let result: Result<SendMessageOutput, SdkError<SendMessageError, Response>> =
+                msg_input.send().await;
+
+            if result.is_ok() {
+                telemetry::record_message_sent(queue_url.as_deref(), None);
+            }
+
+            result
         };
 
-        result.map_err(SqsExtendedClientError::SqsSendMessage)
+        result.map_err(|err| SqsExtendedClientError::SqsSendMessage(err.into()))
     }
 
-    pub async fn receive_message(
+    async fn offload_and_send_message_batch(
         &self,
-        receive_message_builder: ReceiveMessageFluentBuilder,
-    ) -> Result<ReceiveMessageOutput, SqsExtendedClientError> {
-        let mut sqs_response: ReceiveMessageOutput = receive_message_builder
-            .message_attribute_names("All")
-            .send()
-            .await?;
-
-        let mut messages: Vec<Message> = match &sqs_response.messages {
-            None => return Ok(sqs_response),
-            Some(msgs) => msgs.clone(),
+        batch_input: SendMessageBatchFluentBuilder,
+    ) -> Result<SendMessageBatchOutput, SqsExtendedClientError> {
+        let Some(bn) = &self.bucket_name else {
+            return Err(SqsExtendedClientError::NoBucketName { context: None });
         };
+        let bucket_name: String = bn.to_string();
 
-        for msg in messages.iter_mut() {
-            let mut found: bool = false;
+        let queue_url: Option<String> = batch_input.get_queue_url().clone();
+        let mut entries: Vec<SendMessageBatchRequestEntry> =
+            batch_input.get_entries().clone().unwrap_or_default();
+        let mut offloaded: Vec<Option<S3Pointer>> = vec![None; entries.len()];
+
+        let threshold: usize = self.message_size_threshold_for(queue_url.as_deref()).await;
+
+        // Nothing was sent yet, so every payload uploaded so far is rolled back
+        // when a later one fails.
+        if let Err(err) = self
+            .offload_batch_entries(
+                &bucket_name,
+                &queue_url,
+                threshold,
+                &mut entries,
+                &mut offloaded,
+            )
+            .await
+        {
+            let leaked: Vec<S3Pointer> = self.roll_back_payloads(offloaded.iter().flatten()).await;
+            return Err(err.with_context(ErrorContext::for_leaked_payloads(leaked)));
+        }
 
-            for rsrvd_attr in self.reserved_attributes.iter() {
-                let msg_attrs: HashMap<String, MessageAttributeValue> =
-                    match &msg.message_attributes {
-                        None => break,
-                        Some(ma) => ma.clone(),
-                    };
+        let entry_ids: Vec<String> = entries.iter().map(|entry| entry.id.clone()).collect();
 
-                if msg_attrs.contains_key(rsrvd_attr.as_str()) {
-                    found = true;
-                    break;
-                }
-            }
+        let result: Result<SendMessageBatchOutput, SdkError<SendMessageBatchError, HttpResponse>> =
+            batch_input.set_entries(Some(entries)).send().await;
 
-            if !found {
-                continue;
-            }
+        // Count the entries SQS accepted and roll back the payloads of the ones
+        // it did not - all of them when the whole batch failed.
+        let mut unsent: Vec<&S3Pointer> = Vec::new();
 
-            let body: String = match &msg.body {
-                None => return Err(SqsExtendedClientError::NoMessageBody),
-                Some(b) => b.to_string(),
+        for (id, s3_pointer) in entry_ids.iter().zip(&offloaded) {
+            let failed: bool = match &result {
+                Err(_) => true,
+                Ok(output) => output.failed().iter().any(|entry| entry.id() == id),
             };
 
-            let receipt_handle: String = match &msg.receipt_handle {
-                None => return Err(SqsExtendedClientError::NoReceiptHandle),
-                Some(rh) => rh.to_string(),
-            };
+            if !failed {
+                telemetry::record_message_sent(
+                    queue_url.as_deref(),
+                    s3_pointer.as_ref().map(S3Pointer::bucket_name),
+                );
+            } else if let Some(s3_pointer) = s3_pointer {
+                unsent.push(s3_pointer);
+            }
+        }
 
-            let s3_pointer = S3Pointer::unmarshall_json(&body)?;
+        let leaked: Vec<S3Pointer> = self.roll_back_payloads(unsent).await;
 
-            let object: GetObjectOutput = self
-                .s3_client
-                .get_object()
-                .bucket(s3_pointer.s3_bucket_name.clone())
-                .key(s3_pointer.s3_key.clone())
-                .send()
-                .await?;
+        result.map_err(|err| {
+            SqsExtendedClientError::SqsSendMessageBatch(err.into())
+                .with_context(ErrorContext::for_leaked_payloads(leaked))
+        })
+    }
 
-            let bytes = object.body.collect().await?.into_bytes();
-            let response: &str = std::str::from_utf8(&bytes)?;
+    pub async fn send_json<T: Serialize>(
+        &self,
+        msg_input: SendMessageFluentBuilder,
+        value: &T,
+    ) -> Result<SendMessageOutput, SqsExtendedClientError> {
+        self.send_encoded_body(msg_input, JsonCodec::to_body(value), JsonCodec::NAME)
+            .await
+    }
 
-            msg.body = Some(response.to_string());
-            msg.receipt_handle = Some(Self::new_extended_receipt_handle(
-                s3_pointer.s3_bucket_name.clone(),
-                s3_pointer.s3_key.clone(),
-                receipt_handle,
-            ))
+    pub async fn send_encoded<T, C: Codec<T>>(
+        &self,
+        msg_input: SendMessageFluentBuilder,
+        value: &T,
+        codec: &C,
+    ) -> Result<SendMessageOutput, SqsExtendedClientError> {
+        self.send_encoded_body(msg_input, codec.encode(value), codec.name())
+            .await
+    }
+
+    pub async fn receive_json<T: DeserializeOwned>(
+        &self,
+        receive_message_builder: ReceiveMessageFluentBuilder,
+    ) -> Result<Vec<TypedMessage<T>>, SqsExtendedClientError> {
+        self.receive_decoded_payloads(
+            receive_message_builder,
+            "receive_json",
+            JsonCodec::NAME,
+            JsonCodec::from_body,
+        )
+        .await
+    }
+
+    pub async fn receive_decoded<T, C: Codec<T>>(
+        &self,
+        receive_message_builder: ReceiveMessageFluentBuilder,
+        codec: &C,
+    ) -> Result<Vec<TypedMessage<T>>, SqsExtendedClientError> {
+        self.receive_decoded_payloads(
+            receive_message_builder,
+            "receive_decoded",
+            codec.name(),
+            |payload| codec.decode(payload),
+        )
+        .await
+    }
+
+    pub async fn receive_message(
+        &self,
+        receive_message_builder: ReceiveMessageFluentBuilder,
+    ) -> Result<Vec<ExtendedMessage>, SqsExtendedClientError> {
+        let context: ErrorContext = ErrorContext::new("receive_message")
+            .with_queue_url(receive_message_builder.get_queue_url().as_deref());
+        let span: Span = operation_span!(
+            "receive_message",
+            receive_message_builder.get_queue_url().as_deref()
+        );
+
+        let result: Result<Vec<ExtendedMessage>, SqsExtendedClientError> = self
+            .receive_and_resolve_messages(receive_message_builder)
+            .instrument(span.clone())
+            .await;
+
+        if let Ok(messages) = &result {
+            span.record("message_count", messages.len() as u64);
+            span.record(
+                "payload_bytes",
+                messages
+                    .iter()
+                    .map(|msg| msg.payload.len() as u64)
+                    .sum::<u64>(),
+            );
+        }
+
+        result.map_err(|err| err.with_context(context))
+    }
+
+    async fn receive_and_resolve_messages(
+        &self,
+        receive_message_builder: ReceiveMessageFluentBuilder,
+    ) -> Result<Vec<ExtendedMessage>, SqsExtendedClientError> {
+        let queue_url: Option<String> = receive_message_builder.get_queue_url().clone();
+
+        let sqs_response: ReceiveMessageOutput = receive_message_builder
+            .message_attribute_names("All")
+            .send()
+            .await?;
+
+        let Some(messages) = sqs_response.messages else {
+            return Ok(Vec::new());
+        };
+
+        let mut extended_messages: Vec<ExtendedMessage> = Vec::with_capacity(messages.len());
+
+        for msg in messages {
+            extended_messages.push(
+                self.resolve_queue_message(queue_url.as_deref(), msg)
+                    .await?,
+            );
         }
 
-        sqs_response.messages = Some(messages);
-        Ok(sqs_response)
+        Ok(extended_messages)
     }
 
     pub async fn delete_message(
         &self,
-        mut delete_message_builder: DeleteMessageFluentBuilder,
+        delete_message_builder: DeleteMessageFluentBuilder,
     ) -> Result<DeleteMessageOutput, SqsExtendedClientError> {
         let receipt_handle: String = match delete_message_builder.get_receipt_handle() {
-            None => return Err(SqsExtendedClientError::NoReceiptHandle),
+            None => {
+                return Err(SqsExtendedClientError::NoReceiptHandle { context: None }
+                    .with_context(
+                        ErrorContext::new("delete_message")
+                            .with_queue_url(delete_message_builder.get_queue_url().as_deref()),
+                    ));
+            }
             Some(rh) => rh.to_string(),
         };
 
         let (bucket, key, handle) = self.parse_extended_receipt_handle(receipt_handle.clone());
 
-        if !bucket.is_empty() && !key.is_empty() && !handle.is_empty() {
-            delete_message_builder =
-                delete_message_builder.set_receipt_handle(Some(handle.clone()));
+        if bucket.is_empty() || key.is_empty() || handle.is_empty() {
+            return self
+                .delete_message_and_payload(delete_message_builder, receipt_handle, None)
+                .await;
         }
 
-        let resp: DeleteMessageOutput = delete_message_builder.send().await?;
+        let s3_pointer: S3Pointer = S3Pointer {
+            s3_bucket_name: bucket,
+            s3_key: key,
+            class: self.pointer_class.clone(),
+            checksum: None,
+        };
 
-        if !bucket.is_empty() && !key.is_empty() {
-            self.s3_client
-                .delete_object()
-                .bucket(bucket)
-                .key(key)
-                .send()
-                .await?;
-        }
+        self.delete_message_and_payload(delete_message_builder, handle, Some(&s3_pointer))
+            .await
+    }
 
-        Ok(resp)
+    pub async fn delete_extended_message(
+        &self,
+        delete_message_builder: DeleteMessageFluentBuilder,
+        message: &ExtendedMessage,
+    ) -> Result<DeleteMessageOutput, SqsExtendedClientError> {
+        self.delete_message_and_payload(
+            delete_message_builder,
+            message.receipt_handle.clone(),
+            message.s3_pointer.as_ref(),
+        )
+        .await
     }
 
     pub async fn change_message_visibility(
         &self,
         mut change_message_visibility: ChangeMessageVisibilityFluentBuilder,
     ) -> Result<ChangeMessageVisibilityOutput, SqsExtendedClientError> {
+        let context: ErrorContext = ErrorContext::new("change_message_visibility")
+            .with_queue_url(change_message_visibility.get_queue_url().as_deref());
+
         let receipt_handle: String = match change_message_visibility.get_receipt_handle() {
-            None => return Err(SqsExtendedClientError::NoReceiptHandle),
+            None => {
+                return Err(
+                    SqsExtendedClientError::NoReceiptHandle { context: None }.with_context(context)
+                );
+            }
             Some(rh) => rh.to_string(),
         };
 
@@ -306,104 +829,889 @@ impl SqsExtendedClient {
                 change_message_visibility.set_receipt_handle(Some(handle.clone()));
         }
 
-            let resp: ChangeMessageVisibilityOutput = change_message_visibility.send().await?;
+        let span: Span = operation_span!(
+            "change_message_visibility",
+            change_message_visibility.get_queue_url().as_deref()
+        );
+
+        let resp: ChangeMessageVisibilityOutput = change_message_visibility
+            .send()
+            .instrument(span)
+            .await
+            .map_err(|err| SqsExtendedClientError::from(err).with_context(context))?;
 
         Ok(resp)
     }
 
-    fn message_exceeds_threshold(
+    pub async fn change_extended_message_visibility(
         &self,
-        body: &str,
-        attributes: &Option<HashMap<String, MessageAttributeValue>>,
-    ) -> bool {
-        self.message_size(body, attributes).total() > self.message_size_threshold
+        change_message_visibility: ChangeMessageVisibilityFluentBuilder,
+        message: &ExtendedMessage,
+    ) -> Result<ChangeMessageVisibilityOutput, SqsExtendedClientError> {
+        let context: ErrorContext =
+            ErrorContext::for_extended_message("change_message_visibility", message)
+                .with_queue_url(change_message_visibility.get_queue_url().as_deref());
+
+        let span: Span = operation_span!(
+            "change_message_visibility",
+            change_message_visibility.get_queue_url().as_deref()
+        );
+        span.record("message_id", message.message_id().unwrap_or_default());
+
+        let resp: ChangeMessageVisibilityOutput = change_message_visibility
+            .receipt_handle(message.receipt_handle.clone())
+            .send()
+            .instrument(span)
+            .await
+            .map_err(|err| SqsExtendedClientError::from(err).with_context(context))?;
+
+        Ok(resp)
     }
 
-    fn message_size(
+    // The shared path of `send_json` and `send_encoded`.
+    async fn send_encoded_body(
         &self,
-        body: &str,
-        attributes: &Option<HashMap<String, MessageAttributeValue>>,
-    ) -> MessageSize {
-        MessageSize {
-            body_size: body.len(),
-            attribute_size: self.attribute_size(attributes),
-        }
-    }
+        msg_input: SendMessageFluentBuilder,
+        body: Result<String, CodecError>,
+        codec_name: &str,
+    ) -> Result<SendMessageOutput, SqsExtendedClientError> {
+        let body: String = body?;
 
-    fn attribute_size(&self, attributes: &Option<HashMap<String, MessageAttributeValue>>) -> usize {
-        match attributes {
-            None => 0,
-            Some(hash_map) => self.calc_attribute_size(hash_map),
-        }
+        let codec_attribute: MessageAttributeValue = MessageAttributeValue::builder()
+            .data_type("String")
+            .string_value(codec_name)
+            .build()?;
+
+        self.send_message(
+            msg_input
+                .message_body(body)
+                .message_attributes(CODEC_ATTRIBUTE_NAME, codec_attribute),
+        )
+        .await
     }
 
-    fn calc_attribute_size(&self, attributes: &HashMap<String, MessageAttributeValue>) -> usize {
-        let mut sum: usize = 0;
-        for (k, v) in attributes {
-            sum += k.len();
+    // The shared path of `receive_json` and `receive_decoded`.
+    async fn receive_decoded_payloads<T>(
+        &self,
+        receive_message_builder: ReceiveMessageFluentBuilder,
+        operation: &str,
+        codec_name: &str,
+        decode: impl Fn(&str) -> Result<T, CodecError>,
+    ) -> Result<Vec<TypedMessage<T>>, SqsExtendedClientError> {
+        let messages: Vec<ExtendedMessage> = self.receive_message(receive_message_builder).await?;
+
+        let mut typed_messages: Vec<TypedMessage<T>> = Vec::with_capacity(messages.len());
 
-            match &v.binary_value {
-                None => {}
-                Some(blob) => {
-                    sum += blob.as_ref().len();
-                }
-            }
-            match &v.string_value {
-                None => {}
-                Some(string) => sum += string.len(),
+        for msg in messages {
+            let context: ErrorContext = ErrorContext::for_extended_message(operation, &msg);
+
+            if let Some(err) = Self::codec_mismatch(&msg, codec_name) {
+                return Err(err.with_context(context));
             }
-            sum += v.data_type.len();
+            let value: T = decode(msg.payload())
+                .map_err(|err| SqsExtendedClientError::from(err).with_context(context))?;
+            typed_messages.push(TypedMessage::new(msg, value));
         }
-        sum
+
+        Ok(typed_messages)
     }
 
-    fn s3_key(&self, filename: String) -> String {
-        if !self.object_prefix.is_empty() {
-            return format!("{}/{}", self.object_prefix, filename);
+    // Messages without a codec attribute were sent by a producer that does not
+    // record one, so the caller's codec is trusted.
+    fn codec_mismatch(msg: &ExtendedMessage, expected: &str) -> Option<SqsExtendedClientError> {
+        match msg.codec_name() {
+            Some(found) if found != expected => {
+                Some(SqsExtendedClientError::PayloadCodecMismatch {
+                    expected: expected.to_string(),
+                    found: found.to_string(),
+                    context: None,
+                })
+            }
+            _ => None,
         }
-        filename
     }
 
-    fn new_extended_receipt_handle(bucket: String, key: String, handle: String) -> String {
-        let s3_bucket_name_marker: String = "-..s3BucketName..-".to_string();
-        let s3_key_marker: String = "-..s3Key..-".to_string();
+    async fn offload_payload(
+        &self,
+        bucket_name: String,
+        payload: &str,
+        key_context: &KeyContext<'_>,
+    ) -> Result<S3Pointer, SqsExtendedClientError> {
+        let s3_pointer: S3Pointer = self.new_s3_pointer(bucket_name, payload, key_context);
+
+        let reference_counted: bool = self.payload_cleanup == PayloadCleanup::ReferenceCounted;
+        let directory_bucket: bool = is_directory_bucket(&s3_pointer.s3_bucket_name);
+
+        if reference_counted && directory_bucket {
+            return Err(SqsExtendedClientError::DirectoryBucketUnsupported {
+                operation: "object tagging".to_string(),
+                context: None,
+            });
+        }
 
-        format!(
-            "{}{}{}{}{}{}{}",
-            s3_bucket_name_marker,
-            bucket,
-            s3_bucket_name_marker,
-            s3_key_marker,
-            key,
-            s3_key_marker,
-            handle
-        )
-        .to_string()
-    }
+        let context = || ErrorContext::for_s3_pointer(&s3_pointer);
 
-    fn parse_extended_receipt_handle(
-        &self,
-        extended_receipt_handle: String,
-    ) -> (String, String, String) {
-        let caps = match self
-            .extended_receipt_handler_regex
-            .captures(&extended_receipt_handle)
+        if self.content_addressed_keys
+            && self
+                .payload_exists(&s3_pointer)
+                .await
+                .map_err(|err| err.with_context(context()))?
         {
-            Some(caps) => caps,
-            None => return ("".to_string(), "".to_string(), "".to_string()), // TODO these should be options - not default values 
-        };
-
-        if caps.len() != 4 {
-            return ("".to_string(), "".to_string(), "".to_string()); // TODO these should be options - not default values 
+            if reference_counted {
+                self.adjust_reference_count(&s3_pointer, 1)
+                    .await
+                    .map_err(|err| err.with_context(context()))?;
+            }
+            return Ok(s3_pointer);
         }
 
-        let bucket = caps
-            .get(1)
-            .map(|m| m.as_str().to_string())
-            .unwrap_or_default();
-        let key = caps
-            .get(2)
-            .map(|m| m.as_str().to_string())
+        // a request body is consumed by sending it, so each attempt builds its
+        // own request
+        let put_object = || {
+            let mut put_object: PutObjectFluentBuilder = self
+                .s3_client_for(&s3_pointer.s3_bucket_name)
+                .put_object()
+                .bucket(&s3_pointer.s3_bucket_name)
+                .key(&s3_pointer.s3_key)
+                .body(ByteStream::from(payload.as_bytes().to_vec()));
+
+            if reference_counted {
+                put_object = put_object.tagging(format!("{}=1", REFERENCE_COUNT_TAG));
+            }
+
+            if !directory_bucket {
+                put_object = put_object.set_storage_class(self.storage_class.clone());
+            }
+
+            if let (Some(algorithm), Some(checksum)) = (self.payload_checksum, &s3_pointer.checksum)
+            {
+                put_object = algorithm.apply_to(put_object, checksum);
+            }
+
+            put_object
+        };
+
+        let span: Span = s3_transfer_span!("s3_put_object", &s3_pointer, None::<&str>);
+        span.record("payload_bytes", payload.len() as u64);
+
+        telemetry::timed(
+            span,
+            "PutObject",
+            key_context.queue_url(),
+            &s3_pointer.s3_bucket_name,
+            self.with_s3_retries("PutObject", || async {
+                let s3_result: Result<PutObjectOutput, SdkError<PutObjectError, HttpResponse>> =
+                    put_object().send().await;
+
+                s3_result.map_err(|err| SqsExtendedClientError::S3Upload(err.into()))
+            }),
+        )
+        .await
+        .map_err(|err| err.with_context(context()))?;
+
+        telemetry::record_payload_transfer(
+            "upload",
+            key_context.queue_url(),
+            &s3_pointer.s3_bucket_name,
+            payload.len(),
+        );
+
+        Ok(s3_pointer)
+    }
+
+    fn new_s3_pointer(
+        &self,
+        bucket_name: String,
+        payload: &str,
+        key_context: &KeyContext<'_>,
+    ) -> S3Pointer {
+        S3Pointer {
+            s3_key: self.s3_key(if self.content_addressed_keys {
+                sha256_hex(payload.as_bytes())
+            } else {
+                self.key_strategy.object_name(key_context)
+            }),
+            s3_bucket_name: self
+                .bucket_router
+                .as_ref()
+                .and_then(|router| router.bucket_name(key_context))
+                .unwrap_or(bucket_name),
+            class: self.pointer_class.clone(),
+            checksum: self
+                .payload_checksum
+                .map(|algorithm| algorithm.compute(payload.as_bytes())),
+        }
+    }
+
+    // Clients for other regions are derived from the builder's client, so they
+    // share its credentials and settings.
+    fn s3_client_for(&self, bucket_name: &str) -> aws_sdk_s3::Client {
+        let Some(region) = self
+            .bucket_router
+            .as_ref()
+            .and_then(|router| router.region(bucket_name))
+        else {
+            return self.s3_client.clone();
+        };
+
+        if self.s3_client.config().region().map(Region::as_ref) == Some(region.as_str()) {
+            return self.s3_client.clone();
+        }
+
+        let mut regional_s3_clients = self
+            .regional_s3_clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        regional_s3_clients
+            .entry(region.clone())
+            .or_insert_with(|| {
+                aws_sdk_s3::Client::from_conf(
+                    self.s3_client
+                        .config()
+                        .to_builder()
+                        .region(Region::new(region))
+                        .build(),
+                )
+            })
+            .clone()
+    }
+
+    async fn payload_exists(&self, s3_pointer: &S3Pointer) -> Result<bool, SqsExtendedClientError> {
+        self.with_s3_retries("HeadObject", || async {
+            let head_result: Result<HeadObjectOutput, SdkError<HeadObjectError, HttpResponse>> =
+                self.s3_client_for(&s3_pointer.s3_bucket_name)
+                    .head_object()
+                    .bucket(&s3_pointer.s3_bucket_name)
+                    .key(&s3_pointer.s3_key)
+                    .send()
+                    .await;
+
+            match head_result {
+                Ok(_) => Ok(true),
+                Err(err) if err.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+                Err(err) => Err(SqsExtendedClientError::S3HeadObject(err.into())),
+            }
+        })
+        .await
+    }
+
+    // Reference counts are read-modify-write on the object tags, so concurrent
+    // senders and consumers of the same payload can race - the count is a best
+    // effort guard, pair it with a lifecycle rule as a backstop.
+    async fn adjust_reference_count(
+        &self,
+        s3_pointer: &S3Pointer,
+        delta: i64,
+    ) -> Result<i64, SqsExtendedClientError> {
+        if is_directory_bucket(&s3_pointer.s3_bucket_name) {
+            return Err(SqsExtendedClientError::DirectoryBucketUnsupported {
+                operation: "object tagging".to_string(),
+                context: None,
+            });
+        }
+
+        let tagging: GetObjectTaggingOutput = self
+            .with_s3_retries("GetObjectTagging", || async {
+                Ok(self
+                    .s3_client_for(&s3_pointer.s3_bucket_name)
+                    .get_object_tagging()
+                    .bucket(&s3_pointer.s3_bucket_name)
+                    .key(&s3_pointer.s3_key)
+                    .send()
+                    .await?)
+            })
+            .await?;
+
+        let mut tags: Vec<Tag> = tagging.tag_set().to_vec();
+        let count: i64 = (reference_count(&tags) + delta).max(0);
+
+        tags.retain(|tag| tag.key() != REFERENCE_COUNT_TAG);
+        tags.push(
+            Tag::builder()
+                .key(REFERENCE_COUNT_TAG)
+                .value(count.to_string())
+                .build()?,
+        );
+
+        let tagging: Tagging = Tagging::builder().set_tag_set(Some(tags)).build()?;
+
+        self.with_s3_retries("PutObjectTagging", || async {
+            self.s3_client_for(&s3_pointer.s3_bucket_name)
+                .put_object_tagging()
+                .bucket(&s3_pointer.s3_bucket_name)
+                .key(&s3_pointer.s3_key)
+                .tagging(tagging.clone())
+                .send()
+                .await?;
+            Ok(())
+        })
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn release_payload(&self, s3_pointer: &S3Pointer) -> Result<(), SqsExtendedClientError> {
+        match self.payload_cleanup {
+            PayloadCleanup::RetentionOnly => return Ok(()),
+            PayloadCleanup::ReferenceCounted => {
+                if self.adjust_reference_count(s3_pointer, -1).await? > 0 {
+                    return Ok(());
+                }
+            }
+            PayloadCleanup::DeleteOnAck => {}
+        }
+
+        self.delete_payload(s3_pointer).await
+    }
+
+    async fn delete_payload(&self, s3_pointer: &S3Pointer) -> Result<(), SqsExtendedClientError> {
+        telemetry::timed(
+            s3_transfer_span!("s3_delete_object", s3_pointer, None::<&str>),
+            "DeleteObject",
+            None,
+            &s3_pointer.s3_bucket_name,
+            self.with_s3_retries("DeleteObject", || async {
+                self.s3_client_for(&s3_pointer.s3_bucket_name)
+                    .delete_object()
+                    .bucket(s3_pointer.s3_bucket_name.clone())
+                    .key(s3_pointer.s3_key.clone())
+                    .send()
+                    .await?;
+                Ok(())
+            }),
+        )
+        .await
+    }
+
+    // Undoes `offload_payload` for a message SQS did not accept. An object under
+    // a fresh key was never seen by a consumer and is deleted outright, but a
+    // content addressed object may back other messages, so it is released.
+    async fn roll_back_payload(
+        &self,
+        s3_pointer: &S3Pointer,
+    ) -> Result<(), SqsExtendedClientError> {
+        if self.content_addressed_keys {
+            return self.release_payload(s3_pointer).await;
+        }
+
+        self.delete_payload(s3_pointer).await
+    }
+
+    // Rolls back the payloads of messages that were not sent and returns the
+    // ones that could not be - they are logged, and left to
+    // `reconcile_payloads`.
+    pub(crate) async fn roll_back_payloads<'a>(
+        &self,
+        s3_pointers: impl IntoIterator<Item = &'a S3Pointer>,
+    ) -> Vec<S3Pointer> {
+        let mut leaked: Vec<S3Pointer> = Vec::new();
+
+        for s3_pointer in s3_pointers {
+            if let Err(err) = self.roll_back_payload(s3_pointer).await {
+                telemetry::warn_payload_rollback_failed(s3_pointer, &err);
+                leaked.push(s3_pointer.clone());
+            }
+        }
+
+        leaked
+    }
+
+    async fn offload_batch_entries(
+        &self,
+        bucket_name: &str,
+        queue_url: &Option<String>,
+        threshold: usize,
+        entries: &mut [SendMessageBatchRequestEntry],
+        offloaded: &mut [Option<S3Pointer>],
+    ) -> Result<(), SqsExtendedClientError> {
+        for (i, entry) in entries.iter_mut().enumerate() {
+            if self.always_through_s3
+                || self.message_exceeds_threshold(
+                    &entry.message_body,
+                    entry.message_attributes.as_ref(),
+                    threshold,
+                )
+            {
+                offloaded[i] = Some(
+                    self.offload_batch_entry(bucket_name, queue_url, threshold, entry)
+                        .await?,
+                );
+            }
+        }
+
+        // Entries that fit on their own can still push the whole batch over the
+        // batch threshold - offload the largest remaining ones until it fits.
+        while self.batch_size(entries) > self.batch_message_size_threshold {
+            let largest: Option<usize> = entries
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| offloaded[*i].is_none())
+                .max_by_key(|(_, entry)| {
+                    message_size(&entry.message_body, entry.message_attributes.as_ref())
+                })
+                .map(|(i, _)| i);
+
+            let Some(i) = largest else {
+                break;
+            };
+
+            offloaded[i] = Some(
+                self.offload_batch_entry(bucket_name, queue_url, threshold, &mut entries[i])
+                    .await?,
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn offload_batch_entry(
+        &self,
+        bucket_name: &str,
+        queue_url: &Option<String>,
+        threshold: usize,
+        entry: &mut SendMessageBatchRequestEntry,
+    ) -> Result<S3Pointer, SqsExtendedClientError> {
+        let key_context: KeyContext = KeyContext::new(
+            queue_url.as_deref(),
+            entry.message_group_id.as_deref(),
+            entry.message_attributes.as_ref(),
+            SystemTime::now(),
+        );
+
+        let mut message_attributes: HashMap<String, MessageAttributeValue> =
+            entry.message_attributes.clone().unwrap_or_default();
+        let offloaded_attributes: HashMap<String, MessageAttributeValue> = self
+            .take_offloaded_attributes(
+                &mut message_attributes,
+                threshold,
+                bucket_name,
+                &entry.message_body,
+                &key_context,
+            );
+
+        let envelope: Option<String> = (!offloaded_attributes.is_empty()).then(|| {
+            AttributeEnvelope::new(&entry.message_body, &offloaded_attributes).marshall_json()
+        });
+
+        let s3_pointer: S3Pointer = self
+            .offload_payload(
+                bucket_name.to_string(),
+                envelope.as_deref().unwrap_or(&entry.message_body),
+                &key_context,
+            )
+            .await?;
+
+        let reserved_attribute: MessageAttributeValue =
+            Self::reserved_attribute_for(entry.message_body.len())?;
+
+        if let Some(deduplication_id) = self.fifo_deduplication_id(
+            queue_url,
+            &entry.message_deduplication_id,
+            &entry.message_body,
+        ) {
+            entry.message_deduplication_id = Some(deduplication_id);
+        }
+
+        if !offloaded_attributes.is_empty() {
+            message_attributes.insert(
+                ATTRIBUTES_ATTRIBUTE_NAME.to_string(),
+                Self::attributes_attribute_for(offloaded_attributes.len())?,
+            );
+            entry.message_attributes = Some(message_attributes);
+        }
+
+        entry.message_body = self.pointer_codec.encode(&s3_pointer);
+        entry
+            .message_attributes
+            .get_or_insert_with(HashMap::new)
+            .insert(self.reserved_attributes[0].clone(), reserved_attribute);
+
+        if let Some(checksum) = &s3_pointer.checksum {
+            entry
+                .message_attributes
+                .get_or_insert_with(HashMap::new)
+                .insert(
+                    CHECKSUM_ATTRIBUTE_NAME.to_string(),
+                    Self::checksum_attribute_for(checksum)?,
+                );
+        }
+
+        Ok(s3_pointer)
+    }
+
+    fn reserved_attribute_for(payload_size: usize) -> Result<MessageAttributeValue, BuildError> {
+        MessageAttributeValue::builder()
+            .data_type("Number")
+            .string_value(payload_size.to_string())
+            .build()
+    }
+
+    fn checksum_attribute_for(checksum: &str) -> Result<MessageAttributeValue, BuildError> {
+        MessageAttributeValue::builder()
+            .data_type("String")
+            .string_value(checksum)
+            .build()
+    }
+
+    // With content-based deduplication SQS hashes the body, which for an
+    // offloaded message is a pointer to a fresh S3 key - so with
+    // `payload_deduplication_ids` the UTF-8 bytes of the body the caller sent
+    // are hashed instead, as SQS would have. Attributes, including ones moved
+    // into the S3 object, are not part of the hash.
+    fn fifo_deduplication_id(
+        &self,
+        queue_url: &Option<String>,
+        deduplication_id: &Option<String>,
+        message_body: &str,
+    ) -> Option<String> {
+        if !self.payload_deduplication_ids {
+            return None;
+        }
+
+        let Some(url) = queue_url else {
+            return None;
+        };
+
+        if !url.ends_with(".fifo") || deduplication_id.is_some() {
+            return None;
+        }
+
+        Some(sha256_hex(message_body.as_bytes()))
+    }
+
+    fn batch_size(&self, entries: &[SendMessageBatchRequestEntry]) -> usize {
+        entries
+            .iter()
+            .map(|entry| message_size(&entry.message_body, entry.message_attributes.as_ref()))
+            .sum()
+    }
+
+    async fn resolve_message(
+        &self,
+        msg: Message,
+    ) -> Result<ExtendedMessage, SqsExtendedClientError> {
+        self.resolve_queue_message(None, msg).await
+    }
+
+    // `resolve_message` for a message received from a known queue, which its
+    // metrics are labelled with.
+    async fn resolve_queue_message(
+        &self,
+        queue_url: Option<&str>,
+        msg: Message,
+    ) -> Result<ExtendedMessage, SqsExtendedClientError> {
+        let unwrapped: Result<UnwrappedMessage, SqsExtendedClientError> = match &msg.body {
+            None => Err(SqsExtendedClientError::NoMessageBody { context: None }),
+            Some(body) => self.unwrap_message(&msg, body).map_err(|err| {
+                telemetry::record_pointer_parse_failure(queue_url);
+                err.into()
+            }),
+        };
+
+        let unwrapped: UnwrappedMessage =
+            unwrapped.map_err(|err| err.with_context(ErrorContext::for_message(&msg)))?;
+
+        let context: ErrorContext = match &unwrapped.s3_pointer {
+            Some(s3_pointer) => ErrorContext::for_message(&msg)
+                .with_s3_pointer(s3_pointer)
+                .with_payload_size(unwrapped.payload_size),
+            None => ErrorContext::for_message(&msg),
+        };
+
+        let result: Result<ExtendedMessage, SqsExtendedClientError> = self
+            .resolve_message_payload(queue_url, msg, unwrapped)
+            .await;
+
+        if let Ok(message) = &result {
+            telemetry::record_message_received(
+                queue_url,
+                message.s3_pointer().map(S3Pointer::bucket_name),
+            );
+        }
+
+        result.map_err(|err| err.with_context(context))
+    }
+
+    async fn resolve_message_payload(
+        &self,
+        queue_url: Option<&str>,
+        msg: Message,
+        unwrapped: UnwrappedMessage,
+    ) -> Result<ExtendedMessage, SqsExtendedClientError> {
+        let receipt_handle: String = match &msg.receipt_handle {
+            None => return Err(SqsExtendedClientError::NoReceiptHandle { context: None }),
+            Some(rh) => rh.to_string(),
+        };
+
+        let UnwrappedMessage {
+            body,
+            message_attributes,
+            s3_pointer,
+            payload_size,
+        } = unwrapped;
+
+        let Some(s3_pointer) = s3_pointer else {
+            return Ok(ExtendedMessage {
+                message: msg,
+                payload: body,
+                message_attributes,
+                s3_pointer: None,
+                payload_size: None,
+                receipt_handle,
+            });
+        };
+
+        let span: Span = s3_transfer_span!("s3_get_object", &s3_pointer, msg.message_id.as_deref());
+
+        // a download cut short is retried from the start
+        let bytes = telemetry::timed(
+            span.clone(),
+            "GetObject",
+            queue_url,
+            &s3_pointer.s3_bucket_name,
+            self.with_s3_retries("GetObject", || async {
+                let object: GetObjectOutput = self
+                    .s3_client_for(&s3_pointer.s3_bucket_name)
+                    .get_object()
+                    .bucket(s3_pointer.s3_bucket_name.clone())
+                    .key(s3_pointer.s3_key.clone())
+                    .send()
+                    .await?;
+
+                Ok(object.body.collect().await?)
+            }),
+        )
+        .await?
+        .into_bytes();
+
+        span.record("payload_bytes", bytes.len() as u64);
+        telemetry::record_payload_transfer(
+            "download",
+            queue_url,
+            &s3_pointer.s3_bucket_name,
+            bytes.len(),
+        );
+
+        if let Some(err) = s3_pointer
+            .checksum
+            .as_deref()
+            .and_then(|expected| checksum_mismatch(expected, &bytes))
+        {
+            return Err(err);
+        }
+
+        let payload: &str = std::str::from_utf8(&bytes)?;
+
+        // The marker only tells the receive path to unwrap the envelope - the
+        // caller sees the attributes as they were sent.
+        let (payload, message_attributes): (String, HashMap<String, MessageAttributeValue>) =
+            if message_attributes.contains_key(ATTRIBUTES_ATTRIBUTE_NAME) {
+                let (body, offloaded_attributes) =
+                    AttributeEnvelope::unmarshall_json(payload)?.into_parts();
+                let mut message_attributes = message_attributes;
+                message_attributes.remove(ATTRIBUTES_ATTRIBUTE_NAME);
+                message_attributes.extend(offloaded_attributes);
+                (body, message_attributes)
+            } else {
+                (payload.to_string(), message_attributes)
+            };
+
+        Ok(ExtendedMessage {
+            message: msg,
+            payload,
+            message_attributes,
+            s3_pointer: Some(s3_pointer),
+            payload_size,
+            receipt_handle,
+        })
+    }
+
+    // Unwraps the SNS envelope and decodes the S3 pointer, without downloading
+    // the payload.
+    fn unwrap_message(
+        &self,
+        msg: &Message,
+        body: &str,
+    ) -> Result<UnwrappedMessage, PointerCodecError> {
+        let (body, message_attributes): (String, HashMap<String, MessageAttributeValue>) =
+            match self.unwrap_sns_envelope(body) {
+                Some(envelope) => (envelope.message.clone(), envelope.message_attributes()),
+                None => (
+                    body.to_string(),
+                    msg.message_attributes.clone().unwrap_or_default(),
+                ),
+            };
+
+        let Some(payload_size) = self.reserved_attribute_value(&message_attributes) else {
+            return Ok(UnwrappedMessage {
+                body,
+                message_attributes,
+                s3_pointer: None,
+                payload_size: None,
+            });
+        };
+
+        let mut s3_pointer: S3Pointer = if self.pointer_codec.detect(&body) {
+            self.pointer_codec.decode(&body)?
+        } else {
+            S3Pointer::unmarshall_json(&body).map_err(PointerCodecError::Json)?
+        };
+
+        if s3_pointer.checksum.is_none() {
+            s3_pointer.checksum = message_attributes
+                .get(CHECKSUM_ATTRIBUTE_NAME)
+                .and_then(|value| value.string_value.clone());
+        }
+
+        Ok(UnwrappedMessage {
+            body,
+            message_attributes,
+            s3_pointer: Some(s3_pointer),
+            payload_size: payload_size.parse::<usize>().ok(),
+        })
+    }
+
+    fn unwrap_sns_envelope(&self, body: &str) -> Option<SnsEnvelope> {
+        if !self.unwrap_sns_envelopes {
+            return None;
+        }
+
+        SnsEnvelope::unmarshall_json(body)
+    }
+
+    fn reserved_attribute_value(
+        &self,
+        msg_attrs: &HashMap<String, MessageAttributeValue>,
+    ) -> Option<String> {
+        self.reserved_attributes
+            .iter()
+            .find_map(|rsrvd_attr| msg_attrs.get(rsrvd_attr.as_str()))
+            .map(|value| value.string_value.clone().unwrap_or_default())
+    }
+
+    // The pointer of an offloaded message and the payload size it records,
+    // without downloading the payload.
+    pub(crate) fn message_s3_pointer(
+        &self,
+        msg: &Message,
+    ) -> Result<Option<(S3Pointer, usize)>, PointerCodecError> {
+        let Some(body) = &msg.body else {
+            return Ok(None);
+        };
+
+        let unwrapped: UnwrappedMessage = self.unwrap_message(msg, body)?;
+
+        Ok(unwrapped
+            .s3_pointer
+            .map(|s3_pointer| (s3_pointer, unwrapped.payload_size.unwrap_or(0))))
+    }
+
+    async fn delete_message_and_payload(
+        &self,
+        delete_message_builder: DeleteMessageFluentBuilder,
+        receipt_handle: String,
+        s3_pointer: Option<&S3Pointer>,
+    ) -> Result<DeleteMessageOutput, SqsExtendedClientError> {
+        let context: ErrorContext = ErrorContext::new("delete_message")
+            .with_queue_url(delete_message_builder.get_queue_url().as_deref());
+        let span: Span = operation_span!(
+            "delete_message",
+            delete_message_builder.get_queue_url().as_deref()
+        );
+        span.record("offloaded", s3_pointer.is_some());
+
+        let resp: DeleteMessageOutput = delete_message_builder
+            .receipt_handle(receipt_handle)
+            .send()
+            .instrument(span.clone())
+            .await
+            .map_err(|err| SqsExtendedClientError::from(err).with_context(context.clone()))?;
+
+        if let Some(s3_pointer) = s3_pointer {
+            self.release_payload(s3_pointer)
+                .instrument(span)
+                .await
+                .map_err(|err| err.with_context(context.with_s3_pointer(s3_pointer)))?;
+        }
+
+        Ok(resp)
+    }
+
+    // Whether `send_message` offloads a message with this body and these
+    // attributes - producers can use it to check messages before sending.
+    pub async fn would_offload(
+        &self,
+        queue_url: Option<&str>,
+        body: &str,
+        attributes: Option<&HashMap<String, MessageAttributeValue>>,
+    ) -> Result<bool, SqsExtendedClientError> {
+        let threshold: usize = self.message_size_threshold_for(queue_url).await;
+
+        Ok(self.always_through_s3 || self.message_exceeds_threshold(body, attributes, threshold))
+    }
+
+    fn message_exceeds_threshold(
+        &self,
+        body: &str,
+        attributes: Option<&HashMap<String, MessageAttributeValue>>,
+        threshold: usize,
+    ) -> bool {
+        message_size(body, attributes) > threshold
+    }
+
+    fn default_message_size_threshold(&self) -> usize {
+        self.message_size_threshold
+            .unwrap_or(MAX_MESSAGE_SIZE_IN_BYTES)
+    }
+
+    fn s3_key(&self, filename: String) -> String {
+        if !self.object_prefix.is_empty() {
+            return format!("{}/{}", self.object_prefix, filename);
+        }
+        filename
+    }
+
+    fn new_extended_receipt_handle(bucket: String, key: String, handle: String) -> String {
+        let s3_bucket_name_marker: String = "-..s3BucketName..-".to_string();
+        let s3_key_marker: String = "-..s3Key..-".to_string();
+
+        format!(
+            "{}{}{}{}{}{}{}",
+            s3_bucket_name_marker,
+            bucket,
+            s3_bucket_name_marker,
+            s3_key_marker,
+            key,
+            s3_key_marker,
+            handle
+        )
+        .to_string()
+    }
+
+    pub fn parse_extended_receipt_handle(
+        &self,
+        extended_receipt_handle: String,
+    ) -> (String, String, String) {
+        let caps = match self
+            .extended_receipt_handler_regex
+            .captures(&extended_receipt_handle)
+        {
+            Some(caps) => caps,
+            None => return ("".to_string(), "".to_string(), "".to_string()), // TODO these should be options - not default values
+        };
+
+        if caps.len() != 4 {
+            return ("".to_string(), "".to_string(), "".to_string()); // TODO these should be options - not default values 
+        }
+
+        let bucket = caps
+            .get(1)
+            .map(|m| m.as_str().to_string())
+            .unwrap_or_default();
+        let key = caps
+            .get(2)
+            .map(|m| m.as_str().to_string())
             .unwrap_or_default();
         let receipt_handle = caps
             .get(3)
@@ -414,8 +1722,26 @@ impl SqsExtendedClient {
     }
 }
 
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn reference_count(tags: &[Tag]) -> i64 {
+    tags.iter()
+        .find(|tag| tag.key() == REFERENCE_COUNT_TAG)
+        .and_then(|tag| tag.value().parse::<i64>().ok())
+        .unwrap_or(0)
+}
+
 //-S3 POINTER-------------------------------------------------------------------
 
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum S3PointerJson {
+    Array(S3PointerArray),
+    Object(S3PointerBucketAndKeyObject),
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct S3PointerArray(String, S3PointerBucketAndKeyObject);
 
@@ -427,44 +1753,216 @@ struct S3PointerBucketAndKeyObject {
     s3_key: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct S3Pointer {
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct S3Pointer {
     s3_bucket_name: String,
     s3_key: String,
     class: String,
+    #[serde(default)]
+    checksum: Option<String>,
 }
 
 impl S3Pointer {
-    fn marshall_json(self) -> String {
-        format!(
-            "[\"{}\",{{\"s3BucketName\":\"{}\",\"s3Key\":\"{}\"}}]",
-            self.class, self.s3_bucket_name, self.s3_key
-        )
+    pub fn new(bucket_name: String, key: String, class: String) -> S3Pointer {
+        S3Pointer {
+            s3_bucket_name: bucket_name,
+            s3_key: key,
+            class,
+            checksum: None,
+        }
     }
 
-    fn unmarshall_json(input: &str) -> SerdeJsonResult<S3Pointer> {
-        let wrapper: S3PointerArray = serde_json::from_str(input)?;
+    // The `<algorithm>:<base64 digest>` of the payload - see PayloadChecksum.
+    pub fn checksum(&self) -> Option<&str> {
+        self.checksum.as_deref()
+    }
 
-        let s3_pointer: S3Pointer = S3Pointer {
-            s3_bucket_name: wrapper.1.s3_bucket_name,
-            s3_key: wrapper.1.s3_key,
-            class: wrapper.0,
+    // For PointerCodecs that carry the checksum in the pointer itself.
+    pub fn with_checksum(mut self, checksum: String) -> S3Pointer {
+        self.checksum = Some(checksum);
+        self
+    }
+
+    pub fn bucket_name(&self) -> &str {
+        &self.s3_bucket_name
+    }
+
+    pub fn key(&self) -> &str {
+        &self.s3_key
+    }
+
+    pub fn class(&self) -> &str {
+        &self.class
+    }
+
+    // Keys derived from attribute values may hold any character, so the
+    // pointer is serialized rather than formatted.
+    fn marshall_json(self, pointer_format: PointerFormat) -> String {
+        let bucket_and_key: serde_json::Value = serde_json::json!({
+            "s3BucketName": self.s3_bucket_name,
+            "s3Key": self.s3_key,
+        });
+
+        match pointer_format {
+            PointerFormat::JavaArray => serde_json::json!([self.class, bucket_and_key]).to_string(),
+            PointerFormat::JsonObject => bucket_and_key.to_string(),
+        }
+    }
+
+    // Accepts every PointerFormat. The object format carries no pointer class,
+    // so `class` is left empty.
+    fn unmarshall_json(input: &str) -> SerdeJsonResult<S3Pointer> {
+        let s3_pointer: S3Pointer = match serde_json::from_str(input)? {
+            S3PointerJson::Array(wrapper) => S3Pointer {
+                s3_bucket_name: wrapper.1.s3_bucket_name,
+                s3_key: wrapper.1.s3_key,
+                class: wrapper.0,
+                checksum: None,
+            },
+            S3PointerJson::Object(object) => S3Pointer {
+                s3_bucket_name: object.s3_bucket_name,
+                s3_key: object.s3_key,
+                class: "".to_string(),
+                checksum: None,
+            },
         };
 
         Ok(s3_pointer)
     }
 }
 
-//-MESSAGE SIZE-----------------------------------------------------------------
+//-SNS ENVELOPE-----------------------------------------------------------------
+
+#[derive(Deserialize, Debug)]
+struct SnsEnvelope {
+    #[serde(rename = "Type")]
+    envelope_type: String,
+    #[serde(rename = "Message")]
+    message: String,
+    #[serde(rename = "MessageAttributes", default)]
+    message_attributes: HashMap<String, SnsEnvelopeAttribute>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SnsEnvelopeAttribute {
+    #[serde(rename = "Type")]
+    data_type: String,
+    #[serde(rename = "Value")]
+    value: String,
+}
+
+impl SnsEnvelope {
+    fn unmarshall_json(input: &str) -> Option<SnsEnvelope> {
+        let envelope: SnsEnvelope = serde_json::from_str(input).ok()?;
+
+        if envelope.envelope_type != "Notification" {
+            return None;
+        }
+
+        Some(envelope)
+    }
+
+    // SNS renders binary attribute values as base64.
+    fn message_attributes(&self) -> HashMap<String, MessageAttributeValue> {
+        self.message_attributes
+            .iter()
+            .filter_map(|(name, attr)| {
+                let builder = MessageAttributeValue::builder().data_type(attr.data_type.clone());
+
+                let builder = if attr.data_type.starts_with("Binary") {
+                    builder.binary_value(Blob::new(BASE64.decode(&attr.value).ok()?))
+                } else {
+                    builder.string_value(attr.value.clone())
+                };
+
+                builder.build().ok().map(|value| (name.clone(), value))
+            })
+            .collect()
+    }
+}
+
+//-UNWRAPPED MESSAGE------------------------------------------------------------
+
+// A received message's body and attributes - those of the SNS notification it
+// may hold - and the S3 pointer of an offloaded payload.
+pub(crate) struct UnwrappedMessage {
+    body: String,
+    message_attributes: HashMap<String, MessageAttributeValue>,
+    s3_pointer: Option<S3Pointer>,
+    payload_size: Option<usize>,
+}
+
+//-EXTENDED MESSAGE-------------------------------------------------------------
 
-struct MessageSize {
-    body_size: usize,
-    attribute_size: usize,
+#[derive(Debug, Clone)]
+pub struct ExtendedMessage {
+    message: Message,
+    payload: String,
+    message_attributes: HashMap<String, MessageAttributeValue>,
+    s3_pointer: Option<S3Pointer>,
+    payload_size: Option<usize>,
+    receipt_handle: String,
 }
 
-impl MessageSize {
-    fn total(self) -> usize {
-        self.body_size + self.attribute_size
+impl ExtendedMessage {
+    // The message exactly as SQS returned it - for offloaded messages the body
+    // is the S3 pointer JSON.
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    pub fn message_id(&self) -> Option<&str> {
+        self.message.message_id.as_deref()
+    }
+
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+
+    // The SQS message attributes - or, for an unwrapped SNS notification, the
+    // attributes published to SNS.
+    pub fn message_attributes(&self) -> &HashMap<String, MessageAttributeValue> {
+        &self.message_attributes
+    }
+
+    pub fn codec_name(&self) -> Option<&str> {
+        self.message_attributes
+            .get(CODEC_ATTRIBUTE_NAME)?
+            .string_value
+            .as_deref()
+    }
+
+    pub fn into_payload(self) -> String {
+        self.payload
+    }
+
+    pub fn s3_pointer(&self) -> Option<&S3Pointer> {
+        self.s3_pointer.as_ref()
+    }
+
+    pub fn is_offloaded(&self) -> bool {
+        self.s3_pointer.is_some()
+    }
+
+    pub fn payload_size(&self) -> Option<usize> {
+        self.payload_size
+    }
+
+    pub fn receipt_handle(&self) -> &str {
+        &self.receipt_handle
+    }
+
+    // The receipt handle in the format accepted by `delete_message` and
+    // `change_message_visibility`.
+    pub fn extended_receipt_handle(&self) -> String {
+        match &self.s3_pointer {
+            None => self.receipt_handle.clone(),
+            Some(s3_pointer) => SqsExtendedClient::new_extended_receipt_handle(
+                s3_pointer.s3_bucket_name.clone(),
+                s3_pointer.s3_key.clone(),
+                self.receipt_handle.clone(),
+            ),
+        }
     }
 }
 
@@ -472,20 +1970,79 @@ impl MessageSize {
 
 #[derive(Debug)]
 pub enum SqsExtendedClientError {
-    S3Upload(SdkError<PutObjectError, HttpResponse>),
-    S3Download(SdkError<GetObjectError, Response>),
-    S3DeleteObject(SdkError<DeleteObjectError, Response>),
-    S3DownloadToBytes(ByteStreamError),
-    S3DownloadToUtf8(Utf8Error),
-    SqsSendMessage(SdkError<SendMessageError, HttpResponse>),
-    SqsReceiveMessage(SdkError<ReceiveMessageError, HttpResponse>),
-    SqsDeleteMessage(SdkError<DeleteMessageError, Response>),
-    SqsChangeMessageVisibility(SdkError<ChangeMessageVisibilityError, Response>),
-    SqsBuildMessageAttribute(BuildError),
-    SqsReceiveMessageUnMarshallMessageBody(serde_json::Error),
-    NoBucketName,
-    NoMessageBody,
-    NoReceiptHandle,
+    S3Upload(Contextual<SdkError<PutObjectError, HttpResponse>>),
+    S3Download(Contextual<SdkError<GetObjectError, Response>>),
+    S3DeleteObject(Contextual<SdkError<DeleteObjectError, Response>>),
+    S3DeleteObjects(Contextual<SdkError<DeleteObjectsError, HttpResponse>>),
+    S3ListObjects(Contextual<SdkError<ListObjectsV2Error, HttpResponse>>),
+    S3HeadObject(Contextual<SdkError<HeadObjectError, HttpResponse>>),
+    S3GetObjectTagging(Contextual<SdkError<GetObjectTaggingError, HttpResponse>>),
+    S3PutObjectTagging(Contextual<SdkError<PutObjectTaggingError, HttpResponse>>),
+    S3CopyObject(Contextual<SdkError<CopyObjectError, HttpResponse>>),
+    S3DownloadToBytes(Contextual<ByteStreamError>),
+    S3DownloadToUtf8(Contextual<Utf8Error>),
+    S3RetriesExhausted {
+        operation: String,
+        attempts: u32,
+        source: Box<SqsExtendedClientError>,
+        context: Option<Box<ErrorContext>>,
+    },
+    SqsSendMessage(Contextual<SdkError<SendMessageError, HttpResponse>>),
+    SqsSendMessageBatch(Contextual<SdkError<SendMessageBatchError, HttpResponse>>),
+    SqsGetQueueAttributes(Contextual<SdkError<GetQueueAttributesError, HttpResponse>>),
+    SqsReceiveMessage(Contextual<SdkError<ReceiveMessageError, HttpResponse>>),
+    SqsDeleteMessage(Contextual<SdkError<DeleteMessageError, Response>>),
+    SqsDeleteMessageBatch(Contextual<SdkError<DeleteMessageBatchError, HttpResponse>>),
+    SqsPurgeQueue(Contextual<SdkError<PurgeQueueError, HttpResponse>>),
+    SqsChangeMessageVisibility(Contextual<SdkError<ChangeMessageVisibilityError, Response>>),
+    SqsChangeMessageVisibilityBatch(
+        Contextual<SdkError<ChangeMessageVisibilityBatchError, HttpResponse>>,
+    ),
+    SqsBuildMessageAttribute(Contextual<BuildError>),
+    SqsReceiveMessageUnMarshallMessageBody(Contextual<serde_json::Error>),
+    PointerCodec(Contextual<PointerCodecError>),
+    #[cfg(feature = "sns")]
+    SnsPublish(Contextual<SdkError<aws_sdk_sns::operation::publish::PublishError, HttpResponse>>),
+    #[cfg(feature = "sns")]
+    SnsPublishBatch(
+        Contextual<
+            SdkError<aws_sdk_sns::operation::publish_batch::PublishBatchError, HttpResponse>,
+        >,
+    ),
+    #[cfg(feature = "sns")]
+    SnsJsonMessageStructure {
+        context: Option<Box<ErrorContext>>,
+    },
+    PayloadCodec(Contextual<CodecError>),
+    PayloadCodecMismatch {
+        expected: String,
+        found: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    ChecksumMismatch {
+        expected: String,
+        found: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    DirectoryBucketUnsupported {
+        operation: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    ReconcileContentAddressedKeys {
+        context: Option<Box<ErrorContext>>,
+    },
+    ReconcileWithoutObjectPrefix {
+        context: Option<Box<ErrorContext>>,
+    },
+    NoBucketName {
+        context: Option<Box<ErrorContext>>,
+    },
+    NoMessageBody {
+        context: Option<Box<ErrorContext>>,
+    },
+    NoReceiptHandle {
+        context: Option<Box<ErrorContext>>,
+    },
 }
 
 impl fmt::Display for SqsExtendedClientError {
@@ -494,89 +2051,227 @@ impl fmt::Display for SqsExtendedClientError {
             Self::S3Upload(err) => write!(f, "S3 upload failed: {}", err),
             Self::S3Download(err) => write!(f, "S3 download failed: {}", err),
             Self::S3DeleteObject(err) => write!(f, "S3 delete failed: {}", err),
+            Self::S3DeleteObjects(err) => write!(f, "S3 delete objects failed: {}", err),
+            Self::S3ListObjects(err) => write!(f, "S3 list objects failed: {}", err),
+            Self::S3HeadObject(err) => write!(f, "S3 head object failed: {}", err),
+            Self::S3GetObjectTagging(err) => write!(f, "S3 get object tagging failed: {}", err),
+            Self::S3PutObjectTagging(err) => write!(f, "S3 put object tagging failed: {}", err),
+            Self::S3CopyObject(err) => write!(f, "S3 copy object failed: {}", err),
             Self::S3DownloadToBytes(err) => write!(f, "S3 Byte Stream Error: {}", err),
             Self::S3DownloadToUtf8(err) => write!(f, "S3 Byte Stream Error: {}", err),
+            Self::S3RetriesExhausted {
+                operation,
+                attempts,
+                source,
+                ..
+            } => write!(
+                f,
+                "S3 {} failed after {} attempts: {}",
+                operation, attempts, source
+            ),
             Self::SqsSendMessage(err) => write!(f, "SQS operation failed: {}", err),
+            Self::SqsSendMessageBatch(err) => write!(f, "SQS send batch failed: {}", err),
+            Self::SqsGetQueueAttributes(err) => {
+                write!(f, "SQS get queue attributes failed: {}", err)
+            }
             Self::SqsReceiveMessage(err) => write!(f, "SQS operation failed: {}", err),
             Self::SqsDeleteMessage(err) => write!(f, "SQS delete failed: {}", err),
+            Self::SqsDeleteMessageBatch(err) => write!(f, "SQS delete batch failed: {}", err),
+            Self::SqsPurgeQueue(err) => write!(f, "SQS purge queue failed: {}", err),
             Self::SqsChangeMessageVisibility(err) => {
                 write!(f, "SQS change message visibilty failed: {}", err)
             }
+            Self::SqsChangeMessageVisibilityBatch(err) => {
+                write!(f, "SQS change message visibility batch failed: {}", err)
+            }
             Self::SqsBuildMessageAttribute(err) => {
                 write!(f, "SQS build message attribute failed: {}", err)
             }
             Self::SqsReceiveMessageUnMarshallMessageBody(err) => {
                 write!(f, "Failed to marshall sqs message body: {}", err)
             }
-            Self::NoBucketName => write!(f, "No bucket name configured"),
-            Self::NoMessageBody => write!(f, "No message body"),
-            Self::NoReceiptHandle => write!(f, "No receipt handle"),
+            Self::PointerCodec(err) => write!(f, "Pointer codec failed: {}", err),
+            #[cfg(feature = "sns")]
+            Self::SnsPublish(err) => write!(f, "SNS publish failed: {}", err),
+            #[cfg(feature = "sns")]
+            Self::SnsPublishBatch(err) => write!(f, "SNS publish batch failed: {}", err),
+            #[cfg(feature = "sns")]
+            Self::SnsJsonMessageStructure { .. } => {
+                write!(
+                    f,
+                    "SNS messages with a json message structure cannot be offloaded"
+                )
+            }
+            Self::PayloadCodec(err) => write!(f, "Payload codec failed: {}", err),
+            Self::PayloadCodecMismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "Payload was encoded with the {} codec, expected {}",
+                found, expected
+            ),
+            Self::ChecksumMismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "Payload checksum mismatch: expected {}, found {}",
+                expected, found
+            ),
+            Self::DirectoryBucketUnsupported { operation, .. } => {
+                write!(f, "S3 directory buckets do not support {}", operation)
+            }
+            Self::ReconcileContentAddressedKeys { .. } => {
+                write!(f, "Content addressed payloads cannot be reconciled by age")
+            }
+            Self::ReconcileWithoutObjectPrefix { .. } => {
+                write!(
+                    f,
+                    "Reconciling without an object prefix scans the whole bucket"
+                )
+            }
+            Self::NoBucketName { .. } => write!(f, "No bucket name configured"),
+            Self::NoMessageBody { .. } => write!(f, "No message body"),
+            Self::NoReceiptHandle { .. } => write!(f, "No receipt handle"),
+        }?;
+
+        // the context of the failure, as far as it is known
+        match self.context() {
+            Some(context) if *context != ErrorContext::default() => write!(f, " ({})", context),
+            _ => Ok(()),
         }
     }
 }
 
 impl From<aws_sdk_s3::error::BuildError> for SqsExtendedClientError {
     fn from(err: aws_sdk_s3::error::BuildError) -> Self {
-        Self::SqsBuildMessageAttribute(err)
+        Self::SqsBuildMessageAttribute(err.into())
     }
 }
 
 impl From<Utf8Error> for SqsExtendedClientError {
     fn from(err: Utf8Error) -> Self {
-        Self::S3DownloadToUtf8(err)
+        Self::S3DownloadToUtf8(err.into())
     }
 }
 
 impl From<ByteStreamError> for SqsExtendedClientError {
     fn from(err: ByteStreamError) -> Self {
-        Self::S3DownloadToBytes(err)
+        Self::S3DownloadToBytes(err.into())
     }
 }
 
 impl From<SdkError<GetObjectError, Response>> for SqsExtendedClientError {
     fn from(err: SdkError<GetObjectError, Response>) -> Self {
-        Self::S3Download(err)
+        Self::S3Download(err.into())
     }
 }
 
 impl From<SdkError<DeleteObjectError, Response>> for SqsExtendedClientError {
     fn from(err: SdkError<DeleteObjectError, Response>) -> Self {
-        Self::S3DeleteObject(err)
+        Self::S3DeleteObject(err.into())
+    }
+}
+
+impl From<SdkError<GetObjectTaggingError, HttpResponse>> for SqsExtendedClientError {
+    fn from(err: SdkError<GetObjectTaggingError, HttpResponse>) -> Self {
+        Self::S3GetObjectTagging(err.into())
+    }
+}
+
+impl From<SdkError<PutObjectTaggingError, HttpResponse>> for SqsExtendedClientError {
+    fn from(err: SdkError<PutObjectTaggingError, HttpResponse>) -> Self {
+        Self::S3PutObjectTagging(err.into())
+    }
+}
+
+impl From<SdkError<CopyObjectError, HttpResponse>> for SqsExtendedClientError {
+    fn from(err: SdkError<CopyObjectError, HttpResponse>) -> Self {
+        Self::S3CopyObject(err.into())
     }
 }
 
 impl From<SdkError<ReceiveMessageError, HttpResponse>> for SqsExtendedClientError {
     fn from(err: SdkError<ReceiveMessageError, HttpResponse>) -> Self {
-        Self::SqsReceiveMessage(err)
+        Self::SqsReceiveMessage(err.into())
     }
 }
 
 impl From<SdkError<DeleteMessageError, Response>> for SqsExtendedClientError {
     fn from(err: SdkError<DeleteMessageError, Response>) -> Self {
-        Self::SqsDeleteMessage(err)
+        Self::SqsDeleteMessage(err.into())
     }
 }
 
 impl From<SdkError<ChangeMessageVisibilityError, Response>> for SqsExtendedClientError {
     fn from(err: SdkError<ChangeMessageVisibilityError, Response>) -> Self {
-        Self::SqsChangeMessageVisibility(err)
+        Self::SqsChangeMessageVisibility(err.into())
     }
 }
 
 impl From<serde_json::Error> for SqsExtendedClientError {
     fn from(err: serde_json::Error) -> Self {
-        Self::SqsReceiveMessageUnMarshallMessageBody(err)
+        Self::SqsReceiveMessageUnMarshallMessageBody(err.into())
+    }
+}
+
+impl From<PointerCodecError> for SqsExtendedClientError {
+    fn from(err: PointerCodecError) -> Self {
+        Self::PointerCodec(err.into())
     }
 }
 
-impl std::error::Error for SqsExtendedClientError {}
+impl From<CodecError> for SqsExtendedClientError {
+    fn from(err: CodecError) -> Self {
+        Self::PayloadCodec(err.into())
+    }
+}
+
+impl std::error::Error for SqsExtendedClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::S3Upload(err) => Some(err.error()),
+            Self::S3Download(err) => Some(err.error()),
+            Self::S3DeleteObject(err) => Some(err.error()),
+            Self::S3DeleteObjects(err) => Some(err.error()),
+            Self::S3ListObjects(err) => Some(err.error()),
+            Self::S3HeadObject(err) => Some(err.error()),
+            Self::S3GetObjectTagging(err) => Some(err.error()),
+            Self::S3PutObjectTagging(err) => Some(err.error()),
+            Self::S3CopyObject(err) => Some(err.error()),
+            Self::S3DownloadToBytes(err) => Some(err.error()),
+            Self::S3DownloadToUtf8(err) => Some(err.error()),
+            Self::S3RetriesExhausted { source, .. } => Some(source.as_ref()),
+            Self::SqsSendMessage(err) => Some(err.error()),
+            Self::SqsSendMessageBatch(err) => Some(err.error()),
+            Self::SqsGetQueueAttributes(err) => Some(err.error()),
+            Self::SqsReceiveMessage(err) => Some(err.error()),
+            Self::SqsDeleteMessage(err) => Some(err.error()),
+            Self::SqsDeleteMessageBatch(err) => Some(err.error()),
+            Self::SqsPurgeQueue(err) => Some(err.error()),
+            Self::SqsChangeMessageVisibility(err) => Some(err.error()),
+            Self::SqsChangeMessageVisibilityBatch(err) => Some(err.error()),
+            Self::SqsBuildMessageAttribute(err) => Some(err.error()),
+            Self::SqsReceiveMessageUnMarshallMessageBody(err) => Some(err.error()),
+            Self::PointerCodec(err) => Some(err.error()),
+            #[cfg(feature = "sns")]
+            Self::SnsPublish(err) => Some(err.error()),
+            #[cfg(feature = "sns")]
+            Self::SnsPublishBatch(err) => Some(err.error()),
+            Self::PayloadCodec(err) => Some(err.error()),
+            _ => None,
+        }
+    }
+}
 
 //-TESTS------------------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
     use aws_config::BehaviorVersion;
-    use aws_smithy_types::Blob;
+    use aws_smithy_http_client::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_runtime_api::client::orchestrator::HttpRequest;
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::body::SdkBody;
 
     use super::*;
 
@@ -590,11 +2285,45 @@ mod tests {
         )
     }
 
-    fn make_test_s3_client() -> aws_sdk_s3::client::Client {
+    pub(crate) fn make_test_s3_client() -> aws_sdk_s3::client::Client {
+        aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .credentials_provider(make_test_credentials())
+                .build(),
+        )
+    }
+
+    // Answers requests in order and records them, so tests can check what the
+    // AWS clients actually sent.
+    pub(crate) fn make_replay_event(response_body: &str) -> ReplayEvent {
+        ReplayEvent::new(
+            HttpRequest::new(SdkBody::empty()),
+            HttpResponse::new(
+                StatusCode::try_from(200).expect("invalid status code"),
+                SdkBody::from(response_body),
+            ),
+        )
+    }
+
+    pub(crate) fn make_replay_s3_client(http_client: &StaticReplayClient) -> aws_sdk_s3::Client {
         aws_sdk_s3::Client::from_conf(
             aws_sdk_s3::Config::builder()
                 .behavior_version(BehaviorVersion::latest())
+                .region(Region::new("us-west-2"))
                 .credentials_provider(make_test_credentials())
+                .http_client(http_client.clone())
+                .build(),
+        )
+    }
+
+    pub(crate) fn make_replay_sqs_client(http_client: &StaticReplayClient) -> aws_sdk_sqs::Client {
+        aws_sdk_sqs::Client::from_conf(
+            aws_sdk_sqs::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .region(aws_sdk_sqs::config::Region::new("us-west-2"))
+                .credentials_provider(make_test_credentials())
+                .http_client(http_client.clone())
                 .build(),
         )
     }
@@ -618,24 +2347,44 @@ mod tests {
                 .with_always_through_s3(true)
                 .with_reserved_attribute_names(vec!["attr_one".to_string(), "attr_two".to_string()])
                 .with_pointer_class("pointer-class".to_string())
+                .with_pointer_format(PointerFormat::JsonObject)
+                .with_payload_checksum(PayloadChecksum::Sha256)
                 .with_object_prefix("object-prefix".to_string())
                 .build();
 
         let bucket_name: String = sqs_extended_client.bucket_name.unwrap_or_default();
         assert_eq!("bucket-name", bucket_name);
-        assert_eq!(9999, sqs_extended_client.message_size_threshold);
+        assert_eq!(Some(9999), sqs_extended_client.message_size_threshold);
+        assert_eq!(1000, sqs_extended_client.batch_message_size_threshold);
         assert!(sqs_extended_client.always_through_s3);
         assert_eq!(
             vec!["attr_one".to_string(), "attr_two".to_string()],
             sqs_extended_client.reserved_attributes
         );
         assert_eq!("pointer-class", sqs_extended_client.pointer_class);
+        assert_eq!(
+            r#"{"s3BucketName":"BUCKET","s3Key":"KEY"}"#,
+            sqs_extended_client.pointer_codec.encode(&S3Pointer::new(
+                "BUCKET".to_string(),
+                "KEY".to_string(),
+                "pointer-class".to_string()
+            ))
+        );
         assert_eq!("object-prefix", sqs_extended_client.object_prefix);
+        assert!(!sqs_extended_client.content_addressed_keys);
+        assert_eq!(
+            Some(PayloadChecksum::Sha256),
+            sqs_extended_client.payload_checksum
+        );
+        assert_eq!(
+            PayloadCleanup::DeleteOnAck,
+            sqs_extended_client.payload_cleanup
+        );
     }
 
     #[tokio::test]
     async fn test_send_message_no_bucket() {
-        let sqs_extended_client: SqsExtendedClient = 
+        let sqs_extended_client: SqsExtendedClient =
             SqsExtendedClientBuilder::new(make_test_s3_client()).build();
 
         let msg: SendMessageFluentBuilder = make_test_sqs_client()
@@ -643,21 +2392,187 @@ mod tests {
             .queue_url("queue_url")
             .message_body("hello world");
 
-            let result = sqs_extended_client.send_message(msg).await;
-            assert!(matches!(result, Err(SqsExtendedClientError::NoBucketName)));
+        let result = sqs_extended_client.send_message(msg).await;
+        assert!(matches!(
+            result,
+            Err(SqsExtendedClientError::NoBucketName { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_message_no_message_body() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_s3_bucket_name("bucket_name".to_string())
+                .build();
+
+        let msg: SendMessageFluentBuilder =
+            make_test_sqs_client().send_message().queue_url("queue_url");
+
+        let result = sqs_extended_client.send_message(msg).await;
+        assert!(matches!(
+            result,
+            Err(SqsExtendedClientError::NoMessageBody { .. })
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_send_message_rolls_back_payload() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(""), make_replay_event("")]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![ReplayEvent::new(
+            HttpRequest::new(SdkBody::empty()),
+            HttpResponse::new(
+                StatusCode::try_from(400).expect("invalid status code"),
+                SdkBody::from(
+                    r#"{"__type":"com.amazonaws.sqs#QueueDoesNotExist","message":"no queue"}"#,
+                ),
+            ),
+        )]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("bucket-name".to_string())
+                .with_always_through_s3(true)
+                .build();
+
+        let msg: SendMessageFluentBuilder = make_replay_sqs_client(&sqs_http_client)
+            .send_message()
+            .queue_url("https://sqs.us-west-2.amazonaws.com/123456789012/missing")
+            .message_body("hello world");
+
+        let result = sqs_extended_client.send_message(msg).await;
+        assert!(matches!(
+            result,
+            Err(SqsExtendedClientError::SqsSendMessage(_))
+        ));
+
+        let err: SqsExtendedClientError = result.expect_err("send should fail");
+        let context: &ErrorContext = err.context().expect("no context");
+        assert_eq!(Some("send_message"), context.operation());
+        assert_eq!(
+            Some("https://sqs.us-west-2.amazonaws.com/123456789012/missing"),
+            context.queue_url()
+        );
+
+        let requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+
+        assert_eq!(2, requests.len());
+        assert_eq!("PUT", requests[0].method());
+        assert_eq!("DELETE", requests[1].method());
+        assert_eq!(
+            requests[0].uri().split_once('?').map(|(path, _)| path),
+            requests[1].uri().split_once('?').map(|(path, _)| path)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_message_reports_leaked_payload() {
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(""),
+            ReplayEvent::new(
+                HttpRequest::new(SdkBody::empty()),
+                HttpResponse::new(
+                    StatusCode::try_from(403).expect("invalid status code"),
+                    SdkBody::from(
+                        "<Error><Code>AccessDenied</Code><Message>denied</Message></Error>",
+                    ),
+                ),
+            ),
+        ]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![ReplayEvent::new(
+            HttpRequest::new(SdkBody::empty()),
+            HttpResponse::new(
+                StatusCode::try_from(400).expect("invalid status code"),
+                SdkBody::from(
+                    r#"{"__type":"com.amazonaws.sqs#QueueDoesNotExist","message":"no queue"}"#,
+                ),
+            ),
+        )]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("bucket-name".to_string())
+                .with_always_through_s3(true)
+                .build();
+
+        let err: SqsExtendedClientError = sqs_extended_client
+            .send_message(
+                make_replay_sqs_client(&sqs_http_client)
+                    .send_message()
+                    .queue_url("https://sqs.us-west-2.amazonaws.com/123456789012/missing")
+                    .message_body("hello world"),
+            )
+            .await
+            .expect_err("send should fail");
+
+        assert!(matches!(err, SqsExtendedClientError::SqsSendMessage(_)));
+
+        let context: &ErrorContext = err.context().expect("no context");
+
+        assert_eq!(Some("send_message"), context.operation());
+        assert_eq!(1, context.leaked_payloads().len());
+        assert_eq!("bucket-name", context.leaked_payloads()[0].bucket_name());
+        assert!(err.to_string().contains("leaked payloads: bucket-name/"));
+        assert_eq!(2, s3_http_client.actual_requests().count());
     }
 
     #[tokio::test]
-    async fn test_send_message_no_message_body() {
-        let sqs_extended_client: SqsExtendedClient = 
-            SqsExtendedClientBuilder::new(make_test_s3_client()).with_s3_bucket_name("bucket_name".to_string()).build();
+    async fn test_send_message_batch_rolls_back_uploads() {
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(""),
+            ReplayEvent::new(
+                HttpRequest::new(SdkBody::empty()),
+                HttpResponse::new(
+                    StatusCode::try_from(403).expect("invalid status code"),
+                    SdkBody::from(
+                        "<Error><Code>AccessDenied</Code><Message>denied</Message></Error>",
+                    ),
+                ),
+            ),
+            make_replay_event(""),
+        ]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![]);
 
-        let msg: SendMessageFluentBuilder = make_test_sqs_client()
-            .send_message()
-            .queue_url("queue_url");
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("bucket-name".to_string())
+                .with_always_through_s3(true)
+                .build();
 
-        let result = sqs_extended_client.send_message(msg).await;
-        assert!(matches!(result, Err(SqsExtendedClientError::NoMessageBody)))
+        let entries: Vec<SendMessageBatchRequestEntry> = ["1", "2"]
+            .into_iter()
+            .map(|id| {
+                SendMessageBatchRequestEntry::builder()
+                    .id(id)
+                    .message_body(format!("message {}", id))
+                    .build()
+                    .expect("Failed to build SendMessageBatchRequestEntry")
+            })
+            .collect();
+
+        let result = sqs_extended_client
+            .send_message_batch(
+                make_replay_sqs_client(&sqs_http_client)
+                    .send_message_batch()
+                    .queue_url("https://sqs.us-west-2.amazonaws.com/123456789012/queue")
+                    .set_entries(Some(entries)),
+            )
+            .await;
+
+        assert!(matches!(result, Err(SqsExtendedClientError::S3Upload(_))));
+        assert_eq!(0, sqs_http_client.actual_requests().count());
+
+        let requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+
+        assert_eq!(3, requests.len());
+        assert_eq!("PUT", requests[0].method());
+        assert_eq!("PUT", requests[1].method());
+        assert_eq!("DELETE", requests[2].method());
+        assert_eq!(
+            requests[0].uri().split_once('?').map(|(path, _)| path),
+            requests[2].uri().split_once('?').map(|(path, _)| path)
+        );
     }
 
     #[test]
@@ -668,10 +2583,7 @@ mod tests {
         let bucket_name: String = sqs_extended_client.bucket_name.unwrap_or_default();
 
         assert_eq!("", bucket_name);
-        assert_eq!(
-            MAX_MESSAGE_SIZE_IN_BYTES,
-            sqs_extended_client.message_size_threshold
-        );
+        assert_eq!(None, sqs_extended_client.message_size_threshold);
         assert!(!sqs_extended_client.always_through_s3);
         assert_eq!(
             vec![
@@ -681,6 +2593,14 @@ mod tests {
             sqs_extended_client.reserved_attributes
         );
         assert_eq!(DEFAULT_POINTER_CLASS, sqs_extended_client.pointer_class);
+        assert_eq!(
+            r#"["software.amazon.payloadoffloading.PayloadS3Pointer",{"s3BucketName":"BUCKET","s3Key":"KEY"}]"#,
+            sqs_extended_client.pointer_codec.encode(&S3Pointer::new(
+                "BUCKET".to_string(),
+                "KEY".to_string(),
+                DEFAULT_POINTER_CLASS.to_string()
+            ))
+        );
         assert_eq!("", sqs_extended_client.object_prefix);
     }
 
@@ -698,82 +2618,82 @@ mod tests {
 
         let msg_body: &String = msg.get_message_body().as_ref().unwrap();
 
-        let result: bool = sqs_extended_client_small_msg_size_threshold
-            .message_exceeds_threshold(msg_body, msg.get_message_attributes());
+        let result: bool = sqs_extended_client_small_msg_size_threshold.message_exceeds_threshold(
+            msg_body,
+            msg.get_message_attributes().as_ref(),
+            sqs_extended_client_small_msg_size_threshold.default_message_size_threshold(),
+        );
 
         assert!(result);
 
         let sqs_extended_client_default: SqsExtendedClient =
             SqsExtendedClientBuilder::new(make_test_s3_client()).build();
 
-        let result_2: bool = sqs_extended_client_default
-            .message_exceeds_threshold(msg_body, msg.get_message_attributes());
+        let result_2: bool = sqs_extended_client_default.message_exceeds_threshold(
+            msg_body,
+            msg.get_message_attributes().as_ref(),
+            sqs_extended_client_default.default_message_size_threshold(),
+        );
 
         assert!(!result_2);
     }
 
     #[test]
-    fn test_message_size() {
+    fn test_message_exceeds_threshold_boundary() {
         let sqs_extended_client: SqsExtendedClient =
             SqsExtendedClientBuilder::new(make_test_s3_client()).build();
 
-        let msg: SendMessageFluentBuilder = make_test_sqs_client()
-            .send_message()
-            .queue_url("queue_url")
-            .message_body("hello world");
-
-        let msg_body: &String = msg.get_message_body().as_ref().unwrap();
-
-        let result: MessageSize =
-            sqs_extended_client.message_size(msg_body, msg.get_message_attributes());
-
-        assert!(result.total() == 11);
-
-        let attribute_value_number: MessageAttributeValue = MessageAttributeValue::builder()
-            .data_type("Number")
-            .string_value("12")
-            .build()
-            .expect("Failed to build MessageAttributeValue");
-
-        let attribute_value_binary: MessageAttributeValue = MessageAttributeValue::builder()
-            .data_type("Binary")
-            .binary_value(Blob::new("IT'S BINARY HONEST"))
-            .build()
-            .expect("Failed to build MessageAttributeValue");
-
-        let msg_with_attributes: SendMessageFluentBuilder = make_test_sqs_client()
-            .send_message()
-            .queue_url("queue_url")
-            .message_body("hello world")
-            .message_attributes("test_attribute_one", attribute_value_number)
-            .message_attributes("test_attribute_two", attribute_value_binary);
-
-        let msg_with_attributes_body: &String =
-            msg_with_attributes.get_message_body().as_ref().unwrap();
-
-        let result_2: MessageSize = sqs_extended_client.message_size(
-            msg_with_attributes_body,
-            msg_with_attributes.get_message_attributes(),
-        );
+        let threshold: usize = 1024;
+        let fits: String = "x".repeat(threshold);
 
-        assert!(result_2.total() == 79);
+        assert!(!sqs_extended_client.message_exceeds_threshold(&fits, None, threshold));
+        assert!(sqs_extended_client.message_exceeds_threshold(
+            &format!("{}x", fits),
+            None,
+            threshold
+        ));
     }
 
-    #[test]
-    fn test_calc_attribute_size() {
+    #[tokio::test]
+    async fn test_would_offload() {
         let sqs_extended_client: SqsExtendedClient =
-            SqsExtendedClientBuilder::new(make_test_s3_client()).build();
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_message_size_threshold(16)
+                .build();
 
-        let reserved_attribute = MessageAttributeValue::builder()
-            .data_type(String::from("String"))
-            .string_value(String::from("some string"))
-            .build()
-            .expect("build MessageAttrbuteValue should not fail");
+        let attributes: HashMap<String, MessageAttributeValue> = HashMap::from([(
+            "a".to_string(),
+            MessageAttributeValue::builder()
+                .data_type("String")
+                .string_value("b")
+                .build()
+                .expect("build MessageAttrbuteValue should not fail"),
+        )]);
+
+        assert!(
+            !sqs_extended_client
+                .would_offload(None, "hello world", None)
+                .await
+                .expect("would_offload failed")
+        );
+        assert!(
+            sqs_extended_client
+                .would_offload(None, "hello world", Some(&attributes))
+                .await
+                .expect("would_offload failed")
+        );
 
-        let mut hm: HashMap<String, MessageAttributeValue> = HashMap::new();
-        hm.insert(String::from("testing_strings"), reserved_attribute);
+        let always_s3_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_always_through_s3(true)
+                .build();
 
-        assert_eq!(32, sqs_extended_client.calc_attribute_size(&hm))
+        assert!(
+            always_s3_client
+                .would_offload(None, "", None)
+                .await
+                .expect("would_offload failed")
+        );
     }
 
     #[test]
@@ -837,14 +2757,39 @@ mod tests {
             s3_bucket_name: "BUCKET".to_string(),
             s3_key: "KEY".to_string(),
             class: sqs_extended_client.pointer_class.clone(),
+            checksum: None,
         };
 
-        let json_s3_pointer: String = s3_pointer.marshall_json();
+        let json_s3_pointer: String = s3_pointer.clone().marshall_json(PointerFormat::JavaArray);
 
         assert!(
             json_s3_pointer
                 == r#"["software.amazon.payloadoffloading.PayloadS3Pointer",{"s3BucketName":"BUCKET","s3Key":"KEY"}]"#
         );
+
+        assert_eq!(
+            r#"{"s3BucketName":"BUCKET","s3Key":"KEY"}"#,
+            s3_pointer.marshall_json(PointerFormat::JsonObject)
+        );
+    }
+
+    #[test]
+    fn test_marshall_json_escapes_key() {
+        let s3_pointer: S3Pointer = S3Pointer::new(
+            "BUCKET".to_string(),
+            r#"orders/say "hi"\now"#.to_string(),
+            "software.amazon.payloadoffloading.PayloadS3Pointer".to_string(),
+        );
+
+        for pointer_format in [PointerFormat::JavaArray, PointerFormat::JsonObject] {
+            let json_s3_pointer: String = s3_pointer.clone().marshall_json(pointer_format);
+
+            let decoded: S3Pointer =
+                S3Pointer::unmarshall_json(&json_s3_pointer).expect("s3_pointer unmarshall failed");
+
+            assert_eq!(r#"orders/say "hi"\now"#, decoded.key());
+            assert_eq!("BUCKET", decoded.bucket_name());
+        }
     }
 
     #[test]
@@ -864,4 +2809,413 @@ mod tests {
                     .clone()
         );
     }
+
+    #[test]
+    fn test_unmarshall_json_object() {
+        let s3_pointer_str: &str = r#"{"s3BucketName": "BUCKET", "s3Key": "KEY"}"#;
+
+        let s3_pointer_struct: S3Pointer =
+            S3Pointer::unmarshall_json(s3_pointer_str).expect("s3_pointer unmarshall failed");
+
+        assert_eq!("BUCKET", s3_pointer_struct.bucket_name());
+        assert_eq!("KEY", s3_pointer_struct.key());
+        assert_eq!("", s3_pointer_struct.class());
+
+        assert!(S3Pointer::unmarshall_json(r#"{"s3BucketName": "BUCKET"}"#).is_err());
+        assert!(S3Pointer::unmarshall_json("hello world").is_err());
+    }
+
+    #[test]
+    fn test_reserved_attribute_value() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client()).build();
+
+        let legacy_attribute: MessageAttributeValue = MessageAttributeValue::builder()
+            .data_type("Number")
+            .string_value("42")
+            .build()
+            .expect("Failed to build MessageAttributeValue");
+
+        let msg_attrs: HashMap<String, MessageAttributeValue> =
+            HashMap::from([(LEGACY_RESERVED_ATTRIBUTE_NAME.to_string(), legacy_attribute)]);
+
+        assert_eq!(
+            Some("42".to_string()),
+            sqs_extended_client.reserved_attribute_value(&msg_attrs)
+        );
+
+        assert_eq!(
+            None,
+            sqs_extended_client.reserved_attribute_value(&HashMap::new())
+        );
+    }
+
+    #[test]
+    fn test_extended_message_extended_receipt_handle() {
+        let offloaded: ExtendedMessage = ExtendedMessage {
+            message: Message::builder().receipt_handle("HANDLE").build(),
+            payload: "payload".to_string(),
+            message_attributes: HashMap::new(),
+            s3_pointer: Some(S3Pointer {
+                s3_bucket_name: "BUCKET".to_string(),
+                s3_key: "KEY".to_string(),
+                class: DEFAULT_POINTER_CLASS.to_string(),
+                checksum: None,
+            }),
+            payload_size: Some(7),
+            receipt_handle: "HANDLE".to_string(),
+        };
+
+        assert!(offloaded.is_offloaded());
+        assert_eq!(
+            "-..s3BucketName..-BUCKET-..s3BucketName..--..s3Key..-KEY-..s3Key..-HANDLE",
+            offloaded.extended_receipt_handle()
+        );
+
+        let inline: ExtendedMessage = ExtendedMessage {
+            message: Message::builder().receipt_handle("HANDLE").build(),
+            payload: "payload".to_string(),
+            message_attributes: HashMap::new(),
+            s3_pointer: None,
+            payload_size: None,
+            receipt_handle: "HANDLE".to_string(),
+        };
+
+        assert!(!inline.is_offloaded());
+        assert_eq!("HANDLE", inline.extended_receipt_handle());
+    }
+
+    #[test]
+    fn test_codec_mismatch() {
+        let codec_attribute: MessageAttributeValue = MessageAttributeValue::builder()
+            .data_type("String")
+            .string_value("msgpack")
+            .build()
+            .expect("Failed to build MessageAttributeValue");
+
+        let encoded: ExtendedMessage = ExtendedMessage {
+            message: Message::builder()
+                .message_attributes(CODEC_ATTRIBUTE_NAME, codec_attribute.clone())
+                .build(),
+            payload: "payload".to_string(),
+            message_attributes: HashMap::from([(
+                CODEC_ATTRIBUTE_NAME.to_string(),
+                codec_attribute,
+            )]),
+            s3_pointer: None,
+            payload_size: None,
+            receipt_handle: "HANDLE".to_string(),
+        };
+
+        assert_eq!(Some("msgpack"), encoded.codec_name());
+        assert!(SqsExtendedClient::codec_mismatch(&encoded, "msgpack").is_none());
+        assert!(matches!(
+            SqsExtendedClient::codec_mismatch(&encoded, "json"),
+            Some(SqsExtendedClientError::PayloadCodecMismatch { .. })
+        ));
+
+        let plain: ExtendedMessage = ExtendedMessage {
+            message: Message::builder().build(),
+            payload: "payload".to_string(),
+            message_attributes: HashMap::new(),
+            s3_pointer: None,
+            payload_size: None,
+            receipt_handle: "HANDLE".to_string(),
+        };
+
+        assert_eq!(None, plain.codec_name());
+        assert!(SqsExtendedClient::codec_mismatch(&plain, "json").is_none());
+    }
+
+    #[test]
+    fn test_fifo_deduplication_id() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_payload_deduplication_ids(true)
+                .build();
+
+        let fifo_queue: Option<String> = Some("https://sqs/123/queue.fifo".to_string());
+        let standard_queue: Option<String> = Some("https://sqs/123/queue".to_string());
+
+        let deduplication_id: Option<String> =
+            sqs_extended_client.fifo_deduplication_id(&fifo_queue, &None, "hello world");
+
+        assert_eq!(
+            Some("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9".to_string()),
+            deduplication_id
+        );
+
+        assert_eq!(
+            None,
+            sqs_extended_client.fifo_deduplication_id(
+                &fifo_queue,
+                &Some("explicit".to_string()),
+                "hello world"
+            )
+        );
+        assert_eq!(
+            None,
+            sqs_extended_client.fifo_deduplication_id(&standard_queue, &None, "hello world")
+        );
+        assert_eq!(
+            None,
+            sqs_extended_client.fifo_deduplication_id(&None, &None, "hello world")
+        );
+
+        // off by default
+        assert_eq!(
+            None,
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .build()
+                .fifo_deduplication_id(&fifo_queue, &None, "hello world")
+        );
+    }
+
+    #[test]
+    fn test_batch_size() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client()).build();
+
+        let entries: Vec<SendMessageBatchRequestEntry> = vec![
+            SendMessageBatchRequestEntry::builder()
+                .id("1")
+                .message_body("hello world")
+                .build()
+                .expect("Failed to build SendMessageBatchRequestEntry"),
+            SendMessageBatchRequestEntry::builder()
+                .id("2")
+                .message_body("hello")
+                .build()
+                .expect("Failed to build SendMessageBatchRequestEntry"),
+        ];
+
+        assert_eq!(16, sqs_extended_client.batch_size(&entries));
+    }
+
+    #[tokio::test]
+    async fn test_send_message_batch_no_bucket() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client()).build();
+
+        let batch: SendMessageBatchFluentBuilder = make_test_sqs_client()
+            .send_message_batch()
+            .queue_url("queue_url");
+
+        let result = sqs_extended_client.send_message_batch(batch).await;
+        assert!(matches!(
+            result,
+            Err(SqsExtendedClientError::NoBucketName { .. })
+        ));
+    }
+
+    #[test]
+    fn test_builder_content_addressed_keys_cleanup() {
+        let content_addressed: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_content_addressed_keys(true)
+                .build();
+
+        assert!(content_addressed.content_addressed_keys);
+        assert_eq!(
+            PayloadCleanup::ReferenceCounted,
+            content_addressed.payload_cleanup
+        );
+
+        let retention_only: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_content_addressed_keys(true)
+                .with_payload_cleanup(PayloadCleanup::RetentionOnly)
+                .build();
+
+        assert_eq!(
+            PayloadCleanup::RetentionOnly,
+            retention_only.payload_cleanup
+        );
+    }
+
+    #[test]
+    fn test_reference_count() {
+        let tags: Vec<Tag> = vec![
+            Tag::builder()
+                .key("team")
+                .value("payments")
+                .build()
+                .expect("Failed to build Tag"),
+            Tag::builder()
+                .key(REFERENCE_COUNT_TAG)
+                .value("3")
+                .build()
+                .expect("Failed to build Tag"),
+        ];
+
+        assert_eq!(3, reference_count(&tags));
+        assert_eq!(0, reference_count(&tags[..1]));
+    }
+
+    #[test]
+    fn test_builder_key_strategy() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_object_prefix("payloads".to_string())
+                .with_key_strategy(QueuePrefixKeyStrategy::new())
+                .build();
+
+        let key_context: KeyContext = KeyContext::new(
+            Some("https://sqs.eu-west-2.amazonaws.com/123456789012/orders"),
+            None,
+            None,
+            SystemTime::now(),
+        );
+
+        let s3_key: String =
+            sqs_extended_client.s3_key(sqs_extended_client.key_strategy.object_name(&key_context));
+
+        assert!(s3_key.starts_with("payloads/orders/"));
+    }
+
+    #[test]
+    fn test_sns_envelope_unmarshall_json() {
+        let notification: &str = r#"{
+            "Type": "Notification",
+            "MessageId": "22b80b92-fdea-4c2c-8f9d-bdfb0c7bf324",
+            "TopicArn": "arn:aws:sns:us-east-1:123456789012:topic",
+            "Message": "[\"software.amazon.payloadoffloading.PayloadS3Pointer\",{\"s3BucketName\":\"BUCKET\",\"s3Key\":\"KEY\"}]",
+            "Timestamp": "2025-09-17T10:11:12.000Z",
+            "MessageAttributes": {
+                "ExtendedPayloadSize": {"Type": "Number", "Value": "59"},
+                "Raw": {"Type": "Binary", "Value": "SVQnUyBCSU5BUlkgSE9ORVNU"}
+            }
+        }"#;
+
+        let envelope: SnsEnvelope =
+            SnsEnvelope::unmarshall_json(notification).expect("not an SNS envelope");
+
+        let s3_pointer: S3Pointer =
+            S3Pointer::unmarshall_json(&envelope.message).expect("s3_pointer unmarshall failed");
+
+        assert_eq!("BUCKET", s3_pointer.bucket_name());
+
+        let msg_attrs: HashMap<String, MessageAttributeValue> = envelope.message_attributes();
+
+        assert_eq!(
+            Some("59".to_string()),
+            msg_attrs["ExtendedPayloadSize"].string_value
+        );
+        assert_eq!(
+            Some(Blob::new("IT'S BINARY HONEST")),
+            msg_attrs["Raw"].binary_value
+        );
+
+        assert!(SnsEnvelope::unmarshall_json("hello world").is_none());
+        assert!(
+            SnsEnvelope::unmarshall_json(
+                r#"{"Type": "SubscriptionConfirmation", "Message": "hi"}"#
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_unwrap_sns_envelope_disabled_by_default() {
+        let notification: &str = r#"{"Type": "Notification", "Message": "hello world"}"#;
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client()).build();
+
+        assert!(
+            sqs_extended_client
+                .unwrap_sns_envelope(notification)
+                .is_none()
+        );
+
+        let unwrapping_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_sns_envelope_unwrapping(true)
+                .build();
+
+        assert_eq!(
+            "hello world",
+            unwrapping_client
+                .unwrap_sns_envelope(notification)
+                .expect("not an SNS envelope")
+                .message
+        );
+    }
+
+    #[test]
+    fn test_unwrap_message() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_sns_envelope_unwrapping(true)
+                .build();
+
+        let msg: Message = Message::builder()
+            .body(
+                r#"{"Type": "Notification", "Message": "[\"software.amazon.payloadoffloading.PayloadS3Pointer\",{\"s3BucketName\":\"payloads\",\"s3Key\":\"KEY\"}]", "MessageAttributes": {"ExtendedPayloadSize": {"Type": "Number", "Value": "300000"}, "ExtendedPayloadChecksum": {"Type": "String", "Value": "crc32c:AAAAAA=="}}}"#,
+            )
+            .build();
+
+        let unwrapped: UnwrappedMessage = sqs_extended_client
+            .unwrap_message(&msg, msg.body().expect("no body"))
+            .expect("unwrap failed");
+        let s3_pointer: &S3Pointer = unwrapped.s3_pointer.as_ref().expect("no pointer");
+
+        assert_eq!("payloads", s3_pointer.bucket_name());
+        assert_eq!("KEY", s3_pointer.key());
+        assert_eq!(Some("crc32c:AAAAAA=="), s3_pointer.checksum());
+        assert_eq!(Some(300000), unwrapped.payload_size);
+        assert!(
+            unwrapped
+                .message_attributes
+                .contains_key(CHECKSUM_ATTRIBUTE_NAME)
+        );
+
+        assert_eq!(
+            Some((s3_pointer.clone(), 300000)),
+            sqs_extended_client
+                .message_s3_pointer(&msg)
+                .expect("decode failed")
+        );
+        assert_eq!(
+            None,
+            sqs_extended_client
+                .message_s3_pointer(&Message::builder().body("hello world").build())
+                .expect("decode failed")
+        );
+    }
+
+    #[test]
+    fn test_s3_client_for() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_s3_bucket_name("bucket-name".to_string())
+                .with_bucket_router(
+                    RegionalBucketRouter::new()
+                        .with_bucket("eu-west-2".to_string(), "payloads-london".to_string()),
+                )
+                .build();
+
+        let london_client: aws_sdk_s3::Client =
+            sqs_extended_client.s3_client_for("payloads-london");
+
+        assert_eq!(
+            Some("eu-west-2"),
+            london_client.config().region().map(Region::as_ref)
+        );
+
+        sqs_extended_client.s3_client_for("payloads-london");
+
+        assert_eq!(
+            1,
+            sqs_extended_client
+                .regional_s3_clients
+                .lock()
+                .expect("lock poisoned")
+                .len()
+        );
+
+        let default_client: aws_sdk_s3::Client = sqs_extended_client.s3_client_for("bucket-name");
+
+        assert_eq!(None, default_client.config().region());
+    }
 }
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+
+use aws_sdk_sqs::operation::get_queue_attributes::GetQueueAttributesOutput;
+use aws_sdk_sqs::types::{MessageAttributeValue, QueueAttributeName};
+
+use crate::telemetry;
+use crate::{
+    ATTRIBUTES_ATTRIBUTE_NAME, CHECKSUM_ATTRIBUTE_NAME, MAX_MESSAGE_SIZE_IN_BYTES, S3Pointer,
+    SQS_MAX_MESSAGE_SIZE_IN_BYTES, SqsExtendedClient, SqsExtendedClientError,
+};
+
+//-MESSAGE SIZE-----------------------------------------------------------------
+
+// The size SQS checks against its message size limit: the UTF-8 bytes of the
+// body plus those of every message attribute. Message system attributes such as
+// AWSTraceHeader do not count towards the limit.
+pub fn message_size(
+    body: &str,
+    message_attributes: Option<&HashMap<String, MessageAttributeValue>>,
+) -> usize {
+    body.len() + message_attributes.map_or(0, message_attributes_size)
+}
+
+pub fn message_attributes_size(
+    message_attributes: &HashMap<String, MessageAttributeValue>,
+) -> usize {
+    message_attributes
+        .iter()
+        .map(|(name, value)| message_attribute_size(name, value))
+        .sum()
+}
+
+// An attribute counts its name, its data type (including any custom type
+// suffix) and its values - binary values as raw bytes, not base64.
+pub fn message_attribute_size(name: &str, value: &MessageAttributeValue) -> usize {
+    let string_size: usize = value.string_value.as_ref().map_or(0, String::len);
+    let binary_size: usize = value
+        .binary_value
+        .as_ref()
+        .map_or(0, |blob| blob.as_ref().len());
+    let string_list_size: usize = value
+        .string_list_values
+        .as_ref()
+        .map_or(0, |strings| strings.iter().map(String::len).sum());
+    let binary_list_size: usize = value.binary_list_values.as_ref().map_or(0, |blobs| {
+        blobs.iter().map(|blob| blob.as_ref().len()).sum()
+    });
+
+    name.len()
+        + value.data_type.len()
+        + string_size
+        + binary_size
+        + string_list_size
+        + binary_list_size
+}
+
+//-POINTER MESSAGE SIZE---------------------------------------------------------
+
+impl SqsExtendedClient {
+    // The size of an offloaded message as it is sent: the S3 pointer body, the
+    // attributes left on the message and the ones the client adds to it.
+    pub(crate) fn pointer_message_size(
+        &self,
+        s3_pointer: &S3Pointer,
+        payload_size: usize,
+        attributes: &HashMap<String, MessageAttributeValue>,
+        offloaded_attributes: usize,
+    ) -> usize {
+        let client_attributes: [(&str, Option<MessageAttributeValue>); 3] = [
+            (
+                &self.reserved_attributes[0],
+                Self::reserved_attribute_for(payload_size).ok(),
+            ),
+            (
+                CHECKSUM_ATTRIBUTE_NAME,
+                s3_pointer
+                    .checksum()
+                    .and_then(|checksum| Self::checksum_attribute_for(checksum).ok()),
+            ),
+            (
+                ATTRIBUTES_ATTRIBUTE_NAME,
+                (offloaded_attributes > 0)
+                    .then(|| Self::attributes_attribute_for(offloaded_attributes).ok())
+                    .flatten(),
+            ),
+        ];
+
+        self.pointer_codec.encode(s3_pointer).len()
+            + message_attributes_size(attributes)
+            + client_attributes
+                .iter()
+                .filter_map(|(name, value)| Some(message_attribute_size(name, value.as_ref()?)))
+                .sum::<usize>()
+    }
+}
+
+//-QUEUE SIZE LIMITS------------------------------------------------------------
+
+impl SqsExtendedClient {
+    // The override, else the queue's `MaximumMessageSize` when lookups are
+    // enabled, else the 256 KiB every queue accepts. A failed lookup falls back
+    // to the default rather than failing the send, and is retried on the next
+    // send to that queue.
+    pub(crate) async fn message_size_threshold_for(&self, queue_url: Option<&str>) -> usize {
+        let (None, Some(sqs_client), Some(queue_url)) =
+            (self.message_size_threshold, &self.sqs_client, queue_url)
+        else {
+            return self.default_message_size_threshold();
+        };
+
+        if let Some(limit) = self
+            .queue_size_limits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(queue_url)
+        {
+            return *limit;
+        }
+
+        let output: GetQueueAttributesOutput = match sqs_client
+            .get_queue_attributes()
+            .queue_url(queue_url)
+            .attribute_names(QueueAttributeName::MaximumMessageSize)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(err) => {
+                telemetry::warn_queue_size_lookup_failed(
+                    queue_url,
+                    &SqsExtendedClientError::SqsGetQueueAttributes(err.into()),
+                );
+                return self.default_message_size_threshold();
+            }
+        };
+
+        let limit: usize = output
+            .attributes()
+            .and_then(|attributes| attributes.get(&QueueAttributeName::MaximumMessageSize))
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(MAX_MESSAGE_SIZE_IN_BYTES)
+            .min(SQS_MAX_MESSAGE_SIZE_IN_BYTES);
+
+        self.queue_size_limits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(queue_url.to_string(), limit);
+
+        limit
+    }
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_sqs::operation::send_message::SendMessageOutput;
+    use aws_smithy_http_client::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::Blob;
+    use aws_smithy_types::body::SdkBody;
+
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::tests::{make_replay_event, make_replay_sqs_client, make_test_s3_client};
+    use crate::{KeyContext, PayloadChecksum, SqsExtendedClientBuilder};
+
+    static QUEUE_URL: &str = "https://sqs.eu-west-2.amazonaws.com/123456789012/orders";
+
+    fn make_maximum_message_size_client(
+        maximum_message_size: &str,
+    ) -> (StaticReplayClient, aws_sdk_sqs::Client) {
+        let http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(&format!(
+                r#"{{"Attributes":{{"MaximumMessageSize":"{}"}}}}"#,
+                maximum_message_size
+            ))]);
+        let sqs_client: aws_sdk_sqs::Client = make_replay_sqs_client(&http_client);
+
+        (http_client, sqs_client)
+    }
+
+    #[test]
+    fn test_message_attribute_size() {
+        let string_value: MessageAttributeValue = MessageAttributeValue::builder()
+            .data_type("String.Emoji")
+            .string_value("😊")
+            .build()
+            .expect("invalid attribute");
+
+        assert_eq!(5 + 12 + 4, message_attribute_size("emoji", &string_value));
+
+        let list_value: MessageAttributeValue = MessageAttributeValue::builder()
+            .data_type("Binary")
+            .binary_value(Blob::new(vec![0u8; 3]))
+            .string_list_values("ab")
+            .string_list_values("cd")
+            .binary_list_values(Blob::new(vec![0u8; 5]))
+            .build()
+            .expect("invalid attribute");
+
+        assert_eq!(
+            4 + 6 + 3 + 4 + 5,
+            message_attribute_size("list", &list_value)
+        );
+    }
+
+    #[test]
+    fn test_message_size() {
+        assert_eq!(4, message_size("😊", None));
+
+        let message_attributes: HashMap<String, MessageAttributeValue> = HashMap::from([(
+            "count".to_string(),
+            MessageAttributeValue::builder()
+                .data_type("Number")
+                .string_value("12")
+                .build()
+                .expect("invalid attribute"),
+        )]);
+
+        assert_eq!(
+            11 + 5 + 6 + 2,
+            message_size("hello world", Some(&message_attributes))
+        );
+    }
+
+    #[test]
+    fn test_pointer_message_size() {
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_payload_checksum(PayloadChecksum::Crc32c)
+                .build();
+
+        let s3_pointer: S3Pointer = sqs_extended_client.new_s3_pointer(
+            "payloads".to_string(),
+            "hello world",
+            &KeyContext::new(None, None, None, SystemTime::UNIX_EPOCH),
+        );
+        let attributes: HashMap<String, MessageAttributeValue> = HashMap::from([(
+            "Trace".to_string(),
+            SqsExtendedClient::checksum_attribute_for("abc").expect("invalid attribute"),
+        )]);
+
+        // the message as the send path builds it
+        let mut sent_attributes: HashMap<String, MessageAttributeValue> = attributes.clone();
+        sent_attributes.extend([
+            (
+                "ExtendedPayloadSize".to_string(),
+                SqsExtendedClient::reserved_attribute_for(300000).expect("invalid attribute"),
+            ),
+            (
+                CHECKSUM_ATTRIBUTE_NAME.to_string(),
+                SqsExtendedClient::checksum_attribute_for(
+                    s3_pointer.checksum().expect("no checksum"),
+                )
+                .expect("invalid attribute"),
+            ),
+            (
+                ATTRIBUTES_ATTRIBUTE_NAME.to_string(),
+                SqsExtendedClient::attributes_attribute_for(2).expect("invalid attribute"),
+            ),
+        ]);
+
+        assert_eq!(
+            message_size(
+                &sqs_extended_client.pointer_codec.encode(&s3_pointer),
+                Some(&sent_attributes)
+            ),
+            sqs_extended_client.pointer_message_size(&s3_pointer, 300000, &attributes, 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_message_size_threshold_for_queue() {
+        let (http_client, sqs_client) = make_maximum_message_size_client("1048576");
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_queue_size_lookup(sqs_client)
+                .build();
+
+        for _ in 0..2 {
+            assert_eq!(
+                1048576,
+                sqs_extended_client
+                    .message_size_threshold_for(Some(QUEUE_URL))
+                    .await
+            );
+        }
+
+        // the limit is cached per queue
+        assert_eq!(1, http_client.actual_requests().count());
+
+        assert_eq!(
+            MAX_MESSAGE_SIZE_IN_BYTES,
+            sqs_extended_client.message_size_threshold_for(None).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_message_size_threshold_override() {
+        let (http_client, sqs_client) = make_maximum_message_size_client("1048576");
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_queue_size_lookup(sqs_client)
+                .with_message_size_threshold(1024)
+                .build();
+
+        assert_eq!(
+            1024,
+            sqs_extended_client
+                .message_size_threshold_for(Some(QUEUE_URL))
+                .await
+        );
+        assert_eq!(0, http_client.actual_requests().count());
+    }
+
+    #[tokio::test]
+    async fn test_message_size_threshold_lookup_failure() {
+        let make_access_denied_event = || {
+            ReplayEvent::new(
+                HttpRequest::new(SdkBody::empty()),
+                HttpResponse::new(
+                    StatusCode::try_from(400).expect("invalid status code"),
+                    SdkBody::from(
+                        r#"{"__type":"com.amazon.coral.service#AccessDeniedException","message":"denied"}"#,
+                    ),
+                ),
+            )
+        };
+        let http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_access_denied_event(),
+            make_replay_event(r#"{"MessageId":"ID","MD5OfMessageBody":"MD5"}"#),
+            make_access_denied_event(),
+            make_replay_event(r#"{"MessageId":"ID","MD5OfMessageBody":"MD5"}"#),
+        ]);
+        let sqs_client: aws_sdk_sqs::Client = make_replay_sqs_client(&http_client);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_test_s3_client())
+                .with_s3_bucket_name("bucket-name".to_string())
+                .with_queue_size_lookup(sqs_client.clone())
+                .build();
+
+        // the fallback is not cached - the second send looks the queue up again
+        for _ in 0..2 {
+            let output: SendMessageOutput = sqs_extended_client
+                .send_message(
+                    sqs_client
+                        .send_message()
+                        .queue_url(QUEUE_URL)
+                        .message_body("hello world"),
+                )
+                .await
+                .expect("send_message failed");
+
+            assert_eq!(Some("ID"), output.message_id());
+        }
+
+        assert_eq!(4, http_client.actual_requests().count());
+    }
+}
@@ -0,0 +1,233 @@
+use std::fmt;
+
+use crate::S3Pointer;
+
+//-POINTER CODEC----------------------------------------------------------------
+
+// Writes and reads the message body that replaces an offloaded payload. Only
+// bodies of messages carrying a reserved attribute are handed to a codec.
+pub trait PointerCodec: Send + Sync {
+    fn encode(&self, s3_pointer: &S3Pointer) -> String;
+    fn decode(&self, body: &str) -> Result<S3Pointer, PointerCodecError>;
+    // Whether `body` is in this codec's format - bodies the configured codec
+    // does not detect are still tried against the built-in formats.
+    fn detect(&self, body: &str) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerFormat {
+    // `["<pointer class>",{"s3BucketName":"..","s3Key":".."}]` - the Java
+    // extended client libraries.
+    JavaArray,
+    // `{"s3BucketName":"..","s3Key":".."}` - the Python and Node.js extended
+    // client libraries.
+    JsonObject,
+}
+
+impl PointerFormat {
+    pub(crate) fn codec(self) -> Box<dyn PointerCodec> {
+        match self {
+            PointerFormat::JavaArray => Box::new(JavaPointerCodec),
+            PointerFormat::JsonObject => Box::new(JsonObjectPointerCodec),
+        }
+    }
+}
+
+// The pointer class is taken from the S3Pointer - see `with_pointer_class`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JavaPointerCodec;
+
+impl PointerCodec for JavaPointerCodec {
+    fn encode(&self, s3_pointer: &S3Pointer) -> String {
+        s3_pointer.clone().marshall_json(PointerFormat::JavaArray)
+    }
+
+    fn decode(&self, body: &str) -> Result<S3Pointer, PointerCodecError> {
+        S3Pointer::unmarshall_json(body).map_err(PointerCodecError::Json)
+    }
+
+    fn detect(&self, body: &str) -> bool {
+        body.trim_start().starts_with('[') && self.decode(body).is_ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonObjectPointerCodec;
+
+impl PointerCodec for JsonObjectPointerCodec {
+    fn encode(&self, s3_pointer: &S3Pointer) -> String {
+        s3_pointer.clone().marshall_json(PointerFormat::JsonObject)
+    }
+
+    fn decode(&self, body: &str) -> Result<S3Pointer, PointerCodecError> {
+        S3Pointer::unmarshall_json(body).map_err(PointerCodecError::Json)
+    }
+
+    fn detect(&self, body: &str) -> bool {
+        body.trim_start().starts_with('{') && self.decode(body).is_ok()
+    }
+}
+
+//-ERRORS-----------------------------------------------------------------------
+
+#[derive(Debug)]
+pub enum PointerCodecError {
+    Json(serde_json::Error),
+    // For codecs rejecting a well formed pointer - e.g. an unknown schema
+    // version.
+    Invalid(String),
+}
+
+impl fmt::Display for PointerCodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "S3 pointer JSON is invalid: {}", err),
+            Self::Invalid(msg) => write!(f, "S3 pointer is invalid: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PointerCodecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(err) => Some(err),
+            Self::Invalid(_) => None,
+        }
+    }
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::SqsExtendedClientError;
+
+    // A versioned envelope like the ones some services wrap pointers in.
+    struct EnvelopePointerCodec;
+
+    #[derive(Serialize, Deserialize)]
+    struct Envelope {
+        version: u32,
+        bucket: String,
+        key: String,
+    }
+
+    impl PointerCodec for EnvelopePointerCodec {
+        fn encode(&self, s3_pointer: &S3Pointer) -> String {
+            serde_json::to_string(&Envelope {
+                version: 2,
+                bucket: s3_pointer.bucket_name().to_string(),
+                key: s3_pointer.key().to_string(),
+            })
+            .unwrap_or_default()
+        }
+
+        fn decode(&self, body: &str) -> Result<S3Pointer, PointerCodecError> {
+            let envelope: Envelope = serde_json::from_str(body).map_err(PointerCodecError::Json)?;
+
+            if envelope.version != 2 {
+                return Err(PointerCodecError::Invalid(format!(
+                    "unsupported version {}",
+                    envelope.version
+                )));
+            }
+
+            Ok(S3Pointer::new(
+                envelope.bucket,
+                envelope.key,
+                "".to_string(),
+            ))
+        }
+
+        fn detect(&self, body: &str) -> bool {
+            body.contains(r#""version":"#)
+        }
+    }
+
+    fn make_test_pointer() -> S3Pointer {
+        S3Pointer::new(
+            "BUCKET".to_string(),
+            "KEY".to_string(),
+            "software.amazon.payloadoffloading.PayloadS3Pointer".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_java_pointer_codec() {
+        let body: String = JavaPointerCodec.encode(&make_test_pointer());
+
+        assert_eq!(
+            r#"["software.amazon.payloadoffloading.PayloadS3Pointer",{"s3BucketName":"BUCKET","s3Key":"KEY"}]"#,
+            body
+        );
+        assert!(JavaPointerCodec.detect(&body));
+        assert!(!JavaPointerCodec.detect(r#"{"s3BucketName":"BUCKET","s3Key":"KEY"}"#));
+        assert_eq!(
+            make_test_pointer(),
+            JavaPointerCodec.decode(&body).expect("decode failed")
+        );
+    }
+
+    #[test]
+    fn test_json_object_pointer_codec() {
+        let body: String = JsonObjectPointerCodec.encode(&make_test_pointer());
+
+        assert_eq!(r#"{"s3BucketName":"BUCKET","s3Key":"KEY"}"#, body);
+        assert!(JsonObjectPointerCodec.detect(&body));
+        assert!(!JsonObjectPointerCodec.detect("hello world"));
+        assert_eq!(
+            "KEY",
+            JsonObjectPointerCodec
+                .decode(&body)
+                .expect("decode failed")
+                .key()
+        );
+    }
+
+    #[test]
+    fn test_custom_pointer_codec() {
+        let body: String = EnvelopePointerCodec.encode(&make_test_pointer());
+
+        assert_eq!(r#"{"version":2,"bucket":"BUCKET","key":"KEY"}"#, body);
+        assert!(EnvelopePointerCodec.detect(&body));
+        assert_eq!(
+            "BUCKET",
+            EnvelopePointerCodec
+                .decode(&body)
+                .expect("decode failed")
+                .bucket_name()
+        );
+        assert!(matches!(
+            EnvelopePointerCodec.decode(r#"{"version":1,"bucket":"BUCKET","key":"KEY"}"#),
+            Err(PointerCodecError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_pointer_codec_error_source() {
+        let err: SqsExtendedClientError = JavaPointerCodec
+            .decode("[")
+            .expect_err("decode should fail")
+            .into();
+
+        let pointer_error: &dyn Error = err.source().expect("no pointer codec error");
+        assert!(pointer_error.is::<PointerCodecError>());
+        assert!(
+            pointer_error
+                .source()
+                .expect("no serde error")
+                .is::<serde_json::Error>()
+        );
+
+        assert!(
+            PointerCodecError::Invalid("version 1".to_string())
+                .source()
+                .is_none()
+        );
+    }
+}
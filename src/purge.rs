@@ -0,0 +1,545 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use aws_sdk_sqs::operation::delete_message_batch::DeleteMessageBatchOutput;
+use aws_sdk_sqs::types::{
+    ChangeMessageVisibilityBatchRequestEntry, DeleteMessageBatchRequestEntry, Message,
+};
+
+use crate::{ErrorContext, S3Pointer, SqsExtendedClient, SqsExtendedClientError};
+
+// The most messages a ReceiveMessage or DeleteMessageBatch request handles.
+const PURGE_BATCH_SIZE: i32 = 10;
+// Received messages stay hidden this long, so the scan does not see them twice.
+const PURGE_VISIBILITY_TIMEOUT_IN_SECONDS: i32 = 300;
+
+//-PURGE OPTIONS----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PurgeMode {
+    // Delete the messages in batches as they are received.
+    ReceiveDelete,
+    // Receive every message to collect its pointer, then call PurgeQueue.
+    // Messages received by other consumers during the scan are purged without
+    // their payloads being seen.
+    PurgeQueue,
+}
+
+//-PURGE REPORT-----------------------------------------------------------------
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PurgeReport {
+    messages: usize,
+    payloads: usize,
+    payload_bytes: usize,
+    deleted: usize,
+    failed: Vec<String>,
+}
+
+impl PurgeReport {
+    // The messages removed from the queue - or that would be, for a dry run.
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+
+    // The distinct S3 objects those messages point at.
+    pub fn payloads(&self) -> usize {
+        self.payloads
+    }
+
+    pub fn payload_bytes(&self) -> usize {
+        self.payload_bytes
+    }
+
+    pub fn deleted(&self) -> usize {
+        self.deleted
+    }
+
+    // The keys of the payloads that could not be deleted, and the IDs of the
+    // messages whose S3 pointer could not be read.
+    pub fn failed(&self) -> &[String] {
+        &self.failed
+    }
+}
+
+//-PURGE------------------------------------------------------------------------
+
+impl SqsExtendedClient {
+    // Drains the queue and deletes the S3 objects its messages point at. A dry
+    // run deletes nothing and makes each batch of scanned messages visible
+    // again right away. It stops at the first batch without a message it has
+    // not seen, so on a busy queue the report can miss messages, and each
+    // scanned message's receive count still goes up - which counts towards a
+    // redrive policy's `maxReceiveCount`. Content addressed payloads may back
+    // messages on other queues, so they are released according to the
+    // `PayloadCleanup` instead.
+    // Payloads that cannot be deleted are listed in the report's `failed`.
+    pub async fn purge_with_payloads(
+        &self,
+        sqs_client: &aws_sdk_sqs::Client,
+        queue_url: &str,
+        mode: PurgeMode,
+        dry_run: bool,
+    ) -> Result<PurgeReport, SqsExtendedClientError> {
+        self.drain_queue_and_payloads(sqs_client, queue_url, mode, dry_run)
+            .await
+            .map_err(|err| {
+                err.with_context(
+                    ErrorContext::new("purge_with_payloads").with_queue_url(Some(queue_url)),
+                )
+            })
+    }
+
+    async fn drain_queue_and_payloads(
+        &self,
+        sqs_client: &aws_sdk_sqs::Client,
+        queue_url: &str,
+        mode: PurgeMode,
+        dry_run: bool,
+    ) -> Result<PurgeReport, SqsExtendedClientError> {
+        let mut report: PurgeReport = PurgeReport::default();
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut payloads: HashSet<(String, String)> = HashSet::new();
+        let mut removed: HashSet<(String, String)> = HashSet::new();
+        let mut purged_pointers: Vec<S3Pointer> = Vec::new();
+
+        loop {
+            let messages: Vec<Message> = sqs_client
+                .receive_message()
+                .queue_url(queue_url)
+                .max_number_of_messages(PURGE_BATCH_SIZE)
+                .visibility_timeout(PURGE_VISIBILITY_TIMEOUT_IN_SECONDS)
+                .wait_time_seconds(1)
+                .message_attribute_names("All")
+                .send()
+                .await?
+                .messages
+                .unwrap_or_default();
+
+            if dry_run {
+                self.release_messages(sqs_client, queue_url, &messages)
+                    .await?;
+            }
+
+            // a message seen twice has outlived the visibility timeout, or was
+            // released by a dry run
+            let messages: Vec<Message> = messages
+                .into_iter()
+                .filter(|message| {
+                    message
+                        .message_id
+                        .as_ref()
+                        .is_none_or(|message_id| seen.insert(message_id.clone()))
+                })
+                .collect();
+
+            if messages.is_empty() {
+                break;
+            }
+
+            let messages: Vec<Message> = if dry_run || mode == PurgeMode::PurgeQueue {
+                messages
+            } else {
+                self.delete_message_batch(sqs_client, queue_url, messages)
+                    .await?
+            };
+
+            let mut s3_pointers: Vec<S3Pointer> = Vec::new();
+
+            for message in messages {
+                report.messages += 1;
+
+                match self.message_s3_pointer(&message) {
+                    Ok(None) => {}
+                    Ok(Some((s3_pointer, payload_size))) => {
+                        report.payload_bytes += payload_size;
+                        payloads
+                            .insert((s3_pointer.s3_bucket_name.clone(), s3_pointer.s3_key.clone()));
+                        s3_pointers.push(s3_pointer);
+                    }
+                    Err(_) => report.failed.push(message.message_id.unwrap_or_default()),
+                }
+            }
+
+            // The batch's messages are gone, so their payloads go right away -
+            // an error later in the drain leaves none of them behind.
+            if dry_run || mode == PurgeMode::PurgeQueue {
+                purged_pointers.extend(s3_pointers);
+            } else {
+                self.delete_payloads(&s3_pointers, &mut removed, &mut report)
+                    .await;
+            }
+        }
+
+        report.payloads = payloads.len();
+
+        if dry_run {
+            return Ok(report);
+        }
+
+        if mode == PurgeMode::PurgeQueue {
+            sqs_client
+                .purge_queue()
+                .queue_url(queue_url)
+                .send()
+                .await
+                .map_err(|err| SqsExtendedClientError::SqsPurgeQueue(err.into()))?;
+
+            self.delete_payloads(&purged_pointers, &mut removed, &mut report)
+                .await;
+        }
+
+        Ok(report)
+    }
+
+    // Deletes the payloads not `removed` yet, recording the keys that could not
+    // be deleted in the report rather than stopping the purge.
+    async fn delete_payloads(
+        &self,
+        s3_pointers: &[S3Pointer],
+        removed: &mut HashSet<(String, String)>,
+        report: &mut PurgeReport,
+    ) {
+        // every message holds its own reference to a content addressed object
+        if self.content_addressed_keys {
+            for s3_pointer in s3_pointers {
+                if self.release_payload(s3_pointer).await.is_err() {
+                    report.failed.push(s3_pointer.s3_key.clone());
+                }
+            }
+            return;
+        }
+
+        let mut keys: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for s3_pointer in s3_pointers {
+            if removed.insert((s3_pointer.s3_bucket_name.clone(), s3_pointer.s3_key.clone())) {
+                keys.entry(s3_pointer.s3_bucket_name.clone())
+                    .or_default()
+                    .insert(s3_pointer.s3_key.clone());
+            }
+        }
+
+        for (bucket_name, keys) in keys {
+            let keys: Vec<String> = keys.into_iter().collect();
+
+            let failed: Vec<String> = self.delete_objects(&bucket_name, &keys).await;
+
+            report.deleted += keys.len() - failed.len();
+            report.failed.extend(failed);
+        }
+    }
+
+    // Makes received messages visible again, so a dry run leaves them to the
+    // queue's consumers.
+    async fn release_messages(
+        &self,
+        sqs_client: &aws_sdk_sqs::Client,
+        queue_url: &str,
+        messages: &[Message],
+    ) -> Result<(), SqsExtendedClientError> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let entries: Vec<ChangeMessageVisibilityBatchRequestEntry> = messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| {
+                ChangeMessageVisibilityBatchRequestEntry::builder()
+                    .id(index.to_string())
+                    .set_receipt_handle(message.receipt_handle.clone())
+                    .visibility_timeout(0)
+                    .build()
+            })
+            .collect::<Result<Vec<ChangeMessageVisibilityBatchRequestEntry>, _>>()?;
+
+        sqs_client
+            .change_message_visibility_batch()
+            .queue_url(queue_url)
+            .set_entries(Some(entries))
+            .send()
+            .await
+            .map_err(|err| SqsExtendedClientError::SqsChangeMessageVisibilityBatch(err.into()))?;
+
+        Ok(())
+    }
+
+    // Deletes the messages and returns the ones SQS deleted - the payloads of
+    // the others are kept for when they are received again.
+    async fn delete_message_batch(
+        &self,
+        sqs_client: &aws_sdk_sqs::Client,
+        queue_url: &str,
+        messages: Vec<Message>,
+    ) -> Result<Vec<Message>, SqsExtendedClientError> {
+        let entries: Vec<DeleteMessageBatchRequestEntry> = messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| {
+                DeleteMessageBatchRequestEntry::builder()
+                    .id(index.to_string())
+                    .set_receipt_handle(message.receipt_handle.clone())
+                    .build()
+            })
+            .collect::<Result<Vec<DeleteMessageBatchRequestEntry>, _>>()?;
+
+        let output: DeleteMessageBatchOutput = sqs_client
+            .delete_message_batch()
+            .queue_url(queue_url)
+            .set_entries(Some(entries))
+            .send()
+            .await
+            .map_err(|err| SqsExtendedClientError::SqsDeleteMessageBatch(err.into()))?;
+
+        let failed: HashSet<&str> = output.failed().iter().map(|entry| entry.id()).collect();
+
+        Ok(messages
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| !failed.contains(index.to_string().as_str()))
+            .map(|(_, message)| message)
+            .collect())
+    }
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_smithy_http_client::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::body::SdkBody;
+
+    use super::*;
+    use crate::SqsExtendedClientBuilder;
+    use crate::tests::{make_replay_event, make_replay_s3_client, make_replay_sqs_client};
+
+    static QUEUE_URL: &str = "https://sqs.us-west-2.amazonaws.com/123456789012/orders";
+
+    static RECEIVE_RESPONSE: &str = r#"{"Messages":[
+        {
+            "MessageId":"OFFLOADED",
+            "ReceiptHandle":"RECEIPT_HANDLE_1",
+            "Body":"[\"software.amazon.payloadoffloading.PayloadS3Pointer\",{\"s3BucketName\":\"payloads\",\"s3Key\":\"KEY\"}]",
+            "MessageAttributes":{"ExtendedPayloadSize":{"DataType":"Number","StringValue":"300000"}}
+        },
+        {
+            "MessageId":"INLINE",
+            "ReceiptHandle":"RECEIPT_HANDLE_2",
+            "Body":"hello world"
+        }
+    ]}"#;
+
+    static DELETE_OBJECTS_RESPONSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<DeleteResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"></DeleteResult>"#;
+
+    fn make_error_event(status: u16, body: &str) -> ReplayEvent {
+        ReplayEvent::new(
+            HttpRequest::new(SdkBody::empty()),
+            HttpResponse::new(
+                StatusCode::try_from(status).expect("invalid status code"),
+                SdkBody::from(body.to_string()),
+            ),
+        )
+    }
+
+    fn make_test_client(s3_http_client: &StaticReplayClient) -> SqsExtendedClient {
+        SqsExtendedClientBuilder::new(make_replay_s3_client(s3_http_client))
+            .with_s3_bucket_name("payloads".to_string())
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_purge_with_payloads_dry_run() {
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(Vec::new());
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(RECEIVE_RESPONSE),
+            make_replay_event(r#"{"Successful":[{"Id":"0"},{"Id":"1"}],"Failed":[]}"#),
+            // the released messages again - the scan stops there
+            make_replay_event(RECEIVE_RESPONSE),
+            make_replay_event(r#"{"Successful":[{"Id":"0"},{"Id":"1"}],"Failed":[]}"#),
+        ]);
+
+        let report: PurgeReport = make_test_client(&s3_http_client)
+            .purge_with_payloads(
+                &make_replay_sqs_client(&sqs_http_client),
+                QUEUE_URL,
+                PurgeMode::ReceiveDelete,
+                true,
+            )
+            .await
+            .expect("purge failed");
+
+        assert_eq!(2, report.messages());
+        assert_eq!(1, report.payloads());
+        assert_eq!(300000, report.payload_bytes());
+        assert_eq!(0, report.deleted());
+        assert_eq!(0, s3_http_client.actual_requests().count());
+
+        let requests: Vec<&HttpRequest> = sqs_http_client.actual_requests().collect();
+
+        // every scanned batch is made visible again
+        assert_eq!(4, requests.len());
+        for request in [requests[1], requests[3]] {
+            assert_eq!(
+                Some("AmazonSQS.ChangeMessageVisibilityBatch"),
+                request.headers().get("x-amz-target")
+            );
+            assert!(
+                String::from_utf8_lossy(request.body().bytes().unwrap_or_default())
+                    .contains(r#""VisibilityTimeout":0"#)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_purge_with_payloads_receive_delete() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(DELETE_OBJECTS_RESPONSE)]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(RECEIVE_RESPONSE),
+            make_replay_event(r#"{"Successful":[{"Id":"0"},{"Id":"1"}],"Failed":[]}"#),
+            make_replay_event("{}"),
+        ]);
+
+        let report: PurgeReport = make_test_client(&s3_http_client)
+            .purge_with_payloads(
+                &make_replay_sqs_client(&sqs_http_client),
+                QUEUE_URL,
+                PurgeMode::ReceiveDelete,
+                false,
+            )
+            .await
+            .expect("purge failed");
+
+        assert_eq!(2, report.messages());
+        assert_eq!(1, report.deleted());
+
+        let requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+
+        assert_eq!(1, requests.len());
+        assert!(requests[0].uri().contains("?delete"));
+    }
+
+    #[tokio::test]
+    async fn test_purge_with_payloads_keeps_payloads_of_failed_deletes() {
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(Vec::new());
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(RECEIVE_RESPONSE),
+            make_replay_event(
+                r#"{"Successful":[{"Id":"1"}],"Failed":[{"Id":"0","Code":"ReceiptHandleIsInvalid","SenderFault":true}]}"#,
+            ),
+            make_replay_event("{}"),
+        ]);
+
+        let report: PurgeReport = make_test_client(&s3_http_client)
+            .purge_with_payloads(
+                &make_replay_sqs_client(&sqs_http_client),
+                QUEUE_URL,
+                PurgeMode::ReceiveDelete,
+                false,
+            )
+            .await
+            .expect("purge failed");
+
+        assert_eq!(1, report.messages());
+        assert_eq!(0, report.payloads());
+        assert_eq!(0, s3_http_client.actual_requests().count());
+    }
+
+    #[tokio::test]
+    async fn test_purge_with_payloads_purge_queue() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(DELETE_OBJECTS_RESPONSE)]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(RECEIVE_RESPONSE),
+            // the same messages again, once their visibility timeout expired
+            make_replay_event(RECEIVE_RESPONSE),
+            make_replay_event("{}"),
+        ]);
+
+        let report: PurgeReport = make_test_client(&s3_http_client)
+            .purge_with_payloads(
+                &make_replay_sqs_client(&sqs_http_client),
+                QUEUE_URL,
+                PurgeMode::PurgeQueue,
+                false,
+            )
+            .await
+            .expect("purge failed");
+
+        assert_eq!(2, report.messages());
+        assert_eq!(1, report.deleted());
+
+        let requests: Vec<&HttpRequest> = sqs_http_client.actual_requests().collect();
+
+        assert_eq!(3, requests.len());
+        assert_eq!(
+            Some("AmazonSQS.PurgeQueue"),
+            requests[2].headers().get("x-amz-target")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_purge_with_payloads_deletes_payloads_per_batch() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(DELETE_OBJECTS_RESPONSE)]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(RECEIVE_RESPONSE),
+            make_replay_event(r#"{"Successful":[{"Id":"0"},{"Id":"1"}],"Failed":[]}"#),
+            make_error_event(
+                400,
+                r#"{"__type":"com.amazonaws.sqs#QueueDoesNotExist","message":"no queue"}"#,
+            ),
+        ]);
+
+        let result: Result<PurgeReport, SqsExtendedClientError> = make_test_client(&s3_http_client)
+            .purge_with_payloads(
+                &make_replay_sqs_client(&sqs_http_client),
+                QUEUE_URL,
+                PurgeMode::ReceiveDelete,
+                false,
+            )
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(SqsExtendedClientError::SqsReceiveMessage(_))
+        ));
+
+        // the first batch's payload went with its messages, before the drain
+        // failed
+        let requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+
+        assert_eq!(1, requests.len());
+        assert!(requests[0].uri().contains("?delete"));
+    }
+
+    #[tokio::test]
+    async fn test_purge_with_payloads_reports_failed_payload_deletes() {
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(vec![make_error_event(
+            403,
+            "<Error><Code>AccessDenied</Code><Message>denied</Message></Error>",
+        )]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(RECEIVE_RESPONSE),
+            make_replay_event(r#"{"Successful":[{"Id":"0"},{"Id":"1"}],"Failed":[]}"#),
+            make_replay_event("{}"),
+        ]);
+
+        let report: PurgeReport = make_test_client(&s3_http_client)
+            .purge_with_payloads(
+                &make_replay_sqs_client(&sqs_http_client),
+                QUEUE_URL,
+                PurgeMode::ReceiveDelete,
+                false,
+            )
+            .await
+            .expect("purge failed");
+
+        assert_eq!(2, report.messages());
+        assert_eq!(0, report.deleted());
+        assert_eq!(["KEY".to_string()], report.failed());
+        assert_eq!(3, sqs_http_client.actual_requests().count());
+    }
+}
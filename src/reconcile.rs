@@ -0,0 +1,400 @@
+use std::time::{Duration, SystemTime};
+
+use aws_sdk_s3::operation::delete_objects::DeleteObjectsOutput;
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Output;
+use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use aws_sdk_sqs::operation::get_queue_attributes::GetQueueAttributesOutput;
+use aws_sdk_sqs::operation::get_queue_attributes::builders::GetQueueAttributesFluentBuilder;
+use aws_sdk_sqs::types::QueueAttributeName;
+
+use crate::{ErrorContext, SqsExtendedClient, SqsExtendedClientError, telemetry};
+
+// SQS's default, for queues that do not report their retention period.
+const DEFAULT_RETENTION_PERIOD_IN_SECONDS: u64 = 345600;
+// Payloads are uploaded before their message is sent, so they are kept a while
+// past the retention period to cover slow sends.
+const RECONCILE_GRACE_PERIOD: Duration = Duration::from_secs(3600);
+// The most keys a DeleteObjects request accepts.
+const DELETE_OBJECTS_BATCH_SIZE: usize = 1000;
+
+//-RECONCILE REPORT-------------------------------------------------------------
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReconcileReport {
+    scanned: usize,
+    orphaned: Vec<String>,
+    deleted: usize,
+    failed: Vec<String>,
+}
+
+impl ReconcileReport {
+    pub fn scanned(&self) -> usize {
+        self.scanned
+    }
+
+    // The keys of the objects older than the retention period - deleted unless
+    // this was a dry run.
+    pub fn orphaned(&self) -> &[String] {
+        &self.orphaned
+    }
+
+    pub fn deleted(&self) -> usize {
+        self.deleted
+    }
+
+    pub fn failed(&self) -> &[String] {
+        &self.failed
+    }
+}
+
+//-RECONCILE--------------------------------------------------------------------
+
+impl SqsExtendedClient {
+    // Deletes the objects under the object prefix of the builder's bucket that
+    // are older than the queue's retention period - their messages expired, or
+    // were never sent. Queues sharing the bucket and prefix must be reconciled
+    // with the longest retention period among them. A client without an object
+    // prefix fails with `ReconcileWithoutObjectPrefix`, unless
+    // `with_bucket_wide_reconcile` allows scanning the whole bucket. Objects
+    // that could not be deleted are listed in the report's `failed`.
+    pub async fn reconcile_payloads(
+        &self,
+        queue_attributes: GetQueueAttributesFluentBuilder,
+        dry_run: bool,
+    ) -> Result<ReconcileReport, SqsExtendedClientError> {
+        let context: ErrorContext = ErrorContext::new("reconcile_payloads")
+            .with_queue_url(queue_attributes.get_queue_url().as_deref());
+
+        self.find_and_delete_orphans(queue_attributes, dry_run)
+            .await
+            .map_err(|err| err.with_context(context))
+    }
+
+    async fn find_and_delete_orphans(
+        &self,
+        queue_attributes: GetQueueAttributesFluentBuilder,
+        dry_run: bool,
+    ) -> Result<ReconcileReport, SqsExtendedClientError> {
+        let Some(bucket_name) = &self.bucket_name else {
+            return Err(SqsExtendedClientError::NoBucketName { context: None });
+        };
+
+        // a content addressed object is reused by every later message with the
+        // same payload, so its age says nothing about its messages
+        if self.content_addressed_keys {
+            return Err(SqsExtendedClientError::ReconcileContentAddressedKeys { context: None });
+        }
+
+        // without a prefix every old object in the bucket would be deleted,
+        // including ones this client never wrote
+        if self.object_prefix.is_empty() && !self.bucket_wide_reconcile {
+            return Err(SqsExtendedClientError::ReconcileWithoutObjectPrefix { context: None });
+        }
+
+        let queue_url: Option<String> = queue_attributes.get_queue_url().clone();
+        let retention_period: Duration = self.retention_period(queue_attributes).await?;
+        let cutoff: SystemTime = SystemTime::now()
+            .checked_sub(retention_period + RECONCILE_GRACE_PERIOD)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let mut report: ReconcileReport = ReconcileReport::default();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let page: ListObjectsV2Output = self
+                .with_s3_retries("ListObjectsV2", || async {
+                    self.s3_client_for(bucket_name)
+                        .list_objects_v2()
+                        .bucket(bucket_name)
+                        .set_prefix(
+                            (!self.object_prefix.is_empty())
+                                .then(|| format!("{}/", self.object_prefix)),
+                        )
+                        .set_continuation_token(continuation_token.clone())
+                        .send()
+                        .await
+                        .map_err(|err| SqsExtendedClientError::S3ListObjects(err.into()))
+                })
+                .await?;
+
+            for object in page.contents() {
+                report.scanned += 1;
+
+                let (Some(key), Some(last_modified)) = (object.key(), object.last_modified())
+                else {
+                    continue;
+                };
+
+                if SystemTime::try_from(*last_modified).is_ok_and(|modified| modified < cutoff) {
+                    report.orphaned.push(key.to_string());
+                }
+            }
+
+            continuation_token = page.next_continuation_token().map(str::to_string);
+
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        if !dry_run {
+            report.failed = self.delete_objects(bucket_name, &report.orphaned).await;
+            report.deleted = report.orphaned.len() - report.failed.len();
+
+            telemetry::record_orphans_deleted(queue_url.as_deref(), bucket_name, report.deleted);
+        }
+
+        Ok(report)
+    }
+
+    async fn retention_period(
+        &self,
+        queue_attributes: GetQueueAttributesFluentBuilder,
+    ) -> Result<Duration, SqsExtendedClientError> {
+        let output: GetQueueAttributesOutput = queue_attributes
+            .attribute_names(QueueAttributeName::MessageRetentionPeriod)
+            .send()
+            .await
+            .map_err(|err| SqsExtendedClientError::SqsGetQueueAttributes(err.into()))?;
+
+        let seconds: u64 = output
+            .attributes()
+            .and_then(|attributes| attributes.get(&QueueAttributeName::MessageRetentionPeriod))
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_RETENTION_PERIOD_IN_SECONDS);
+
+        Ok(Duration::from_secs(seconds))
+    }
+
+    // Deletes in batches of up to 1000 keys and returns the keys S3 could not
+    // delete - all of a batch's keys when its request failed.
+    pub(crate) async fn delete_objects(&self, bucket_name: &str, keys: &[String]) -> Vec<String> {
+        let mut failed: Vec<String> = Vec::new();
+
+        for batch in keys.chunks(DELETE_OBJECTS_BATCH_SIZE) {
+            match self.delete_objects_batch(bucket_name, batch).await {
+                Ok(output) => failed.extend(
+                    output
+                        .errors()
+                        .iter()
+                        .filter_map(|error| error.key())
+                        .map(str::to_string),
+                ),
+                Err(_) => failed.extend_from_slice(batch),
+            }
+        }
+
+        failed
+    }
+
+    async fn delete_objects_batch(
+        &self,
+        bucket_name: &str,
+        keys: &[String],
+    ) -> Result<DeleteObjectsOutput, SqsExtendedClientError> {
+        let objects: Vec<ObjectIdentifier> = keys
+            .iter()
+            .map(|key| ObjectIdentifier::builder().key(key).build())
+            .collect::<Result<Vec<ObjectIdentifier>, _>>()?;
+
+        let delete: Delete = Delete::builder()
+            .set_objects(Some(objects))
+            .quiet(true)
+            .build()?;
+
+        self.with_s3_retries("DeleteObjects", || async {
+            self.s3_client_for(bucket_name)
+                .delete_objects()
+                .bucket(bucket_name)
+                .delete(delete.clone())
+                .send()
+                .await
+                .map_err(|err| SqsExtendedClientError::S3DeleteObjects(err.into()))
+        })
+        .await
+    }
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_smithy_http_client::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::body::SdkBody;
+
+    use super::*;
+    use crate::SqsExtendedClientBuilder;
+    use crate::tests::{make_replay_event, make_replay_s3_client, make_replay_sqs_client};
+
+    static QUEUE_URL: &str = "https://sqs.us-west-2.amazonaws.com/123456789012/orders";
+
+    static LIST_OBJECTS_RESPONSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <Name>payloads</Name>
+    <Prefix>prefix/</Prefix>
+    <KeyCount>2</KeyCount>
+    <MaxKeys>1000</MaxKeys>
+    <IsTruncated>false</IsTruncated>
+    <Contents>
+        <Key>prefix/expired</Key>
+        <LastModified>2000-01-01T00:00:00.000Z</LastModified>
+        <Size>11</Size>
+    </Contents>
+    <Contents>
+        <Key>prefix/live</Key>
+        <LastModified>2100-01-01T00:00:00.000Z</LastModified>
+        <Size>11</Size>
+    </Contents>
+</ListBucketResult>"#;
+
+    static DELETE_OBJECTS_RESPONSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<DeleteResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"></DeleteResult>"#;
+
+    fn make_test_queue_attributes() -> (StaticReplayClient, GetQueueAttributesFluentBuilder) {
+        let http_client: StaticReplayClient = StaticReplayClient::new(vec![make_replay_event(
+            r#"{"Attributes":{"MessageRetentionPeriod":"60"}}"#,
+        )]);
+
+        let queue_attributes: GetQueueAttributesFluentBuilder =
+            make_replay_sqs_client(&http_client)
+                .get_queue_attributes()
+                .queue_url(QUEUE_URL);
+
+        (http_client, queue_attributes)
+    }
+
+    fn make_test_client(s3_http_client: &StaticReplayClient) -> SqsExtendedClient {
+        SqsExtendedClientBuilder::new(make_replay_s3_client(s3_http_client))
+            .with_s3_bucket_name("payloads".to_string())
+            .with_object_prefix("prefix".to_string())
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_payloads_dry_run() {
+        let (_, queue_attributes) = make_test_queue_attributes();
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(LIST_OBJECTS_RESPONSE)]);
+
+        let report: ReconcileReport = make_test_client(&s3_http_client)
+            .reconcile_payloads(queue_attributes, true)
+            .await
+            .expect("reconcile failed");
+
+        assert_eq!(2, report.scanned());
+        assert_eq!(["prefix/expired".to_string()], report.orphaned());
+        assert_eq!(0, report.deleted());
+        assert_eq!(1, s3_http_client.actual_requests().count());
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_payloads() {
+        let (_, queue_attributes) = make_test_queue_attributes();
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(LIST_OBJECTS_RESPONSE),
+            make_replay_event(DELETE_OBJECTS_RESPONSE),
+        ]);
+
+        let report: ReconcileReport = make_test_client(&s3_http_client)
+            .reconcile_payloads(queue_attributes, false)
+            .await
+            .expect("reconcile failed");
+
+        assert_eq!(1, report.deleted());
+        assert!(report.failed().is_empty());
+
+        let requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+
+        assert_eq!(2, requests.len());
+        assert!(requests[0].uri().contains("prefix=prefix%2F"));
+        assert_eq!("POST", requests[1].method());
+        assert!(requests[1].uri().contains("?delete"));
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_payloads_delete_failure() {
+        let (_, queue_attributes) = make_test_queue_attributes();
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(LIST_OBJECTS_RESPONSE),
+            ReplayEvent::new(
+                HttpRequest::new(SdkBody::empty()),
+                HttpResponse::new(
+                    StatusCode::try_from(403).expect("invalid status code"),
+                    SdkBody::from(
+                        "<Error><Code>AccessDenied</Code><Message>denied</Message></Error>",
+                    ),
+                ),
+            ),
+        ]);
+
+        let report: ReconcileReport = make_test_client(&s3_http_client)
+            .reconcile_payloads(queue_attributes, false)
+            .await
+            .expect("reconcile failed");
+
+        assert_eq!(["prefix/expired".to_string()], report.orphaned());
+        assert_eq!(0, report.deleted());
+        assert_eq!(["prefix/expired".to_string()], report.failed());
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_payloads_without_object_prefix() {
+        let (sqs_http_client, queue_attributes) = make_test_queue_attributes();
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(Vec::new());
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("payloads".to_string())
+                .build();
+
+        assert!(matches!(
+            sqs_extended_client
+                .reconcile_payloads(queue_attributes, true)
+                .await,
+            Err(SqsExtendedClientError::ReconcileWithoutObjectPrefix { .. })
+        ));
+        assert_eq!(0, sqs_http_client.actual_requests().count());
+
+        let (_, queue_attributes) = make_test_queue_attributes();
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(LIST_OBJECTS_RESPONSE)]);
+
+        let report: ReconcileReport =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("payloads".to_string())
+                .with_bucket_wide_reconcile(true)
+                .build()
+                .reconcile_payloads(queue_attributes, true)
+                .await
+                .expect("reconcile failed");
+
+        assert_eq!(2, report.scanned());
+        assert!(
+            !s3_http_client
+                .actual_requests()
+                .any(|request| request.uri().contains("prefix="))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_payloads_content_addressed_keys() {
+        let (sqs_http_client, queue_attributes) = make_test_queue_attributes();
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(Vec::new());
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("payloads".to_string())
+                .with_content_addressed_keys(true)
+                .build();
+
+        assert!(matches!(
+            sqs_extended_client
+                .reconcile_payloads(queue_attributes, true)
+                .await,
+            Err(SqsExtendedClientError::ReconcileContentAddressedKeys { .. })
+        ));
+        assert_eq!(0, sqs_http_client.actual_requests().count());
+    }
+}
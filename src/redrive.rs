@@ -0,0 +1,708 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use aws_sdk_s3::operation::copy_object::CopyObjectOutput;
+use aws_sdk_s3::operation::copy_object::builders::CopyObjectFluentBuilder;
+use aws_sdk_sqs::operation::delete_message::builders::DeleteMessageFluentBuilder;
+use aws_sdk_sqs::operation::receive_message::builders::ReceiveMessageFluentBuilder;
+use aws_sdk_sqs::operation::send_message::builders::SendMessageFluentBuilder;
+use aws_sdk_sqs::types::{Message, MessageAttributeValue, MessageSystemAttributeName};
+use tokio::time::{Interval, MissedTickBehavior};
+
+use crate::telemetry::{self, Span, s3_transfer_span};
+use crate::{
+    ATTRIBUTES_ATTRIBUTE_NAME, CHECKSUM_ATTRIBUTE_NAME, ErrorContext, ExtendedMessage, KeyContext,
+    S3Pointer, SqsExtendedClient, SqsExtendedClientError, UnwrappedMessage, is_directory_bucket,
+};
+
+// The most messages a ReceiveMessage request returns.
+const RECEIVE_BATCH_SIZE: usize = 10;
+
+//-REDRIVE OPTIONS--------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedriveMode {
+    // Send the payload on without downloading it. The object is copied to a
+    // fresh key in S3, so `reconcile_payloads` does not take the redriven
+    // message's payload for an expired one - content addressed objects are
+    // never reconciled and are shared as they are.
+    KeepPointer,
+    // Resolve the payload and send it again with this client's settings, e.g.
+    // to move it to another bucket. The source object is left in place for
+    // `reconcile_payloads` to remove.
+    ReOffload,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedriveOptions {
+    mode: RedriveMode,
+    max_messages: Option<usize>,
+    messages_per_second: Option<u32>,
+}
+
+impl RedriveOptions {
+    pub fn new(mode: RedriveMode) -> RedriveOptions {
+        RedriveOptions {
+            mode,
+            max_messages: None,
+            messages_per_second: None,
+        }
+    }
+
+    pub fn with_max_messages(mut self, max_messages: usize) -> RedriveOptions {
+        self.max_messages = Some(max_messages);
+        self
+    }
+
+    pub fn with_rate_limit(mut self, messages_per_second: u32) -> RedriveOptions {
+        self.messages_per_second = Some(messages_per_second);
+        self
+    }
+}
+
+//-REDRIVE----------------------------------------------------------------------
+
+impl SqsExtendedClient {
+    // Moves messages from the `receive_msg` queue to the `send_msg` queue until
+    // the source is empty or `max_messages` were moved, and returns how many
+    // were moved. Source messages are deleted by their plain receipt handle, so
+    // their S3 objects are never removed - they are left to
+    // `reconcile_payloads`. A failed send stops the redrive and leaves the
+    // message on the source queue - the error's context holds how many
+    // messages were moved before it.
+    pub async fn redrive_messages(
+        &self,
+        receive_msg: ReceiveMessageFluentBuilder,
+        send_msg: SendMessageFluentBuilder,
+        delete_msg: DeleteMessageFluentBuilder,
+        options: RedriveOptions,
+    ) -> Result<usize, SqsExtendedClientError> {
+        let context: ErrorContext = ErrorContext::new("redrive_messages")
+            .with_queue_url(receive_msg.get_queue_url().as_deref());
+
+        self.move_messages(receive_msg, send_msg, delete_msg, options)
+            .await
+            .map_err(|err| err.with_context(context))
+    }
+
+    async fn move_messages(
+        &self,
+        receive_msg: ReceiveMessageFluentBuilder,
+        send_msg: SendMessageFluentBuilder,
+        delete_msg: DeleteMessageFluentBuilder,
+        options: RedriveOptions,
+    ) -> Result<usize, SqsExtendedClientError> {
+        let mut rate_limit: Option<Interval> = options
+            .messages_per_second
+            .filter(|per_second| *per_second > 0)
+            .map(|per_second| {
+                let mut interval: Interval =
+                    tokio::time::interval(Duration::from_secs(1) / per_second);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                interval
+            });
+
+        let mut moved: usize = 0;
+
+        loop {
+            let remaining: usize = options
+                .max_messages
+                .map_or(RECEIVE_BATCH_SIZE, |max_messages| max_messages - moved)
+                .min(RECEIVE_BATCH_SIZE);
+
+            if remaining == 0 {
+                break;
+            }
+
+            let receive_msg: ReceiveMessageFluentBuilder = receive_msg
+                .clone()
+                .max_number_of_messages(remaining as i32)
+                .message_attribute_names("All")
+                .message_system_attribute_names(MessageSystemAttributeName::MessageGroupId)
+                .message_system_attribute_names(MessageSystemAttributeName::MessageDeduplicationId);
+
+            let messages: Vec<Message> = match receive_msg.send().await {
+                Ok(output) => output.messages.unwrap_or_default(),
+                Err(err) => {
+                    return Err(SqsExtendedClientError::from(err)
+                        .with_context(ErrorContext::for_messages_moved(moved)));
+                }
+            };
+
+            if messages.is_empty() {
+                break;
+            }
+
+            for message in messages {
+                if let Some(interval) = rate_limit.as_mut() {
+                    interval.tick().await;
+                }
+
+                self.move_message(message, &send_msg, &delete_msg, options.mode)
+                    .await
+                    .map_err(|err| err.with_context(ErrorContext::for_messages_moved(moved)))?;
+
+                moved += 1;
+            }
+        }
+
+        Ok(moved)
+    }
+
+    async fn move_message(
+        &self,
+        message: Message,
+        send_msg: &SendMessageFluentBuilder,
+        delete_msg: &DeleteMessageFluentBuilder,
+        mode: RedriveMode,
+    ) -> Result<(), SqsExtendedClientError> {
+        let receipt_handle: Option<String> = message.receipt_handle.clone();
+        let send_msg: SendMessageFluentBuilder = Self::with_fifo_fields(send_msg.clone(), &message);
+
+        match mode {
+            RedriveMode::KeepPointer => {
+                let (body, message_attributes, copy) =
+                    self.kept_pointer_message(&message, &send_msg).await?;
+
+                let result = send_msg
+                    .set_message_body(body)
+                    .set_message_attributes(message_attributes)
+                    .send()
+                    .await;
+
+                if let Err(err) = result {
+                    let leaked: Vec<S3Pointer> = self.roll_back_payloads(copy.as_ref()).await;
+
+                    return Err(SqsExtendedClientError::SqsSendMessage(err.into())
+                        .with_context(ErrorContext::for_leaked_payloads(leaked)));
+                }
+            }
+            RedriveMode::ReOffload => {
+                let extended_message: ExtendedMessage = self.resolve_message(message).await?;
+                let message_attributes: HashMap<String, MessageAttributeValue> =
+                    self.redriven_attributes(extended_message.message_attributes());
+
+                self.send_message(
+                    send_msg
+                        .message_body(extended_message.into_payload())
+                        .set_message_attributes(Some(message_attributes)),
+                )
+                .await?;
+            }
+        }
+
+        self.delete_message(delete_msg.clone().set_receipt_handle(receipt_handle))
+            .await?;
+
+        Ok(())
+    }
+
+    // The body and attributes of a message redriven in `KeepPointer` mode - a
+    // pointer to a fresh copy of the payload when it was offloaded - and that
+    // copy, to roll back if the message is not sent.
+    async fn kept_pointer_message(
+        &self,
+        message: &Message,
+        send_msg: &SendMessageFluentBuilder,
+    ) -> Result<
+        (
+            Option<String>,
+            Option<HashMap<String, MessageAttributeValue>>,
+            Option<S3Pointer>,
+        ),
+        SqsExtendedClientError,
+    > {
+        if self.content_addressed_keys {
+            return Ok((
+                message.body.clone(),
+                message.message_attributes.clone(),
+                None,
+            ));
+        }
+
+        // an SNS notification is sent on unwrapped - its attributes are those of
+        // the notification
+        let Some(body) = &message.body else {
+            return Ok((None, message.message_attributes.clone(), None));
+        };
+
+        let UnwrappedMessage {
+            message_attributes,
+            s3_pointer: Some(s3_pointer),
+            ..
+        } = self.unwrap_message(message, body)?
+        else {
+            return Ok((
+                message.body.clone(),
+                message.message_attributes.clone(),
+                None,
+            ));
+        };
+
+        let key_context: KeyContext = KeyContext::new(
+            send_msg.get_queue_url().as_deref(),
+            send_msg.get_message_group_id().as_deref(),
+            Some(&message_attributes),
+            SystemTime::now(),
+        );
+
+        let copy: S3Pointer = self
+            .copy_payload(&s3_pointer, &key_context, message.message_id.as_deref())
+            .await
+            .map_err(|err| err.with_context(ErrorContext::for_s3_pointer(&s3_pointer)))?;
+
+        Ok((
+            Some(self.pointer_codec.encode(&copy)),
+            Some(message_attributes),
+            Some(copy),
+        ))
+    }
+
+    async fn copy_payload(
+        &self,
+        s3_pointer: &S3Pointer,
+        key_context: &KeyContext<'_>,
+        message_id: Option<&str>,
+    ) -> Result<S3Pointer, SqsExtendedClientError> {
+        let copy: S3Pointer = S3Pointer {
+            s3_key: self.s3_key(self.key_strategy.object_name(key_context)),
+            ..s3_pointer.clone()
+        };
+
+        let copy_object = || {
+            let copy_object: CopyObjectFluentBuilder = self
+                .s3_client_for(&copy.s3_bucket_name)
+                .copy_object()
+                .bucket(&copy.s3_bucket_name)
+                .key(&copy.s3_key)
+                .copy_source(copy_source(&s3_pointer.s3_bucket_name, &s3_pointer.s3_key));
+
+            if is_directory_bucket(&copy.s3_bucket_name) {
+                copy_object
+            } else {
+                copy_object.set_storage_class(self.storage_class.clone())
+            }
+        };
+
+        let span: Span = s3_transfer_span!("s3_copy_object", &copy, message_id);
+
+        telemetry::timed(
+            span,
+            "CopyObject",
+            key_context.queue_url(),
+            &copy.s3_bucket_name,
+            self.with_s3_retries("CopyObject", || async {
+                let _output: CopyObjectOutput = copy_object().send().await?;
+                Ok(())
+            }),
+        )
+        .await
+        .map_err(|err| err.with_context(ErrorContext::for_s3_pointer(&copy)))?;
+
+        Ok(copy)
+    }
+
+    // Carries the source message's group and deduplication IDs over - a source
+    // message without them keeps the ones set on `send_msg`.
+    fn with_fifo_fields(
+        send_msg: SendMessageFluentBuilder,
+        message: &Message,
+    ) -> SendMessageFluentBuilder {
+        let Some(system_attributes) = &message.attributes else {
+            return send_msg;
+        };
+
+        let send_msg: SendMessageFluentBuilder =
+            match system_attributes.get(&MessageSystemAttributeName::MessageGroupId) {
+                None => send_msg,
+                Some(group_id) => send_msg.message_group_id(group_id),
+            };
+
+        match system_attributes.get(&MessageSystemAttributeName::MessageDeduplicationId) {
+            None => send_msg,
+            Some(deduplication_id) => send_msg.message_deduplication_id(deduplication_id),
+        }
+    }
+
+    // The attributes `send_message` adds itself are dropped, so they describe
+    // the new payload rather than the old one.
+    fn redriven_attributes(
+        &self,
+        message_attributes: &HashMap<String, MessageAttributeValue>,
+    ) -> HashMap<String, MessageAttributeValue> {
+        message_attributes
+            .iter()
+            .filter(|(name, _)| {
+                !self.reserved_attributes.contains(name)
+                    && name.as_str() != CHECKSUM_ATTRIBUTE_NAME
+                    && name.as_str() != ATTRIBUTES_ATTRIBUTE_NAME
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+}
+
+// CopyObject takes its source as `<bucket>/<key>`, URL encoded.
+fn copy_source(bucket_name: &str, key: &str) -> String {
+    let encoded_key: String = key
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect();
+
+    format!("{}/{}", bucket_name, encoded_key)
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_smithy_http_client::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::DateTime;
+    use aws_smithy_types::body::SdkBody;
+    use aws_smithy_types::date_time::Format;
+
+    use super::*;
+    use crate::tests::{make_replay_event, make_replay_s3_client, make_replay_sqs_client};
+    use crate::{ReconcileReport, SqsExtendedClientBuilder};
+
+    static SOURCE_QUEUE_URL: &str = "https://sqs.us-west-2.amazonaws.com/123456789012/orders-dlq";
+    static TARGET_QUEUE_URL: &str = "https://sqs.us-west-2.amazonaws.com/123456789012/orders";
+
+    static RECEIVE_RESPONSE: &str = r#"{"Messages":[{
+        "MessageId":"MESSAGE_ID",
+        "ReceiptHandle":"RECEIPT_HANDLE",
+        "Body":"[\"software.amazon.payloadoffloading.PayloadS3Pointer\",{\"s3BucketName\":\"payloads\",\"s3Key\":\"KEY\"}]",
+        "MessageAttributes":{"ExtendedPayloadSize":{"DataType":"Number","StringValue":"11"}}
+    }]}"#;
+
+    static COPY_OBJECT_RESPONSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<CopyObjectResult><ETag>"ETAG"</ETag><LastModified>2025-09-17T10:11:12.000Z</LastModified></CopyObjectResult>"#;
+
+    fn request_body(request: &HttpRequest) -> String {
+        String::from_utf8_lossy(request.body().bytes().unwrap_or_default()).to_string()
+    }
+
+    fn make_test_builders(
+        sqs_http_client: &StaticReplayClient,
+    ) -> (
+        ReceiveMessageFluentBuilder,
+        SendMessageFluentBuilder,
+        DeleteMessageFluentBuilder,
+    ) {
+        let sqs_client: aws_sdk_sqs::Client = make_replay_sqs_client(sqs_http_client);
+
+        (
+            sqs_client.receive_message().queue_url(SOURCE_QUEUE_URL),
+            sqs_client.send_message().queue_url(TARGET_QUEUE_URL),
+            sqs_client.delete_message().queue_url(SOURCE_QUEUE_URL),
+        )
+    }
+
+    // The key of the copy `KeepPointer` made, from the CopyObject request.
+    fn copied_key(request: &HttpRequest) -> String {
+        request
+            .uri()
+            .split_once('?')
+            .map_or(request.uri(), |(path, _)| path)
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_redrive_messages_keep_pointer() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(COPY_OBJECT_RESPONSE)]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(RECEIVE_RESPONSE),
+            make_replay_event(r#"{"MessageId":"NEW_MESSAGE_ID"}"#),
+            make_replay_event("{}"),
+            make_replay_event("{}"),
+        ]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("payloads".to_string())
+                .build();
+
+        let (receive_msg, send_msg, delete_msg) = make_test_builders(&sqs_http_client);
+
+        let moved: usize = sqs_extended_client
+            .redrive_messages(
+                receive_msg,
+                send_msg,
+                delete_msg,
+                RedriveOptions::new(RedriveMode::KeepPointer),
+            )
+            .await
+            .expect("redrive failed");
+
+        assert_eq!(1, moved);
+
+        // the payload is copied in S3, never downloaded
+        let s3_requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+
+        assert_eq!(1, s3_requests.len());
+        assert_eq!("PUT", s3_requests[0].method());
+        assert_eq!(
+            Some("payloads/KEY"),
+            s3_requests[0].headers().get("x-amz-copy-source")
+        );
+
+        let key: String = copied_key(s3_requests[0]);
+        assert_ne!("KEY", key);
+
+        let requests: Vec<&HttpRequest> = sqs_http_client.actual_requests().collect();
+
+        assert_eq!(4, requests.len());
+
+        let send: String = request_body(requests[1]);
+        assert!(send.contains(TARGET_QUEUE_URL));
+        assert!(send.contains(&format!(r#"\"s3Key\":\"{}\""#, key)));
+        assert!(send.contains("ExtendedPayloadSize"));
+
+        let delete: String = request_body(requests[2]);
+        assert!(delete.contains(SOURCE_QUEUE_URL));
+        assert!(delete.contains(r#""ReceiptHandle":"RECEIPT_HANDLE""#));
+    }
+
+    #[tokio::test]
+    async fn test_redrive_messages_keep_pointer_survives_reconcile() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(COPY_OBJECT_RESPONSE)]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(RECEIVE_RESPONSE),
+            make_replay_event(r#"{"MessageId":"NEW_MESSAGE_ID"}"#),
+            make_replay_event("{}"),
+            make_replay_event("{}"),
+        ]);
+
+        let (receive_msg, send_msg, delete_msg) = make_test_builders(&sqs_http_client);
+
+        SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+            .with_s3_bucket_name("payloads".to_string())
+            .build()
+            .redrive_messages(
+                receive_msg,
+                send_msg,
+                delete_msg,
+                RedriveOptions::new(RedriveMode::KeepPointer),
+            )
+            .await
+            .expect("redrive failed");
+
+        let key: String = copied_key(
+            s3_http_client
+                .actual_requests()
+                .next()
+                .expect("no CopyObject request"),
+        );
+
+        // the source object is as old as the dead-lettered message, the copy
+        // the redriven message points to is new
+        let reconcile_s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(&format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <Name>payloads</Name>
+    <KeyCount>2</KeyCount>
+    <IsTruncated>false</IsTruncated>
+    <Contents><Key>KEY</Key><LastModified>2000-01-01T00:00:00.000Z</LastModified></Contents>
+    <Contents><Key>{}</Key><LastModified>{}</LastModified></Contents>
+</ListBucketResult>"#,
+                key,
+                DateTime::from(SystemTime::now())
+                    .fmt(Format::DateTime)
+                    .expect("invalid date")
+            ))]);
+        let queue_attributes_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(
+                r#"{"Attributes":{"MessageRetentionPeriod":"60"}}"#,
+            )]);
+
+        let report: ReconcileReport =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&reconcile_s3_http_client))
+                .with_s3_bucket_name("payloads".to_string())
+                .with_bucket_wide_reconcile(true)
+                .build()
+                .reconcile_payloads(
+                    make_replay_sqs_client(&queue_attributes_http_client)
+                        .get_queue_attributes()
+                        .queue_url(TARGET_QUEUE_URL),
+                    true,
+                )
+                .await
+                .expect("reconcile failed");
+
+        assert_eq!(["KEY".to_string()], report.orphaned());
+    }
+
+    #[tokio::test]
+    async fn test_redrive_messages_max_messages() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(COPY_OBJECT_RESPONSE)]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(RECEIVE_RESPONSE),
+            make_replay_event(r#"{"MessageId":"NEW_MESSAGE_ID"}"#),
+            make_replay_event("{}"),
+        ]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("payloads".to_string())
+                .build();
+
+        let (receive_msg, send_msg, delete_msg) = make_test_builders(&sqs_http_client);
+
+        let moved: usize = sqs_extended_client
+            .redrive_messages(
+                receive_msg,
+                send_msg,
+                delete_msg,
+                RedriveOptions::new(RedriveMode::KeepPointer)
+                    .with_max_messages(1)
+                    .with_rate_limit(100),
+            )
+            .await
+            .expect("redrive failed");
+
+        assert_eq!(1, moved);
+
+        let requests: Vec<&HttpRequest> = sqs_http_client.actual_requests().collect();
+
+        // no second receive once the limit is reached
+        assert_eq!(3, requests.len());
+        assert!(request_body(requests[0]).contains(r#""MaxNumberOfMessages":1"#));
+    }
+
+    #[tokio::test]
+    async fn test_redrive_messages_keep_pointer_send_fails() {
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(COPY_OBJECT_RESPONSE),
+            make_replay_event(""),
+        ]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(RECEIVE_RESPONSE),
+            ReplayEvent::new(
+                HttpRequest::new(SdkBody::empty()),
+                HttpResponse::new(
+                    StatusCode::try_from(400).expect("invalid status code"),
+                    SdkBody::from(
+                        r#"{"__type":"com.amazonaws.sqs#QueueDoesNotExist","message":"no queue"}"#,
+                    ),
+                ),
+            ),
+        ]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("payloads".to_string())
+                .build();
+
+        let (receive_msg, send_msg, delete_msg) = make_test_builders(&sqs_http_client);
+
+        let err: SqsExtendedClientError = sqs_extended_client
+            .redrive_messages(
+                receive_msg,
+                send_msg,
+                delete_msg,
+                RedriveOptions::new(RedriveMode::KeepPointer),
+            )
+            .await
+            .expect_err("redrive should fail");
+
+        assert!(matches!(err, SqsExtendedClientError::SqsSendMessage(_)));
+
+        let context: &ErrorContext = err.context().expect("no context");
+
+        assert_eq!(Some(0), context.messages_moved());
+        assert!(context.leaked_payloads().is_empty());
+
+        // the copy is deleted again, the source object is kept
+        let s3_requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+
+        assert_eq!(2, s3_requests.len());
+        assert_eq!("DELETE", s3_requests[1].method());
+        assert_eq!(copied_key(s3_requests[0]), copied_key(s3_requests[1]));
+    }
+
+    #[test]
+    fn test_with_fifo_fields() {
+        let send_msg: SendMessageFluentBuilder =
+            make_replay_sqs_client(&StaticReplayClient::new(Vec::new()))
+                .send_message()
+                .message_group_id("CALLER_GROUP");
+
+        let plain: Message = Message::builder()
+            .attributes(MessageSystemAttributeName::ApproximateReceiveCount, "1")
+            .build();
+        let kept: SendMessageFluentBuilder =
+            SqsExtendedClient::with_fifo_fields(send_msg.clone(), &plain);
+
+        assert_eq!(
+            &Some("CALLER_GROUP".to_string()),
+            kept.get_message_group_id()
+        );
+        assert_eq!(&None, kept.get_message_deduplication_id());
+
+        let fifo: Message = Message::builder()
+            .attributes(MessageSystemAttributeName::MessageGroupId, "GROUP")
+            .attributes(MessageSystemAttributeName::MessageDeduplicationId, "DEDUP")
+            .build();
+        let carried: SendMessageFluentBuilder =
+            SqsExtendedClient::with_fifo_fields(send_msg, &fifo);
+
+        assert_eq!(&Some("GROUP".to_string()), carried.get_message_group_id());
+        assert_eq!(
+            &Some("DEDUP".to_string()),
+            carried.get_message_deduplication_id()
+        );
+    }
+
+    #[test]
+    fn test_copy_source() {
+        assert_eq!(
+            "payloads/prefix/a%20b%2Bc%22",
+            copy_source("payloads", r#"prefix/a b+c""#)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redriven_attributes() {
+        let sqs_extended_client: SqsExtendedClient = SqsExtendedClientBuilder::new(
+            make_replay_s3_client(&StaticReplayClient::new(Vec::new())),
+        )
+        .build();
+
+        let make_attribute = |value: &str| {
+            MessageAttributeValue::builder()
+                .data_type("String")
+                .string_value(value)
+                .build()
+                .expect("invalid attribute")
+        };
+
+        let message_attributes: HashMap<String, MessageAttributeValue> = HashMap::from([
+            ("ExtendedPayloadSize".to_string(), make_attribute("11")),
+            (
+                CHECKSUM_ATTRIBUTE_NAME.to_string(),
+                make_attribute("crc32c:yZRlqg=="),
+            ),
+            ("TenantId".to_string(), make_attribute("tenant")),
+        ]);
+
+        let redriven: HashMap<String, MessageAttributeValue> =
+            sqs_extended_client.redriven_attributes(&message_attributes);
+
+        assert_eq!(1, redriven.len());
+        assert!(redriven.contains_key("TenantId"));
+    }
+}
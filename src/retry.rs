@@ -0,0 +1,299 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::result::SdkError;
+
+use crate::{SqsExtendedClient, SqsExtendedClientError};
+
+//-RETRY POLICY-----------------------------------------------------------------
+
+// Retries of S3 payload operations for failures the SDK's own retry strategy
+// gives up on. The SDK already retries throttling, timeouts and 5xx responses,
+// so by default only `NoSuchKey` - which S3-compatible stores may answer a read
+// right after a write with - and truncated downloads are retried here. Listing
+// codes the SDK retries as well multiplies the attempts of both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    retryable_error_codes: Vec<String>,
+    retryable_status_codes: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+            retryable_error_codes: vec!["NoSuchKey".to_string()],
+            retryable_status_codes: Vec::new(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            ..RetryPolicy::default()
+        }
+    }
+
+    // The backoff doubles after every attempt, from `initial` up to `max`.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> RetryPolicy {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    // With jitter each backoff is a random duration up to the computed one, so
+    // clients that failed together do not retry together.
+    pub fn with_jitter(mut self, jitter: bool) -> RetryPolicy {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_retryable_error_codes(mut self, error_codes: Vec<String>) -> RetryPolicy {
+        self.retryable_error_codes = error_codes;
+        self
+    }
+
+    pub fn with_retryable_status_codes(mut self, status_codes: Vec<u16>) -> RetryPolicy {
+        self.retryable_status_codes = status_codes;
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff: Duration = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff);
+
+        if !self.jitter {
+            return backoff;
+        }
+
+        let random: u64 = RandomState::new().build_hasher().finish();
+        backoff.mul_f64(random as f64 / u64::MAX as f64)
+    }
+
+    // Truncated downloads are always retried - the SDK has returned the response
+    // by the time its body is read. Other errors are retried by their code or
+    // HTTP status.
+    fn is_retryable(&self, err: &SqsExtendedClientError) -> bool {
+        match err {
+            SqsExtendedClientError::S3Upload(err) => self.is_retryable_sdk_error(err),
+            SqsExtendedClientError::S3Download(err) => self.is_retryable_sdk_error(err),
+            SqsExtendedClientError::S3DeleteObject(err) => self.is_retryable_sdk_error(err),
+            SqsExtendedClientError::S3DeleteObjects(err) => self.is_retryable_sdk_error(err),
+            SqsExtendedClientError::S3HeadObject(err) => self.is_retryable_sdk_error(err),
+            SqsExtendedClientError::S3GetObjectTagging(err) => self.is_retryable_sdk_error(err),
+            SqsExtendedClientError::S3PutObjectTagging(err) => self.is_retryable_sdk_error(err),
+            SqsExtendedClientError::S3CopyObject(err) => self.is_retryable_sdk_error(err),
+            SqsExtendedClientError::S3DownloadToBytes(_) => true,
+            _ => false,
+        }
+    }
+
+    fn is_retryable_sdk_error<E: ProvideErrorMetadata>(
+        &self,
+        err: &SdkError<E, HttpResponse>,
+    ) -> bool {
+        err.code()
+            .is_some_and(|code| self.retryable_error_codes.iter().any(|c| c == code))
+            || err.raw_response().is_some_and(|response| {
+                self.retryable_status_codes
+                    .contains(&response.status().as_u16())
+            })
+    }
+}
+
+//-RETRIES----------------------------------------------------------------------
+
+impl SqsExtendedClient {
+    // Runs an S3 payload operation under the builder's retry policy. When the
+    // last attempt fails with a retryable error, the error is wrapped in
+    // `S3RetriesExhausted` so callers can tell it was retried.
+    pub(crate) async fn with_s3_retries<T, F, Fut>(
+        &self,
+        operation: &str,
+        mut attempt: F,
+    ) -> Result<T, SqsExtendedClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SqsExtendedClientError>>,
+    {
+        let Some(retry_policy) = &self.retry_policy else {
+            return attempt().await;
+        };
+
+        let mut attempts: u32 = 0;
+
+        loop {
+            attempts += 1;
+
+            let err: SqsExtendedClientError = match attempt().await {
+                Ok(output) => return Ok(output),
+                Err(err) => err,
+            };
+
+            if !retry_policy.is_retryable(&err) {
+                return Err(err);
+            }
+
+            if attempts >= retry_policy.max_attempts {
+                return Err(SqsExtendedClientError::S3RetriesExhausted {
+                    operation: operation.to_string(),
+                    attempts,
+                    source: Box::new(err),
+                    context: None,
+                });
+            }
+
+            tokio::time::sleep(retry_policy.backoff(attempts)).await;
+        }
+    }
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_smithy_http_client::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_runtime_api::client::orchestrator::HttpRequest;
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::body::SdkBody;
+
+    use super::*;
+    use crate::SqsExtendedClientBuilder;
+    use crate::tests::{make_replay_event, make_replay_s3_client};
+
+    static NO_SUCH_KEY_RESPONSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message></Error>"#;
+
+    static ACCESS_DENIED_RESPONSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>"#;
+
+    fn make_no_such_key_event() -> ReplayEvent {
+        ReplayEvent::new(
+            HttpRequest::new(SdkBody::empty()),
+            HttpResponse::new(
+                StatusCode::try_from(404).expect("invalid status code"),
+                SdkBody::from(NO_SUCH_KEY_RESPONSE),
+            ),
+        )
+    }
+
+    fn make_test_client(
+        http_client: &StaticReplayClient,
+        retry_policy: RetryPolicy,
+    ) -> SqsExtendedClient {
+        SqsExtendedClientBuilder::new(make_replay_s3_client(http_client))
+            .with_retry_policy(retry_policy)
+            .build()
+    }
+
+    async fn get_object(
+        sqs_extended_client: &SqsExtendedClient,
+    ) -> Result<(), SqsExtendedClientError> {
+        sqs_extended_client
+            .with_s3_retries("GetObject", || async {
+                sqs_extended_client
+                    .s3_client
+                    .get_object()
+                    .bucket("payloads")
+                    .key("key")
+                    .send()
+                    .await?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[test]
+    fn test_backoff() {
+        let retry_policy: RetryPolicy = RetryPolicy::new(5)
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(300))
+            .with_jitter(false);
+
+        assert_eq!(Duration::from_millis(100), retry_policy.backoff(1));
+        assert_eq!(Duration::from_millis(200), retry_policy.backoff(2));
+        assert_eq!(Duration::from_millis(300), retry_policy.backoff(3));
+
+        let jittered: Duration = retry_policy.with_jitter(true).backoff(2);
+        assert!(jittered <= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_with_s3_retries_recovers() {
+        let http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_no_such_key_event(), make_replay_event("payload")]);
+
+        let sqs_extended_client: SqsExtendedClient = make_test_client(
+            &http_client,
+            RetryPolicy::new(3).with_backoff(Duration::ZERO, Duration::ZERO),
+        );
+
+        assert!(get_object(&sqs_extended_client).await.is_ok());
+        assert_eq!(2, http_client.actual_requests().count());
+    }
+
+    #[tokio::test]
+    async fn test_with_s3_retries_exhausted() {
+        let http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_no_such_key_event(), make_no_such_key_event()]);
+
+        let sqs_extended_client: SqsExtendedClient = make_test_client(
+            &http_client,
+            RetryPolicy::new(2)
+                .with_backoff(Duration::ZERO, Duration::ZERO)
+                .with_retryable_error_codes(vec!["NoSuchKey".to_string()]),
+        );
+
+        let Err(SqsExtendedClientError::S3RetriesExhausted {
+            operation,
+            attempts,
+            source,
+            ..
+        }) = get_object(&sqs_extended_client).await
+        else {
+            panic!("expected S3RetriesExhausted");
+        };
+
+        assert_eq!("GetObject", operation);
+        assert_eq!(2, attempts);
+        assert!(matches!(*source, SqsExtendedClientError::S3Download(_)));
+    }
+
+    #[tokio::test]
+    async fn test_with_s3_retries_not_retryable() {
+        let http_client: StaticReplayClient = StaticReplayClient::new(vec![ReplayEvent::new(
+            HttpRequest::new(SdkBody::empty()),
+            HttpResponse::new(
+                StatusCode::try_from(403).expect("invalid status code"),
+                SdkBody::from(ACCESS_DENIED_RESPONSE),
+            ),
+        )]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            make_test_client(&http_client, RetryPolicy::new(3));
+
+        assert!(matches!(
+            get_object(&sqs_extended_client).await,
+            Err(SqsExtendedClientError::S3Download(_))
+        ));
+        assert_eq!(1, http_client.actual_requests().count());
+    }
+}
@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use aws_sdk_sns::operation::publish::PublishOutput;
+use aws_sdk_sns::operation::publish::builders::PublishFluentBuilder;
+use aws_sdk_sns::operation::publish_batch::PublishBatchOutput;
+use aws_sdk_sns::operation::publish_batch::builders::PublishBatchFluentBuilder;
+use aws_sdk_sns::types::PublishBatchRequestEntry;
+
+use crate::{
+    CHECKSUM_ATTRIBUTE_NAME, ErrorContext, KeyContext, S3Pointer, SqsExtendedClient,
+    SqsExtendedClientError, message_size,
+};
+
+//-SNS EXTENDED CLIENT----------------------------------------------------------
+
+// Publishes through SNS with the same settings - and the same S3 pointer
+// format - as the SqsExtendedClient it was built from, so subscribed queues can
+// be read with `SqsExtendedClient::receive_message`.
+pub struct SnsExtendedClient {
+    inner: SqsExtendedClient,
+}
+
+impl SnsExtendedClient {
+    pub(crate) fn new(inner: SqsExtendedClient) -> SnsExtendedClient {
+        SnsExtendedClient { inner }
+    }
+
+    pub async fn publish(
+        &self,
+        publish_input: PublishFluentBuilder,
+    ) -> Result<PublishOutput, SqsExtendedClientError> {
+        let context: ErrorContext =
+            ErrorContext::new("publish").with_topic_arn(publish_input.get_topic_arn().as_deref());
+
+        self.offload_and_publish(publish_input)
+            .await
+            .map_err(|err| err.with_context(context))
+    }
+
+    pub async fn publish_batch(
+        &self,
+        batch_input: PublishBatchFluentBuilder,
+    ) -> Result<PublishBatchOutput, SqsExtendedClientError> {
+        let context: ErrorContext = ErrorContext::new("publish_batch")
+            .with_topic_arn(batch_input.get_topic_arn().as_deref());
+
+        self.offload_and_publish_batch(batch_input)
+            .await
+            .map_err(|err| err.with_context(context))
+    }
+
+    async fn offload_and_publish(
+        &self,
+        publish_input: PublishFluentBuilder,
+    ) -> Result<PublishOutput, SqsExtendedClientError> {
+        let Some(bn) = &self.inner.bucket_name else {
+            return Err(SqsExtendedClientError::NoBucketName { context: None });
+        };
+        let bucket_name: String = bn.to_string();
+
+        let Some(msg) = publish_input.get_message() else {
+            return Err(SqsExtendedClientError::NoMessageBody { context: None });
+        };
+        let message: &str = msg;
+
+        let sqs_attributes: Option<HashMap<String, aws_sdk_sqs::types::MessageAttributeValue>> =
+            publish_input
+                .get_message_attributes()
+                .as_ref()
+                .map(to_sqs_attributes);
+
+        if !self.inner.always_through_s3
+            && !self.inner.message_exceeds_threshold(
+                message,
+                sqs_attributes.as_ref(),
+                self.inner.default_message_size_threshold(),
+            )
+        {
+            return publish_input
+                .send()
+                .await
+                .map_err(|err| SqsExtendedClientError::SnsPublish(err.into()));
+        }
+
+        if is_json_message_structure(publish_input.get_message_structure()) {
+            return Err(SqsExtendedClientError::SnsJsonMessageStructure { context: None });
+        }
+
+        let key_context: KeyContext = KeyContext::new(
+            None,
+            publish_input.get_message_group_id().as_deref(),
+            sqs_attributes.as_ref(),
+            SystemTime::now(),
+        );
+
+        let s3_pointer: S3Pointer = self
+            .inner
+            .offload_payload(bucket_name, message, &key_context)
+            .await?;
+
+        let reserved_attribute: aws_sdk_sns::types::MessageAttributeValue =
+            reserved_attribute_for(message.len())?;
+
+        let publish_input: PublishFluentBuilder = match self.inner.fifo_deduplication_id(
+            publish_input.get_topic_arn(),
+            publish_input.get_message_deduplication_id(),
+            message,
+        ) {
+            None => publish_input,
+            Some(deduplication_id) => publish_input.message_deduplication_id(deduplication_id),
+        };
+
+        let publish_input: PublishFluentBuilder = match &s3_pointer.checksum {
+            None => publish_input,
+            Some(checksum) => publish_input
+                .message_attributes(CHECKSUM_ATTRIBUTE_NAME, checksum_attribute_for(checksum)?),
+        };
+
+        let result: Result<PublishOutput, SqsExtendedClientError> = publish_input
+            .message(self.inner.pointer_codec.encode(&s3_pointer))
+            .message_attributes(
+                self.inner.reserved_attributes[0].clone(),
+                reserved_attribute,
+            )
+            .send()
+            .await
+            .map_err(|err| SqsExtendedClientError::SnsPublish(err.into()));
+
+        match result {
+            Ok(output) => Ok(output),
+            Err(err) => {
+                let leaked: Vec<S3Pointer> = self.inner.roll_back_payloads([&s3_pointer]).await;
+                Err(err.with_context(ErrorContext::for_leaked_payloads(leaked)))
+            }
+        }
+    }
+
+    async fn offload_and_publish_batch(
+        &self,
+        batch_input: PublishBatchFluentBuilder,
+    ) -> Result<PublishBatchOutput, SqsExtendedClientError> {
+        let Some(bn) = &self.inner.bucket_name else {
+            return Err(SqsExtendedClientError::NoBucketName { context: None });
+        };
+        let bucket_name: String = bn.to_string();
+
+        let topic_arn: Option<String> = batch_input.get_topic_arn().clone();
+        let mut entries: Vec<PublishBatchRequestEntry> = batch_input
+            .get_publish_batch_request_entries()
+            .clone()
+            .unwrap_or_default();
+        let mut offloaded: Vec<Option<S3Pointer>> = vec![None; entries.len()];
+
+        // Nothing was published yet, so every payload uploaded so far is rolled
+        // back when a later one fails.
+        if let Err(err) = self
+            .offload_batch_entries(&bucket_name, &topic_arn, &mut entries, &mut offloaded)
+            .await
+        {
+            let leaked: Vec<S3Pointer> = self
+                .inner
+                .roll_back_payloads(offloaded.iter().flatten())
+                .await;
+            return Err(err.with_context(ErrorContext::for_leaked_payloads(leaked)));
+        }
+
+        let entry_ids: Vec<String> = entries.iter().map(|entry| entry.id.clone()).collect();
+
+        let result: Result<PublishBatchOutput, SqsExtendedClientError> = batch_input
+            .set_publish_batch_request_entries(Some(entries))
+            .send()
+            .await
+            .map_err(|err| SqsExtendedClientError::SnsPublishBatch(err.into()));
+
+        // Roll back the payloads of the entries SNS did not accept - all of
+        // them when the whole batch failed.
+        let unsent: Vec<&S3Pointer> = entry_ids
+            .iter()
+            .zip(&offloaded)
+            .filter(|(id, _)| match &result {
+                Err(_) => true,
+                Ok(output) => output.failed().iter().any(|entry| entry.id() == *id),
+            })
+            .filter_map(|(_, s3_pointer)| s3_pointer.as_ref())
+            .collect();
+
+        let leaked: Vec<S3Pointer> = self.inner.roll_back_payloads(unsent).await;
+
+        result.map_err(|err| err.with_context(ErrorContext::for_leaked_payloads(leaked)))
+    }
+
+    async fn offload_batch_entries(
+        &self,
+        bucket_name: &str,
+        topic_arn: &Option<String>,
+        entries: &mut [PublishBatchRequestEntry],
+        offloaded: &mut [Option<S3Pointer>],
+    ) -> Result<(), SqsExtendedClientError> {
+        for (i, entry) in entries.iter_mut().enumerate() {
+            if self.inner.always_through_s3 || self.entry_exceeds_threshold(entry) {
+                offloaded[i] = Some(
+                    self.offload_batch_entry(bucket_name, topic_arn, entry)
+                        .await?,
+                );
+            }
+        }
+
+        while entries
+            .iter()
+            .map(|entry| self.entry_size(entry))
+            .sum::<usize>()
+            > self.inner.batch_message_size_threshold
+        {
+            let largest: Option<usize> = entries
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| offloaded[*i].is_none())
+                .max_by_key(|(_, entry)| self.entry_size(entry))
+                .map(|(i, _)| i);
+
+            let Some(i) = largest else {
+                break;
+            };
+
+            offloaded[i] = Some(
+                self.offload_batch_entry(bucket_name, topic_arn, &mut entries[i])
+                    .await?,
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn offload_batch_entry(
+        &self,
+        bucket_name: &str,
+        topic_arn: &Option<String>,
+        entry: &mut PublishBatchRequestEntry,
+    ) -> Result<S3Pointer, SqsExtendedClientError> {
+        if is_json_message_structure(&entry.message_structure) {
+            return Err(SqsExtendedClientError::SnsJsonMessageStructure { context: None });
+        }
+
+        let sqs_attributes: Option<HashMap<String, aws_sdk_sqs::types::MessageAttributeValue>> =
+            entry.message_attributes.as_ref().map(to_sqs_attributes);
+
+        let key_context: KeyContext = KeyContext::new(
+            None,
+            entry.message_group_id.as_deref(),
+            sqs_attributes.as_ref(),
+            SystemTime::now(),
+        );
+
+        let s3_pointer: S3Pointer = self
+            .inner
+            .offload_payload(bucket_name.to_string(), &entry.message, &key_context)
+            .await?;
+
+        let reserved_attribute: aws_sdk_sns::types::MessageAttributeValue =
+            reserved_attribute_for(entry.message.len())?;
+
+        if let Some(deduplication_id) = self.inner.fifo_deduplication_id(
+            topic_arn,
+            &entry.message_deduplication_id,
+            &entry.message,
+        ) {
+            entry.message_deduplication_id = Some(deduplication_id);
+        }
+
+        entry.message = self.inner.pointer_codec.encode(&s3_pointer);
+        entry
+            .message_attributes
+            .get_or_insert_with(HashMap::new)
+            .insert(
+                self.inner.reserved_attributes[0].clone(),
+                reserved_attribute,
+            );
+
+        if let Some(checksum) = &s3_pointer.checksum {
+            entry
+                .message_attributes
+                .get_or_insert_with(HashMap::new)
+                .insert(
+                    CHECKSUM_ATTRIBUTE_NAME.to_string(),
+                    checksum_attribute_for(checksum)?,
+                );
+        }
+
+        Ok(s3_pointer)
+    }
+
+    fn entry_size(&self, entry: &PublishBatchRequestEntry) -> usize {
+        let sqs_attributes: Option<HashMap<String, aws_sdk_sqs::types::MessageAttributeValue>> =
+            entry.message_attributes.as_ref().map(to_sqs_attributes);
+
+        message_size(&entry.message, sqs_attributes.as_ref())
+    }
+
+    fn entry_exceeds_threshold(&self, entry: &PublishBatchRequestEntry) -> bool {
+        let sqs_attributes: Option<HashMap<String, aws_sdk_sqs::types::MessageAttributeValue>> =
+            entry.message_attributes.as_ref().map(to_sqs_attributes);
+
+        self.inner.message_exceeds_threshold(
+            &entry.message,
+            sqs_attributes.as_ref(),
+            self.inner.default_message_size_threshold(),
+        )
+    }
+}
+
+fn reserved_attribute_for(
+    payload_size: usize,
+) -> Result<aws_sdk_sns::types::MessageAttributeValue, aws_sdk_sns::error::BuildError> {
+    aws_sdk_sns::types::MessageAttributeValue::builder()
+        .data_type("Number")
+        .string_value(payload_size.to_string())
+        .build()
+}
+
+fn checksum_attribute_for(
+    checksum: &str,
+) -> Result<aws_sdk_sns::types::MessageAttributeValue, aws_sdk_sns::error::BuildError> {
+    aws_sdk_sns::types::MessageAttributeValue::builder()
+        .data_type("String")
+        .string_value(checksum)
+        .build()
+}
+
+// Per protocol messages cannot be swapped for a single S3 pointer.
+fn is_json_message_structure(message_structure: &Option<String>) -> bool {
+    message_structure.as_deref() == Some("json")
+}
+
+// SNS and SQS message attributes carry the same fields, so the SQS size
+// accounting and key strategies can be reused as is.
+fn to_sqs_attributes(
+    attributes: &HashMap<String, aws_sdk_sns::types::MessageAttributeValue>,
+) -> HashMap<String, aws_sdk_sqs::types::MessageAttributeValue> {
+    attributes
+        .iter()
+        .filter_map(|(name, value)| {
+            aws_sdk_sqs::types::MessageAttributeValue::builder()
+                .data_type(value.data_type.clone())
+                .set_string_value(value.string_value.clone())
+                .set_binary_value(value.binary_value.clone())
+                .build()
+                .ok()
+                .map(|sqs_value| (name.clone(), sqs_value))
+        })
+        .collect()
+}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_s3::config::BehaviorVersion;
+    use aws_smithy_http_client::test_util::{ReplayEvent, StaticReplayClient};
+    use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::Blob;
+    use aws_smithy_types::body::SdkBody;
+
+    use super::*;
+    use crate::SqsExtendedClientBuilder;
+    use crate::tests::{make_replay_event, make_replay_s3_client};
+
+    const TOPIC_ARN: &str = "arn:aws:sns:us-west-2:123456789012:orders";
+
+    fn make_replay_sns_client(http_client: &StaticReplayClient) -> aws_sdk_sns::Client {
+        aws_sdk_sns::Client::from_conf(
+            aws_sdk_sns::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .region(aws_sdk_sns::config::Region::new("us-west-2"))
+                .credentials_provider(aws_sdk_sns::config::Credentials::new(
+                    "TEST_ACCESS_KEY_ID",
+                    "TEST_SECRET_ACCESS_KEY",
+                    None,
+                    None,
+                    "",
+                ))
+                .http_client(http_client.clone())
+                .build(),
+        )
+    }
+
+    fn make_sns_error_event() -> ReplayEvent {
+        ReplayEvent::new(
+            HttpRequest::new(SdkBody::empty()),
+            HttpResponse::new(
+                StatusCode::try_from(404).expect("invalid status code"),
+                SdkBody::from(
+                    "<ErrorResponse><Error><Type>Sender</Type><Code>NotFound</Code>\
+                     <Message>no topic</Message></Error><RequestId>ID</RequestId></ErrorResponse>",
+                ),
+            ),
+        )
+    }
+
+    fn make_test_sns_client(s3_http_client: &StaticReplayClient) -> SnsExtendedClient {
+        SqsExtendedClientBuilder::new(make_replay_s3_client(s3_http_client))
+            .with_s3_bucket_name("bucket-name".to_string())
+            .with_message_size_threshold(10)
+            .build_sns()
+    }
+
+    fn request_body(request: &HttpRequest) -> String {
+        String::from_utf8_lossy(request.body().bytes().expect("streaming body")).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_publish_offloads_payload() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event("")]);
+        let sns_http_client: StaticReplayClient = StaticReplayClient::new(vec![make_replay_event(
+            "<PublishResponse><PublishResult><MessageId>ID</MessageId></PublishResult>\
+             </PublishResponse>",
+        )]);
+
+        let output: PublishOutput = make_test_sns_client(&s3_http_client)
+            .publish(
+                make_replay_sns_client(&sns_http_client)
+                    .publish()
+                    .topic_arn(TOPIC_ARN)
+                    .message("a message over the threshold"),
+            )
+            .await
+            .expect("publish failed");
+
+        assert_eq!(Some("ID"), output.message_id());
+
+        let s3_requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+        assert_eq!(1, s3_requests.len());
+        assert_eq!("PUT", s3_requests[0].method());
+
+        let sns_requests: Vec<&HttpRequest> = sns_http_client.actual_requests().collect();
+        let body: String = request_body(sns_requests[0]);
+        assert!(body.contains(
+            "Message=%5B%22software.amazon.payloadoffloading.PayloadS3Pointer%22%2C%7B%22s3BucketName%22%3A%22bucket-name%22"
+        ));
+        assert!(body.contains("Name=ExtendedPayloadSize"));
+        assert!(body.contains("StringValue=28"));
+    }
+
+    #[tokio::test]
+    async fn test_publish_rolls_back_payload() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(""), make_replay_event("")]);
+        let sns_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_sns_error_event()]);
+
+        let result: Result<PublishOutput, SqsExtendedClientError> =
+            make_test_sns_client(&s3_http_client)
+                .publish(
+                    make_replay_sns_client(&sns_http_client)
+                        .publish()
+                        .topic_arn(TOPIC_ARN)
+                        .message("a message over the threshold"),
+                )
+                .await;
+
+        let err: SqsExtendedClientError = result.expect_err("publish should fail");
+        assert!(matches!(err, SqsExtendedClientError::SnsPublish(_)));
+
+        let context: &ErrorContext = err.context().expect("no context");
+        assert_eq!(Some("publish"), context.operation());
+        assert_eq!(Some(TOPIC_ARN), context.topic_arn());
+
+        let requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+        assert_eq!(2, requests.len());
+        assert_eq!("PUT", requests[0].method());
+        assert_eq!("DELETE", requests[1].method());
+        assert_eq!(
+            requests[0].uri().split_once('?').map(|(path, _)| path),
+            requests[1].uri().split_once('?').map(|(path, _)| path)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_publish_batch_rolls_back_failed_entries() {
+        let s3_http_client: StaticReplayClient = StaticReplayClient::new(vec![
+            make_replay_event(""),
+            make_replay_event(""),
+            make_replay_event(""),
+        ]);
+        let sns_http_client: StaticReplayClient = StaticReplayClient::new(vec![make_replay_event(
+            "<PublishBatchResponse><PublishBatchResult>\
+             <Successful><member><Id>1</Id><MessageId>ID</MessageId></member></Successful>\
+             <Failed><member><Id>2</Id><Code>Throttled</Code><SenderFault>false</SenderFault></member></Failed>\
+             </PublishBatchResult></PublishBatchResponse>",
+        )]);
+
+        let entries: Vec<PublishBatchRequestEntry> = ["1", "2"]
+            .into_iter()
+            .map(|id| {
+                PublishBatchRequestEntry::builder()
+                    .id(id)
+                    .message(format!("message {} over the threshold", id))
+                    .build()
+                    .expect("Failed to build PublishBatchRequestEntry")
+            })
+            .collect();
+
+        let output: PublishBatchOutput = make_test_sns_client(&s3_http_client)
+            .publish_batch(
+                make_replay_sns_client(&sns_http_client)
+                    .publish_batch()
+                    .topic_arn(TOPIC_ARN)
+                    .set_publish_batch_request_entries(Some(entries)),
+            )
+            .await
+            .expect("publish_batch failed");
+
+        assert_eq!(1, output.failed().len());
+
+        let sns_requests: Vec<&HttpRequest> = sns_http_client.actual_requests().collect();
+        assert!(request_body(sns_requests[0]).contains("Name=ExtendedPayloadSize"));
+
+        let requests: Vec<&HttpRequest> = s3_http_client.actual_requests().collect();
+        assert_eq!(3, requests.len());
+        assert_eq!("PUT", requests[0].method());
+        assert_eq!("PUT", requests[1].method());
+        assert_eq!("DELETE", requests[2].method());
+        assert_eq!(
+            requests[1].uri().split_once('?').map(|(path, _)| path),
+            requests[2].uri().split_once('?').map(|(path, _)| path)
+        );
+    }
+
+    #[test]
+    fn test_to_sqs_attributes() {
+        let tenant: aws_sdk_sns::types::MessageAttributeValue =
+            aws_sdk_sns::types::MessageAttributeValue::builder()
+                .data_type("String")
+                .string_value("acme")
+                .build()
+                .expect("Failed to build MessageAttributeValue");
+
+        let binary: aws_sdk_sns::types::MessageAttributeValue =
+            aws_sdk_sns::types::MessageAttributeValue::builder()
+                .data_type("Binary")
+                .binary_value(Blob::new("IT'S BINARY HONEST"))
+                .build()
+                .expect("Failed to build MessageAttributeValue");
+
+        let attributes: HashMap<String, aws_sdk_sns::types::MessageAttributeValue> =
+            HashMap::from([
+                ("TenantId".to_string(), tenant),
+                ("Raw".to_string(), binary),
+            ]);
+
+        let sqs_attributes: HashMap<String, aws_sdk_sqs::types::MessageAttributeValue> =
+            to_sqs_attributes(&attributes);
+
+        assert_eq!("String", sqs_attributes["TenantId"].data_type);
+        assert_eq!(
+            Some("acme".to_string()),
+            sqs_attributes["TenantId"].string_value
+        );
+        assert_eq!("Binary", sqs_attributes["Raw"].data_type);
+        assert_eq!(
+            Some(Blob::new("IT'S BINARY HONEST")),
+            sqs_attributes["Raw"].binary_value
+        );
+    }
+
+    #[test]
+    fn test_is_json_message_structure() {
+        assert!(is_json_message_structure(&Some("json".to_string())));
+        assert!(!is_json_message_structure(&None));
+    }
+
+    #[test]
+    fn test_reserved_attribute_for() {
+        let reserved_attribute: aws_sdk_sns::types::MessageAttributeValue =
+            reserved_attribute_for(59).expect("Failed to build MessageAttributeValue");
+
+        assert_eq!("Number", reserved_attribute.data_type);
+        assert_eq!(Some("59".to_string()), reserved_attribute.string_value);
+    }
+}
@@ -0,0 +1,563 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::{S3Pointer, SqsExtendedClientError};
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{Instrument, Span};
+
+//-SPANS------------------------------------------------------------------------
+
+// A span per client operation. The fields are recorded as the operation learns
+// them - `Span::current()` inside the instrumented future is this span.
+macro_rules! operation_span {
+    ($name:literal, $queue_url:expr) => {{
+        #[cfg(feature = "tracing")]
+        {
+            tracing::info_span!(
+                $name,
+                queue_url = $queue_url.unwrap_or_default(),
+                message_id = tracing::field::Empty,
+                message_count = tracing::field::Empty,
+                offloaded = tracing::field::Empty,
+                payload_bytes = tracing::field::Empty,
+            )
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            let _ = $queue_url;
+            $crate::telemetry::Span
+        }
+    }};
+}
+
+// A child span per S3 request that moves or removes a payload.
+macro_rules! s3_transfer_span {
+    ($name:literal, $s3_pointer:expr, $message_id:expr) => {{
+        #[cfg(feature = "tracing")]
+        {
+            tracing::info_span!(
+                $name,
+                bucket = $s3_pointer.bucket_name(),
+                key = $s3_pointer.key(),
+                message_id = $message_id.unwrap_or_default(),
+                payload_bytes = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+            )
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            let _ = ($s3_pointer, $message_id);
+            $crate::telemetry::Span
+        }
+    }};
+}
+
+pub(crate) use {operation_span, s3_transfer_span};
+
+// Runs an S3 request in its span and records how long it took, on the span and
+// as a metric labelled with the queue the payload belongs to, where known.
+pub(crate) async fn timed<T>(
+    span: Span,
+    operation: &'static str,
+    queue_url: Option<&str>,
+    bucket: &str,
+    request: impl Future<Output = T>,
+) -> T {
+    let started: Instant = Instant::now();
+    let output: T = request.instrument(span.clone()).await;
+    let elapsed: Duration = started.elapsed();
+
+    span.record("latency_ms", elapsed.as_millis() as u64);
+    record_s3_latency(operation, queue_url, bucket, elapsed);
+
+    output
+}
+
+//-METRICS----------------------------------------------------------------------
+
+// Recorded through the `metrics` facade - the application installs a recorder,
+// e.g. a Prometheus exporter. Without the `metrics` feature these do nothing.
+#[cfg(feature = "metrics")]
+mod names {
+    pub(super) const MESSAGES_SENT: &str = "sqs_extended_client_messages_sent_total";
+    pub(super) const MESSAGES_RECEIVED: &str = "sqs_extended_client_messages_received_total";
+    pub(super) const PAYLOAD_BYTES: &str = "sqs_extended_client_payload_bytes_total";
+    pub(super) const PAYLOAD_SIZE: &str = "sqs_extended_client_payload_size_bytes";
+    pub(super) const S3_LATENCY: &str = "sqs_extended_client_s3_request_duration_seconds";
+    pub(super) const ORPHANS_DELETED: &str = "sqs_extended_client_orphaned_payloads_deleted_total";
+    pub(super) const POINTER_PARSE_FAILURES: &str =
+        "sqs_extended_client_pointer_parse_failures_total";
+}
+
+// The queue and bucket labels, where they are known.
+#[cfg(feature = "metrics")]
+fn labels(queue_url: Option<&str>, bucket: Option<&str>) -> Vec<metrics::Label> {
+    [("queue", queue_url), ("bucket", bucket)]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| metrics::Label::new(name, value.to_string())))
+        .collect()
+}
+
+// A message is offloaded when it has a bucket.
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn record_message_sent(queue_url: Option<&str>, bucket: Option<&str>) {
+    #[cfg(feature = "metrics")]
+    {
+        let mut labels: Vec<metrics::Label> = labels(queue_url, bucket);
+        labels.push(metrics::Label::new(
+            "offloaded",
+            bucket.is_some().to_string(),
+        ));
+
+        metrics::counter!(names::MESSAGES_SENT, labels).increment(1);
+    }
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn record_message_received(queue_url: Option<&str>, bucket: Option<&str>) {
+    #[cfg(feature = "metrics")]
+    {
+        let mut labels: Vec<metrics::Label> = labels(queue_url, bucket);
+        labels.push(metrics::Label::new(
+            "offloaded",
+            bucket.is_some().to_string(),
+        ));
+
+        metrics::counter!(names::MESSAGES_RECEIVED, labels).increment(1);
+    }
+}
+
+// `direction` is "upload" or "download".
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn record_payload_transfer(
+    direction: &'static str,
+    queue_url: Option<&str>,
+    bucket: &str,
+    bytes: usize,
+) {
+    #[cfg(feature = "metrics")]
+    {
+        let mut labels: Vec<metrics::Label> = labels(queue_url, Some(bucket));
+        labels.push(metrics::Label::new("direction", direction));
+
+        metrics::counter!(names::PAYLOAD_BYTES, labels.clone()).increment(bytes as u64);
+        metrics::histogram!(names::PAYLOAD_SIZE, labels).record(bytes as f64);
+    }
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+fn record_s3_latency(
+    operation: &'static str,
+    queue_url: Option<&str>,
+    bucket: &str,
+    elapsed: Duration,
+) {
+    #[cfg(feature = "metrics")]
+    {
+        let mut labels: Vec<metrics::Label> = labels(queue_url, Some(bucket));
+        labels.push(metrics::Label::new("operation", operation));
+
+        metrics::histogram!(names::S3_LATENCY, labels).record(elapsed.as_secs_f64());
+    }
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn record_orphans_deleted(queue_url: Option<&str>, bucket: &str, deleted: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(names::ORPHANS_DELETED, labels(queue_url, Some(bucket)))
+        .increment(deleted as u64);
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn record_pointer_parse_failure(queue_url: Option<&str>) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(names::POINTER_PARSE_FAILURES, labels(queue_url, None)).increment(1);
+}
+
+//-WARNINGS---------------------------------------------------------------------
+
+// Emitted as `tracing` events. Without the `tracing` feature these do nothing.
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn warn_queue_size_lookup_failed(queue_url: &str, error: &SqsExtendedClientError) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        queue_url,
+        error = %error,
+        "MaximumMessageSize lookup failed - using the default threshold"
+    );
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn warn_payload_rollback_failed(s3_pointer: &S3Pointer, error: &SqsExtendedClientError) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        bucket = s3_pointer.bucket_name(),
+        key = s3_pointer.key(),
+        error = %error,
+        "Payload of an unsent message could not be rolled back - it is left to reconcile_payloads"
+    );
+}
+
+#[cfg(feature = "lambda")]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn warn_payload_release_failed(s3_pointer: &S3Pointer, error: &SqsExtendedClientError) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        bucket = s3_pointer.bucket_name(),
+        key = s3_pointer.key(),
+        error = %error,
+        "Payload of a handled message could not be released - it is left to reconcile_payloads"
+    );
+}
+
+//-NO-OP SPANS------------------------------------------------------------------
+
+// Without the `tracing` feature spans compile away to these.
+
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone)]
+pub(crate) struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub(crate) fn current() -> Span {
+        Span
+    }
+
+    pub(crate) fn record<V>(&self, _field: &str, _value: V) -> &Span {
+        self
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) trait Instrument: Sized {
+    fn instrument(self, _span: Span) -> Self {
+        self
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl<T: Future> Instrument for T {}
+
+//-TESTS------------------------------------------------------------------------
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+
+    use aws_smithy_http_client::test_util::StaticReplayClient;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+    use tracing_core::span::Current;
+
+    use crate::tests::{make_replay_event, make_replay_s3_client, make_replay_sqs_client};
+    use crate::{SqsExtendedClient, SqsExtendedClientBuilder};
+
+    static QUEUE_URL: &str = "https://sqs.us-west-2.amazonaws.com/123456789012/orders";
+
+    // Collects the fields of every span by span name, and tracks the entered
+    // spans so `Span::current()` works.
+    #[derive(Clone, Default)]
+    struct SpanRecorder {
+        spans: Arc<Mutex<Vec<&'static Metadata<'static>>>>,
+        entered: Arc<Mutex<Vec<Id>>>,
+        fields: Arc<Mutex<HashMap<&'static str, HashMap<String, String>>>>,
+    }
+
+    struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl Subscriber for SpanRecorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(span.metadata());
+
+            let mut fields = self.fields.lock().unwrap();
+            span.record(&mut FieldVisitor(
+                fields.entry(span.metadata().name()).or_default(),
+            ));
+
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let name: &'static str =
+                self.spans.lock().unwrap()[span.into_u64() as usize - 1].name();
+            let mut fields = self.fields.lock().unwrap();
+            values.record(&mut FieldVisitor(fields.entry(name).or_default()));
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, span: &Id) {
+            self.entered.lock().unwrap().push(span.clone());
+        }
+
+        fn exit(&self, _span: &Id) {
+            self.entered.lock().unwrap().pop();
+        }
+
+        fn current_span(&self) -> Current {
+            match self.entered.lock().unwrap().last() {
+                Some(span) => Current::new(
+                    span.clone(),
+                    self.spans.lock().unwrap()[span.into_u64() as usize - 1],
+                ),
+                None => Current::none(),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_message_spans() {
+        let recorder: SpanRecorder = SpanRecorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event("")]);
+        let sqs_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(r#"{"MessageId":"MESSAGE_ID"}"#)]);
+
+        let sqs_extended_client: SqsExtendedClient =
+            SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                .with_s3_bucket_name("payloads".to_string())
+                .with_always_through_s3(true)
+                .build();
+
+        sqs_extended_client
+            .send_message(
+                make_replay_sqs_client(&sqs_http_client)
+                    .send_message()
+                    .queue_url(QUEUE_URL)
+                    .message_body("hello world"),
+            )
+            .await
+            .expect("send failed");
+
+        let fields = recorder.fields.lock().unwrap();
+
+        let send_message: &HashMap<String, String> = &fields["send_message"];
+        assert_eq!(QUEUE_URL, send_message["queue_url"]);
+        assert_eq!("MESSAGE_ID", send_message["message_id"]);
+        assert_eq!("true", send_message["offloaded"]);
+        assert_eq!("11", send_message["payload_bytes"]);
+
+        let put_object: &HashMap<String, String> = &fields["s3_put_object"];
+        assert_eq!("payloads", put_object["bucket"]);
+        assert_eq!("11", put_object["payload_bytes"]);
+        assert!(put_object.contains_key("latency_ms"));
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod metrics_tests {
+    use std::future::Future;
+
+    use aws_smithy_http_client::test_util::StaticReplayClient;
+    use metrics::Label;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    use crate::tests::{
+        make_replay_event, make_replay_s3_client, make_replay_sqs_client, make_test_s3_client,
+    };
+    use crate::{SqsExtendedClient, SqsExtendedClientBuilder};
+
+    static QUEUE_URL: &str = "https://sqs.us-west-2.amazonaws.com/123456789012/orders";
+
+    // Runs `f` with a recorder of its own and returns what it recorded by
+    // metric name and labels.
+    fn record<F: Future>(f: F) -> Vec<(String, Vec<Label>, DebugValue)> {
+        let recorder: DebuggingRecorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        metrics::with_local_recorder(&recorder, || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("failed to build runtime")
+                .block_on(f)
+        });
+
+        snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let (key, labels) = key.key().clone().into_parts();
+                let mut labels: Vec<Label> = labels;
+                labels.sort();
+                (key.as_str().to_string(), labels, value)
+            })
+            .collect()
+    }
+
+    fn find<'a>(
+        metrics: &'a [(String, Vec<Label>, DebugValue)],
+        name: &str,
+    ) -> (&'a [Label], &'a DebugValue) {
+        metrics
+            .iter()
+            .find(|(key, _, _)| key == name)
+            .map(|(_, labels, value)| (labels.as_slice(), value))
+            .unwrap_or_else(|| panic!("no metric {}", name))
+    }
+
+    #[test]
+    fn test_send_message_metrics() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event("")]);
+        let sqs_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event(r#"{"MessageId":"MESSAGE_ID"}"#)]);
+
+        let metrics = record(async {
+            let sqs_extended_client: SqsExtendedClient =
+                SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client))
+                    .with_s3_bucket_name("payloads".to_string())
+                    .with_always_through_s3(true)
+                    .build();
+
+            sqs_extended_client
+                .send_message(
+                    make_replay_sqs_client(&sqs_http_client)
+                        .send_message()
+                        .queue_url(QUEUE_URL)
+                        .message_body("hello world"),
+                )
+                .await
+                .expect("send failed");
+        });
+
+        let (labels, value) = find(&metrics, "sqs_extended_client_messages_sent_total");
+        assert_eq!(
+            vec![
+                Label::new("bucket", "payloads"),
+                Label::new("offloaded", "true"),
+                Label::new("queue", QUEUE_URL),
+            ],
+            labels
+        );
+        assert_eq!(&DebugValue::Counter(1), value);
+
+        let (labels, value) = find(&metrics, "sqs_extended_client_payload_bytes_total");
+        assert_eq!(
+            vec![
+                Label::new("bucket", "payloads"),
+                Label::new("direction", "upload"),
+                Label::new("queue", QUEUE_URL),
+            ],
+            labels
+        );
+        assert_eq!(&DebugValue::Counter(11), value);
+
+        let (labels, value) = find(&metrics, "sqs_extended_client_s3_request_duration_seconds");
+        assert_eq!(
+            vec![
+                Label::new("bucket", "payloads"),
+                Label::new("operation", "PutObject"),
+                Label::new("queue", QUEUE_URL),
+            ],
+            labels
+        );
+        assert!(matches!(value, DebugValue::Histogram(values) if values.len() == 1));
+    }
+
+    #[test]
+    fn test_receive_message_metrics() {
+        let s3_http_client: StaticReplayClient =
+            StaticReplayClient::new(vec![make_replay_event("hello world")]);
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![make_replay_event(
+            r#"{"Messages":[{
+                    "MessageId":"MESSAGE_ID",
+                    "ReceiptHandle":"RECEIPT_HANDLE",
+                    "Body":"[\"software.amazon.payloadoffloading.PayloadS3Pointer\",{\"s3BucketName\":\"payloads\",\"s3Key\":\"KEY\"}]",
+                    "MessageAttributes":{"ExtendedPayloadSize":{"DataType":"Number","StringValue":"11"}}
+                }]}"#,
+        )]);
+
+        let metrics = record(async {
+            let sqs_extended_client: SqsExtendedClient =
+                SqsExtendedClientBuilder::new(make_replay_s3_client(&s3_http_client)).build();
+
+            sqs_extended_client
+                .receive_message(
+                    make_replay_sqs_client(&sqs_http_client)
+                        .receive_message()
+                        .queue_url(QUEUE_URL),
+                )
+                .await
+                .expect("receive failed");
+        });
+
+        let (labels, value) = find(&metrics, "sqs_extended_client_payload_bytes_total");
+        assert_eq!(
+            vec![
+                Label::new("bucket", "payloads"),
+                Label::new("direction", "download"),
+                Label::new("queue", QUEUE_URL),
+            ],
+            labels
+        );
+        assert_eq!(&DebugValue::Counter(11), value);
+
+        let (labels, _) = find(&metrics, "sqs_extended_client_s3_request_duration_seconds");
+        assert_eq!(
+            vec![
+                Label::new("bucket", "payloads"),
+                Label::new("operation", "GetObject"),
+                Label::new("queue", QUEUE_URL),
+            ],
+            labels
+        );
+    }
+
+    #[test]
+    fn test_pointer_parse_failure_metric() {
+        let sqs_http_client: StaticReplayClient = StaticReplayClient::new(vec![make_replay_event(
+            r#"{"Messages":[{
+                    "MessageId":"MESSAGE_ID",
+                    "ReceiptHandle":"RECEIPT_HANDLE",
+                    "Body":"not a pointer",
+                    "MessageAttributes":{"ExtendedPayloadSize":{"DataType":"Number","StringValue":"300000"}}
+                }]}"#,
+        )]);
+
+        let metrics = record(async {
+            let sqs_extended_client: SqsExtendedClient =
+                SqsExtendedClientBuilder::new(make_test_s3_client()).build();
+
+            sqs_extended_client
+                .receive_message(
+                    make_replay_sqs_client(&sqs_http_client)
+                        .receive_message()
+                        .queue_url(QUEUE_URL),
+                )
+                .await
+                .expect_err("receive should fail");
+        });
+
+        let (labels, value) = find(&metrics, "sqs_extended_client_pointer_parse_failures_total");
+        assert_eq!(vec![Label::new("queue", QUEUE_URL)], labels);
+        assert_eq!(&DebugValue::Counter(1), value);
+    }
+}
@@ -4,12 +4,13 @@ use std::str::from_utf8;
 use aws_config::{BehaviorVersion, Region, meta::region::RegionProviderChain};
 use aws_sdk_s3::operation::get_object::GetObjectOutput;
 use aws_sdk_s3::operation::list_buckets::ListBucketsOutput;
-use aws_sdk_sqs::operation::change_message_visibility;
 use aws_sdk_sqs::operation::change_message_visibility::builders::ChangeMessageVisibilityFluentBuilder;
 use aws_sdk_sqs::operation::delete_message::builders::DeleteMessageFluentBuilder;
 use aws_sdk_sqs::operation::list_queues::ListQueuesOutput;
-use aws_sdk_sqs::operation::receive_message::ReceiveMessageOutput;
-use aws_sdk_sqs::types::Message;
+use aws_sdk_sqs::operation::send_message_batch::builders::SendMessageBatchFluentBuilder;
+use aws_sdk_sqs::types::{
+    MessageSystemAttributeName, QueueAttributeName, SendMessageBatchRequestEntry,
+};
 use aws_sdk_sqs::{
     self,
     operation::{
@@ -18,7 +19,12 @@ use aws_sdk_sqs::{
     },
     types::MessageAttributeValue,
 };
-use sqs_extended_client::{SqsExtendedClient, SqsExtendedClientBuilder};
+#[cfg(feature = "sns")]
+use sqs_extended_client::SnsExtendedClient;
+use sqs_extended_client::{
+    ATTRIBUTES_ATTRIBUTE_NAME, ExtendedMessage, PayloadChecksum, PointerFormat, S3Pointer,
+    SqsExtendedClient, SqsExtendedClientBuilder, SqsExtendedClientError,
+};
 use testcontainers_modules::{
     localstack::{self, LocalStack},
     testcontainers::{ContainerAsync, ImageExt, runners::AsyncRunner},
@@ -46,13 +52,11 @@ async fn send_message_always_through_s3() -> Result<(), Box<dyn std::error::Erro
     let receive_msg: ReceiveMessageFluentBuilder =
         receive_sqs_client.receive_message().queue_url(&queue_url);
 
-    let response: ReceiveMessageOutput = sqs_extended_client.receive_message(receive_msg).await?;
-
-    let msgs: Vec<Message> = response.messages.clone().unwrap_or_default();
+    let msgs: Vec<ExtendedMessage> = sqs_extended_client.receive_message(receive_msg).await?;
 
     assert_eq!(msgs.len(), 1);
     assert_eq!(
-        msgs[0].body.as_ref().unwrap(),
+        msgs[0].payload(),
         "hello SQS! with love from the sqs-extended-client-rust 😊"
     );
 
@@ -62,30 +66,20 @@ async fn send_message_always_through_s3() -> Result<(), Box<dyn std::error::Erro
         .build()?;
 
     let attributes: HashMap<String, MessageAttributeValue> =
-        msgs[0].message_attributes.clone().unwrap();
+        msgs[0].message().message_attributes.clone().unwrap();
 
     let expected_attributes: HashMap<String, MessageAttributeValue> =
         HashMap::from([("ExtendedPayloadSize".to_string(), reserved_attribute)]);
 
     assert_eq!(attributes, expected_attributes);
 
-    // Check the receipt handle - and use the values to check for the S3 Object!
-    let receipt_handle = msgs[0].receipt_handle.clone().unwrap();
-
-    let s3_bucket: &str = receipt_handle
-        .split("-..s3BucketName..-")
-        .nth(1)
-        .unwrap()
-        .split("-..s3BucketName..-")
-        .next()
-        .unwrap();
-    let s3_key: &str = receipt_handle
-        .split("-..s3Key..-")
-        .nth(1)
-        .unwrap()
-        .split("-..s3Key..-")
-        .next()
-        .unwrap();
+    assert_eq!(msgs[0].payload_size(), Some(59));
+
+    // Check the S3 pointer - and use the values to check for the S3 Object!
+    let s3_pointer: &S3Pointer = msgs[0].s3_pointer().unwrap();
+
+    let s3_bucket: &str = s3_pointer.bucket_name();
+    let s3_key: &str = s3_pointer.key();
 
     // get the file contents from s3 as a string:
     let list_objects_output = s3_client
@@ -174,17 +168,15 @@ async fn send_receive_and_delete_small_message() -> Result<(), Box<dyn std::erro
     let receive_msg: ReceiveMessageFluentBuilder =
         receive_sqs_client.receive_message().queue_url(&queue_url);
 
-    let response: ReceiveMessageOutput = sqs_extended_client.receive_message(receive_msg).await?;
-
-    let msgs: Vec<Message> = response.messages.clone().unwrap_or_default();
+    let msgs: Vec<ExtendedMessage> = sqs_extended_client.receive_message(receive_msg).await?;
 
     assert_eq!(msgs.len(), 1);
     assert_eq!(
-        msgs[0].body.as_ref().unwrap(),
+        msgs[0].payload(),
         "hello SQS! with love from the sqs-extended-client-rust 😊"
     );
 
-    let receipt_handle = msgs[0].receipt_handle.clone().unwrap();
+    let receipt_handle = msgs[0].extended_receipt_handle();
 
     // delete the message
     let delete_message_input: DeleteMessageFluentBuilder = delete_sqs_client
@@ -275,17 +267,15 @@ async fn send_receive_and_delete_large_message() -> Result<(), Box<dyn std::erro
     let receive_msg: ReceiveMessageFluentBuilder =
         receive_sqs_client.receive_message().queue_url(&queue_url);
 
-    let response: ReceiveMessageOutput = sqs_extended_client.receive_message(receive_msg).await?;
-
-    let msgs: Vec<Message> = response.messages.clone().unwrap_or_default();
+    let msgs: Vec<ExtendedMessage> = sqs_extended_client.receive_message(receive_msg).await?;
 
     assert_eq!(msgs.len(), 1);
     assert_eq!(
-        msgs[0].body.as_ref().unwrap(),
+        msgs[0].payload(),
         "hello SQS! with love from the sqs-extended-client-rust 😊"
     );
 
-    let receipt_handle = msgs[0].receipt_handle.clone().unwrap();
+    let receipt_handle = msgs[0].extended_receipt_handle();
 
     // delete the message
     let delete_message_input: DeleteMessageFluentBuilder = delete_sqs_client
@@ -331,6 +321,67 @@ async fn send_receive_and_delete_large_message() -> Result<(), Box<dyn std::erro
     Ok(())
 }
 
+#[tokio::test]
+async fn receive_and_delete_extended_message() -> Result<(), Box<dyn std::error::Error + 'static>> {
+    let (node, _endpoint_url, queue_url, s3_client, sqs_client) =
+        create_localstack_with_bucket_and_queue().await?;
+
+    let sqs_extended_client: SqsExtendedClient = SqsExtendedClientBuilder::new(s3_client.clone())
+        .with_s3_bucket_name("sqs-extended-client-bucket".to_string())
+        .with_message_size_threshold(3)
+        .build();
+
+    let msg_input: SendMessageFluentBuilder = sqs_client
+        .send_message()
+        .queue_url(&queue_url)
+        .message_body("hello SQS! with love from the sqs-extended-client-rust 😊");
+
+    sqs_extended_client.send_message(msg_input).await?;
+
+    let receive_msg: ReceiveMessageFluentBuilder =
+        sqs_client.receive_message().queue_url(&queue_url);
+
+    let msgs: Vec<ExtendedMessage> = sqs_extended_client.receive_message(receive_msg).await?;
+
+    assert_eq!(msgs.len(), 1);
+    assert!(msgs[0].is_offloaded());
+    assert_eq!(msgs[0].payload_size(), Some(59));
+    assert_ne!(msgs[0].message().body.as_deref(), Some(msgs[0].payload()));
+    assert_eq!(
+        msgs[0].message().receipt_handle.as_deref(),
+        Some(msgs[0].receipt_handle())
+    );
+
+    let change_message_visibility: ChangeMessageVisibilityFluentBuilder = sqs_client
+        .change_message_visibility()
+        .queue_url(&queue_url)
+        .visibility_timeout(30);
+
+    sqs_extended_client
+        .change_extended_message_visibility(change_message_visibility, &msgs[0])
+        .await?;
+
+    let delete_message_input: DeleteMessageFluentBuilder =
+        sqs_client.delete_message().queue_url(&queue_url);
+
+    sqs_extended_client
+        .delete_extended_message(delete_message_input, &msgs[0])
+        .await?;
+
+    // Check we don't have anything in S3
+    let list_objects_output = s3_client
+        .list_objects_v2()
+        .bucket("sqs-extended-client-bucket")
+        .send()
+        .await?;
+
+    assert_eq!(list_objects_output.contents().len(), 0);
+
+    let _rm = node.rm();
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn change_message_visibility_large_message() -> Result<(), Box<dyn std::error::Error + 'static>> {
     let (node, _endpoint_url, queue_url, s3_client, sqs_client) =
@@ -375,17 +426,15 @@ async fn change_message_visibility_large_message() -> Result<(), Box<dyn std::er
     let receive_msg: ReceiveMessageFluentBuilder =
         receive_sqs_client.receive_message().queue_url(&queue_url);
 
-    let response: ReceiveMessageOutput = sqs_extended_client.receive_message(receive_msg).await?;
-
-    let msgs: Vec<Message> = response.messages.clone().unwrap_or_default();
+    let msgs: Vec<ExtendedMessage> = sqs_extended_client.receive_message(receive_msg).await?;
 
     assert_eq!(msgs.len(), 1);
     assert_eq!(
-        msgs[0].body.as_ref().unwrap(),
+        msgs[0].payload(),
         "hello SQS! with love from the sqs-extended-client-rust 😊"
     );
 
-    let receipt_handle = msgs[0].receipt_handle.clone().unwrap();
+    let receipt_handle = msgs[0].extended_receipt_handle();
 
     // change message visibility 
     let change_message_visibility: ChangeMessageVisibilityFluentBuilder = cmv_sqs_client
@@ -416,6 +465,8 @@ async fn change_message_visibility_large_message() -> Result<(), Box<dyn std::er
 
     assert_eq!(number_of_messages, 0);
 
+    let _rm = node.rm();
+
     Ok(())
 }
 
@@ -463,17 +514,15 @@ async fn change_message_visibility_small_message() -> Result<(), Box<dyn std::er
     let receive_msg: ReceiveMessageFluentBuilder =
         receive_sqs_client.receive_message().queue_url(&queue_url);
 
-    let response: ReceiveMessageOutput = sqs_extended_client.receive_message(receive_msg).await?;
-
-    let msgs: Vec<Message> = response.messages.clone().unwrap_or_default();
+    let msgs: Vec<ExtendedMessage> = sqs_extended_client.receive_message(receive_msg).await?;
 
     assert_eq!(msgs.len(), 1);
     assert_eq!(
-        msgs[0].body.as_ref().unwrap(),
+        msgs[0].payload(),
         "hello SQS! with love from the sqs-extended-client-rust 😊"
     );
 
-    let receipt_handle = msgs[0].receipt_handle.clone().unwrap();
+    let receipt_handle = msgs[0].extended_receipt_handle();
 
     // change message visibility 
     let change_message_visibility: ChangeMessageVisibilityFluentBuilder = cmv_sqs_client
@@ -504,6 +553,350 @@ async fn change_message_visibility_small_message() -> Result<(), Box<dyn std::er
 
     assert_eq!(number_of_messages, 0);
 
+    let _rm = node.rm();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn receive_message_sent_with_json_object_pointer()
+-> Result<(), Box<dyn std::error::Error + 'static>> {
+    let (node, _endpoint_url, queue_url, s3_client, sqs_client) =
+        create_localstack_with_bucket_and_queue().await?;
+
+    // as written by the Python and Node.js extended clients
+    let send_extended_client: SqsExtendedClient = SqsExtendedClientBuilder::new(s3_client.clone())
+        .with_s3_bucket_name("sqs-extended-client-bucket".to_string())
+        .with_always_through_s3(true)
+        .with_pointer_format(PointerFormat::JsonObject)
+        .with_reserved_attribute_names(vec!["SQSLargePayloadSize".to_string()])
+        .build();
+
+    let msg_input: SendMessageFluentBuilder = sqs_client
+        .send_message()
+        .queue_url(&queue_url)
+        .message_body("hello SQS! with love from the sqs-extended-client-rust 😊");
+
+    send_extended_client.send_message(msg_input).await?;
+
+    let receive_extended_client: SqsExtendedClient =
+        SqsExtendedClientBuilder::new(s3_client.clone()).build();
+
+    let receive_msg: ReceiveMessageFluentBuilder =
+        sqs_client.receive_message().queue_url(&queue_url);
+
+    let msgs: Vec<ExtendedMessage> = receive_extended_client.receive_message(receive_msg).await?;
+
+    assert_eq!(msgs.len(), 1);
+    assert!(msgs[0].is_offloaded());
+    assert!(
+        msgs[0]
+            .message()
+            .body
+            .as_deref()
+            .unwrap_or_default()
+            .starts_with(r#"{"s3BucketName":"sqs-extended-client-bucket","s3Key":""#)
+    );
+    assert_eq!(
+        msgs[0].payload(),
+        "hello SQS! with love from the sqs-extended-client-rust 😊"
+    );
+
+    let _rm = node.rm();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn receive_message_verifies_payload_checksum()
+-> Result<(), Box<dyn std::error::Error + 'static>> {
+    let (node, _endpoint_url, queue_url, s3_client, sqs_client) =
+        create_localstack_with_bucket_and_queue().await?;
+
+    let sqs_extended_client: SqsExtendedClient = SqsExtendedClientBuilder::new(s3_client.clone())
+        .with_s3_bucket_name("sqs-extended-client-bucket".to_string())
+        .with_always_through_s3(true)
+        .with_payload_checksum(PayloadChecksum::Crc32c)
+        .build();
+
+    let msg_input: SendMessageFluentBuilder = sqs_client
+        .send_message()
+        .queue_url(&queue_url)
+        .message_body("hello SQS! with love from the sqs-extended-client-rust 😊");
+
+    sqs_extended_client.send_message(msg_input).await?;
+
+    let receive_msg: ReceiveMessageFluentBuilder = sqs_client
+        .receive_message()
+        .queue_url(&queue_url)
+        .visibility_timeout(0);
+
+    let msgs: Vec<ExtendedMessage> = sqs_extended_client.receive_message(receive_msg).await?;
+
+    assert_eq!(msgs.len(), 1);
+
+    let s3_pointer: &S3Pointer = msgs[0].s3_pointer().unwrap();
+
+    assert_eq!(
+        s3_pointer.checksum(),
+        Some(
+            PayloadChecksum::Crc32c
+                .compute("hello SQS! with love from the sqs-extended-client-rust 😊".as_bytes())
+                .as_str()
+        )
+    );
+
+    // Overwrite the payload behind the pointer
+    s3_client
+        .put_object()
+        .bucket(s3_pointer.bucket_name())
+        .key(s3_pointer.key())
+        .body("tampered".as_bytes().to_vec().into())
+        .send()
+        .await?;
+
+    let receive_msg: ReceiveMessageFluentBuilder =
+        sqs_client.receive_message().queue_url(&queue_url);
+
+    let result = sqs_extended_client.receive_message(receive_msg).await;
+
+    assert!(matches!(
+        result,
+        Err(SqsExtendedClientError::ChecksumMismatch { .. })
+    ));
+
+    let _rm = node.rm();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn send_offloaded_messages_to_fifo_queue_with_content_based_deduplication()
+-> Result<(), Box<dyn std::error::Error + 'static>> {
+    let (node, _endpoint_url, _queue_url, s3_client, sqs_client) =
+        create_localstack_with_bucket_and_queue().await?;
+
+    let fifo_queue_url: String = sqs_client
+        .create_queue()
+        .queue_name("sqs-extended-client-queue.fifo")
+        .attributes(QueueAttributeName::FifoQueue, "true")
+        .attributes(QueueAttributeName::ContentBasedDeduplication, "true")
+        .send()
+        .await?
+        .queue_url
+        .expect("Queue URL should exist");
+
+    let sqs_extended_client: SqsExtendedClient = SqsExtendedClientBuilder::new(s3_client.clone())
+        .with_s3_bucket_name("sqs-extended-client-bucket".to_string())
+        .with_message_size_threshold(3)
+        .with_payload_deduplication_ids(true)
+        .build();
+
+    // the same payload twice - each upload gets a new S3 key
+    for _ in 0..2 {
+        let msg_input: SendMessageFluentBuilder = sqs_client
+            .send_message()
+            .queue_url(&fifo_queue_url)
+            .message_group_id("group-one")
+            .message_body("hello SQS! with love from the sqs-extended-client-rust 😊");
+
+        sqs_extended_client.send_message(msg_input).await?;
+    }
+
+    let batch_input: SendMessageBatchFluentBuilder = sqs_client
+        .send_message_batch()
+        .queue_url(&fifo_queue_url)
+        .entries(
+            SendMessageBatchRequestEntry::builder()
+                .id("1")
+                .message_group_id("group-two")
+                .message_body("hello again SQS! with love from the sqs-extended-client-rust 😊")
+                .build()?,
+        )
+        .entries(
+            SendMessageBatchRequestEntry::builder()
+                .id("2")
+                .message_group_id("group-two")
+                .message_body("hello again SQS! with love from the sqs-extended-client-rust 😊")
+                .build()?,
+        );
+
+    sqs_extended_client.send_message_batch(batch_input).await?;
+
+    let receive_msg: ReceiveMessageFluentBuilder = sqs_client
+        .receive_message()
+        .queue_url(&fifo_queue_url)
+        .max_number_of_messages(10)
+        .message_system_attribute_names(MessageSystemAttributeName::MessageGroupId);
+
+    let msgs: Vec<ExtendedMessage> = sqs_extended_client.receive_message(receive_msg).await?;
+
+    // one message per payload survives deduplication
+    assert_eq!(msgs.len(), 2);
+
+    let mut group_ids: Vec<&str> = msgs
+        .iter()
+        .filter_map(|msg| msg.message().attributes.as_ref())
+        .filter_map(|attrs| attrs.get(&MessageSystemAttributeName::MessageGroupId))
+        .map(|group_id| group_id.as_str())
+        .collect();
+    group_ids.sort();
+
+    assert_eq!(group_ids, vec!["group-one", "group-two"]);
+
+    let _rm = node.rm();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn content_addressed_payloads_are_stored_once_and_reference_counted()
+-> Result<(), Box<dyn std::error::Error + 'static>> {
+    let (node, _endpoint_url, queue_url, s3_client, sqs_client) =
+        create_localstack_with_bucket_and_queue().await?;
+
+    let sqs_extended_client: SqsExtendedClient = SqsExtendedClientBuilder::new(s3_client.clone())
+        .with_s3_bucket_name("sqs-extended-client-bucket".to_string())
+        .with_message_size_threshold(3)
+        .with_content_addressed_keys(true)
+        .build();
+
+    for _ in 0..2 {
+        let msg_input: SendMessageFluentBuilder = sqs_client
+            .send_message()
+            .queue_url(&queue_url)
+            .message_body("hello SQS! with love from the sqs-extended-client-rust 😊");
+
+        sqs_extended_client.send_message(msg_input).await?;
+    }
+
+    let list_objects_output = s3_client
+        .list_objects_v2()
+        .bucket("sqs-extended-client-bucket")
+        .send()
+        .await?;
+
+    assert_eq!(list_objects_output.contents().len(), 1);
+
+    let receive_msg: ReceiveMessageFluentBuilder = sqs_client
+        .receive_message()
+        .queue_url(&queue_url)
+        .max_number_of_messages(10);
+
+    let msgs: Vec<ExtendedMessage> = sqs_extended_client.receive_message(receive_msg).await?;
+
+    assert_eq!(msgs.len(), 2);
+    assert_eq!(msgs[0].s3_pointer(), msgs[1].s3_pointer());
+
+    // the first delete leaves the payload for the second message
+    sqs_extended_client
+        .delete_extended_message(sqs_client.delete_message().queue_url(&queue_url), &msgs[0])
+        .await?;
+
+    let list_objects_output = s3_client
+        .list_objects_v2()
+        .bucket("sqs-extended-client-bucket")
+        .send()
+        .await?;
+
+    assert_eq!(list_objects_output.contents().len(), 1);
+
+    sqs_extended_client
+        .delete_extended_message(sqs_client.delete_message().queue_url(&queue_url), &msgs[1])
+        .await?;
+
+    let list_objects_output = s3_client
+        .list_objects_v2()
+        .bucket("sqs-extended-client-bucket")
+        .send()
+        .await?;
+
+    assert_eq!(list_objects_output.contents().len(), 0);
+
+    let _rm = node.rm();
+
+    Ok(())
+}
+
+#[cfg(feature = "sns")]
+#[tokio::test]
+async fn publish_large_message_through_sns_to_sqs()
+-> Result<(), Box<dyn std::error::Error + 'static>> {
+    let (node, endpoint_url, queue_url, s3_client, sqs_client) =
+        create_localstack_with_bucket_and_queue().await?;
+
+    let sns_config: aws_config::SdkConfig = aws_config::defaults(BehaviorVersion::v2025_01_17())
+        .region(Region::new("us-east-1"))
+        .credentials_provider(aws_sdk_sns::config::Credentials::new(
+            "fake", "fake", None, None, "test",
+        ))
+        .endpoint_url(&endpoint_url)
+        .load()
+        .await;
+
+    let sns_client: aws_sdk_sns::Client = aws_sdk_sns::Client::new(&sns_config);
+
+    let topic_arn: String = sns_client
+        .create_topic()
+        .name("sqs-extended-client-topic")
+        .send()
+        .await?
+        .topic_arn
+        .expect("Topic ARN should exist");
+
+    let queue_arn: String = sqs_client
+        .get_queue_attributes()
+        .queue_url(&queue_url)
+        .attribute_names(QueueAttributeName::QueueArn)
+        .send()
+        .await?
+        .attributes
+        .and_then(|attrs| attrs.get(&QueueAttributeName::QueueArn).cloned())
+        .expect("Queue ARN should exist");
+
+    sns_client
+        .subscribe()
+        .topic_arn(&topic_arn)
+        .protocol("sqs")
+        .endpoint(queue_arn)
+        .attributes("RawMessageDelivery", "true")
+        .send()
+        .await?;
+
+    let sns_extended_client: SnsExtendedClient = SqsExtendedClientBuilder::new(s3_client.clone())
+        .with_s3_bucket_name("sqs-extended-client-bucket".to_string())
+        .with_message_size_threshold(3)
+        .build_sns();
+
+    sns_extended_client
+        .publish(
+            sns_client
+                .publish()
+                .topic_arn(&topic_arn)
+                .message("hello SNS! with love from the sqs-extended-client-rust 😊"),
+        )
+        .await?;
+
+    let sqs_extended_client: SqsExtendedClient = SqsExtendedClientBuilder::new(s3_client.clone())
+        .with_s3_bucket_name("sqs-extended-client-bucket".to_string())
+        .build();
+
+    let receive_msg: ReceiveMessageFluentBuilder = sqs_client
+        .receive_message()
+        .queue_url(&queue_url)
+        .wait_time_seconds(5);
+
+    let msgs: Vec<ExtendedMessage> = sqs_extended_client.receive_message(receive_msg).await?;
+
+    assert_eq!(msgs.len(), 1);
+    assert!(msgs[0].is_offloaded());
+    assert_eq!(
+        msgs[0].payload(),
+        "hello SNS! with love from the sqs-extended-client-rust 😊"
+    );
+
+    let _rm = node.rm();
+
     Ok(())
 }
 
@@ -518,7 +911,7 @@ async fn create_localstack_with_bucket_and_queue() -> Result<
     Box<dyn std::error::Error + 'static>,
 > {
     let node: ContainerAsync<LocalStack> = localstack::LocalStack::default()
-        .with_env_var("SERVICES", "s3,sqs")
+        .with_env_var("SERVICES", "s3,sqs,sns")
         .start()
         .await?;
 
@@ -577,3 +970,66 @@ async fn create_localstack_with_bucket_and_queue() -> Result<
 
     Ok((node, endpoint_url, queue_url, s3_client, sqs_client))
 }
+
+#[tokio::test]
+async fn send_and_receive_message_with_offloaded_attributes()
+-> Result<(), Box<dyn std::error::Error + 'static>> {
+    let (node, _endpoint_url, queue_url, s3_client, sqs_client) =
+        create_localstack_with_bucket_and_queue().await?;
+
+    let sqs_extended_client: SqsExtendedClient = SqsExtendedClientBuilder::new(s3_client)
+        .with_s3_bucket_name("sqs-extended-client-bucket".to_string())
+        .with_attribute_offloading(true)
+        .build();
+
+    let large_attribute: String = "A".repeat(200 * 1024);
+
+    let msg_input: SendMessageFluentBuilder = sqs_client
+        .send_message()
+        .queue_url(&queue_url)
+        .message_body("small body")
+        .message_attributes(
+            "Large",
+            MessageAttributeValue::builder()
+                .data_type("String")
+                .string_value(&large_attribute)
+                .build()?,
+        )
+        .message_attributes(
+            "Small",
+            MessageAttributeValue::builder()
+                .data_type("String")
+                .string_value("small")
+                .build()?,
+        );
+
+    sqs_extended_client.send_message(msg_input).await?;
+
+    let receive_msg: ReceiveMessageFluentBuilder =
+        sqs_client.receive_message().queue_url(&queue_url);
+
+    let msgs: Vec<ExtendedMessage> = sqs_extended_client.receive_message(receive_msg).await?;
+
+    assert_eq!(msgs.len(), 1);
+    assert!(msgs[0].is_offloaded());
+    assert_eq!(msgs[0].payload(), "small body");
+
+    let sqs_attributes: HashMap<String, MessageAttributeValue> =
+        msgs[0].message().message_attributes.clone().unwrap();
+
+    assert!(!sqs_attributes.contains_key("Large"));
+    assert!(sqs_attributes.contains_key(ATTRIBUTES_ATTRIBUTE_NAME));
+
+    assert_eq!(
+        msgs[0].message_attributes()["Large"].string_value(),
+        Some(large_attribute.as_str())
+    );
+    assert_eq!(
+        msgs[0].message_attributes()["Small"].string_value(),
+        Some("small")
+    );
+
+    let _rm = node.rm();
+
+    Ok(())
+}